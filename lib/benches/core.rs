@@ -0,0 +1,118 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use lozenge_tilings::PeriodicLozengeTiling;
+
+const SEED: u64 = 42;
+const Q: f32 = 0.9;
+// boxes generated before measuring moves and voxels
+const PREPARE_ITERATIONS: i32 = 20000;
+
+// x_shift, y_shift, z_height
+const PERIODS: [(i32, i32, i32); 4] = [(0, 0, 0), (0, 0, 10), (1, 2, 3), (3, 3, 5)];
+const DRAW_DISTANCES: [i32; 3] = [20, 50, 100];
+
+fn create_tiling(periods: (i32, i32, i32), draw_distance: i32) -> PeriodicLozengeTiling {
+    let (x_shift, y_shift, z_height) = periods;
+    let mut lozenge_tiling = PeriodicLozengeTiling::new(
+        x_shift,
+        y_shift,
+        z_height,
+        draw_distance,
+        draw_distance,
+        draw_distance,
+    );
+    lozenge_tiling.set_seed(SEED);
+    lozenge_tiling
+}
+
+fn create_prepared_tiling(periods: (i32, i32, i32), draw_distance: i32) -> PeriodicLozengeTiling {
+    let mut lozenge_tiling = create_tiling(periods, draw_distance);
+    lozenge_tiling.generate_with_markov_chain(PREPARE_ITERATIONS, Q);
+    lozenge_tiling
+}
+
+fn periods_label(periods: (i32, i32, i32)) -> String {
+    format!("{}_{}_{}", periods.0, periods.1, periods.2)
+}
+
+fn bench_moves(c: &mut Criterion) {
+    let mut group = c.benchmark_group("moves");
+
+    for periods in PERIODS {
+        let lozenge_tiling = create_prepared_tiling(periods, 20);
+        let addable_box = lozenge_tiling.get_addable_boxes()[0];
+        let removable_box = lozenge_tiling.get_removable_boxes()[0];
+
+        group.bench_function(BenchmarkId::new("add_box", periods_label(periods)), |b| {
+            b.iter_batched_ref(
+                || lozenge_tiling.clone(),
+                |lozenge_tiling| lozenge_tiling.add_box(black_box(addable_box)),
+                BatchSize::SmallInput,
+            )
+        });
+
+        group.bench_function(
+            BenchmarkId::new("remove_box", periods_label(periods)),
+            |b| {
+                b.iter_batched_ref(
+                    || lozenge_tiling.clone(),
+                    |lozenge_tiling| lozenge_tiling.remove_box(black_box(removable_box)),
+                    BatchSize::SmallInput,
+                )
+            },
+        );
+    }
+
+    group.finish();
+}
+
+fn bench_generate_with_markov_chain(c: &mut Criterion) {
+    let mut group = c.benchmark_group("generate_with_markov_chain");
+    group.sample_size(20);
+
+    for periods in PERIODS {
+        for iterations in [1000, 10000] {
+            group.bench_with_input(
+                BenchmarkId::new(periods_label(periods), iterations),
+                &iterations,
+                |b, iterations| {
+                    b.iter_batched_ref(
+                        || create_tiling(periods, 20),
+                        |lozenge_tiling| lozenge_tiling.generate_with_markov_chain(*iterations, Q),
+                        BatchSize::SmallInput,
+                    )
+                },
+            );
+        }
+    }
+
+    group.finish();
+}
+
+fn bench_voxels(c: &mut Criterion) {
+    let mut group = c.benchmark_group("voxels");
+    group.sample_size(10);
+
+    for periods in PERIODS {
+        for draw_distance in DRAW_DISTANCES {
+            let lozenge_tiling = create_prepared_tiling(periods, draw_distance);
+            let label = format!("{}/{}", periods_label(periods), draw_distance);
+
+            group.bench_function(BenchmarkId::new("get_box_voxels", &label), |b| {
+                b.iter(|| lozenge_tiling.get_box_voxels())
+            });
+            group.bench_function(BenchmarkId::new("get_wall_voxels", &label), |b| {
+                b.iter(|| lozenge_tiling.get_wall_voxels())
+            });
+        }
+    }
+
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_moves,
+    bench_generate_with_markov_chain,
+    bench_voxels
+);
+criterion_main!(benches);
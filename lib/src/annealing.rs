@@ -0,0 +1,350 @@
+use std::{error::Error, fmt};
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+use crate::{box_move::BoxMove, vector2::Vector2, vector3::Vector3, PeriodicLozengeTiling};
+
+// Energy minimized by anneal, negate it to look for maxima
+pub trait Energy {
+    // energy of the period
+    fn get_energy(&self, tiling: &PeriodicLozengeTiling) -> f64;
+    // change by a legal move that hasn't been made yet
+    fn get_change(&self, tiling: &PeriodicLozengeTiling, box_move: &BoxMove) -> f64;
+}
+
+// volume * boxes + neighbour * pairs of boxes sharing a face
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InteractionEnergy {
+    pub volume: f64,
+    pub neighbour: f64,
+}
+
+impl Default for InteractionEnergy {
+    fn default() -> Self {
+        InteractionEnergy {
+            volume: 1.0,
+            neighbour: 0.0,
+        }
+    }
+}
+
+impl Energy for InteractionEnergy {
+    fn get_energy(&self, tiling: &PeriodicLozengeTiling) -> f64 {
+        self.volume * f64::from(tiling.get_period_box_count())
+            + self.neighbour * f64::from(tiling.get_period_contact_count())
+    }
+
+    fn get_change(&self, tiling: &PeriodicLozengeTiling, box_move: &BoxMove) -> f64 {
+        match box_move {
+            BoxMove::Add(position) => {
+                self.volume + self.neighbour * f64::from(tiling.get_box_neighbour_count(position))
+            }
+            BoxMove::Remove(position) => {
+                -self.volume - self.neighbour * f64::from(tiling.get_box_neighbour_count(position))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Cooling {
+    #[default]
+    Geometric,
+    Linear,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AnnealingSchedule {
+    pub initial_temperature: f64,
+    pub final_temperature: f64,
+    pub cooling: Cooling,
+    pub steps: i32,
+    // steps between energy log entries
+    pub log_every: i32,
+}
+
+impl Default for AnnealingSchedule {
+    fn default() -> Self {
+        AnnealingSchedule {
+            initial_temperature: 10.0,
+            final_temperature: 0.01,
+            cooling: Cooling::Geometric,
+            steps: 100_000,
+            log_every: 1000,
+        }
+    }
+}
+
+impl AnnealingSchedule {
+    pub fn get_temperature(&self, step: i32) -> f64 {
+        let progress = match self.steps {
+            0 | 1 => 1.0,
+            steps => f64::from(step) / f64::from(steps - 1),
+        };
+        let AnnealingSchedule {
+            initial_temperature,
+            final_temperature,
+            ..
+        } = *self;
+
+        match self.cooling {
+            Cooling::Geometric => {
+                initial_temperature * (final_temperature / initial_temperature).powf(progress)
+            }
+            Cooling::Linear => {
+                initial_temperature + (final_temperature - initial_temperature) * progress
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct EnergySample {
+    pub step: i32,
+    pub temperature: f64,
+    pub energy: f64,
+}
+
+pub fn energy_log_to_csv(log: &[EnergySample]) -> String {
+    let mut csv = String::from("step,temperature,energy\n");
+    for EnergySample {
+        step,
+        temperature,
+        energy,
+    } in log.iter()
+    {
+        csv.push_str(&format!("{},{},{}\n", step, temperature, energy));
+    }
+    csv
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AnnealingError {
+    // temperatures must be positive
+    InvalidTemperature,
+    // single box moves would break the symmetry
+    Symmetry,
+}
+
+impl fmt::Display for AnnealingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnnealingError::InvalidTemperature => write!(f, "temperatures must be positive"),
+            AnnealingError::Symmetry => write!(f, "annealing can't keep a symmetry"),
+        }
+    }
+}
+
+impl Error for AnnealingError {}
+
+impl PeriodicLozengeTiling {
+    pub fn get_box_neighbour_count(&self, position: &Vector3) -> i32 {
+        let Vector3(x, y, z) = *position;
+        [
+            Vector3(x - 1, y, z),
+            Vector3(x + 1, y, z),
+            Vector3(x, y - 1, z),
+            Vector3(x, y + 1, z),
+            Vector3(x, y, z - 1),
+            Vector3(x, y, z + 1),
+        ]
+        .iter()
+        .filter(|neighbour| self.is_box(neighbour))
+        .count() as i32
+    }
+
+    // Pairs of boxes sharing a face, once per period
+    pub fn get_period_contact_count(&self) -> i32 {
+        let mut contacts = 0;
+        for (column, height) in self.data.iter() {
+            let Vector2(x, y) = *column;
+            let top = self.get_height(column);
+            for z in top - height..=top {
+                contacts += [
+                    Vector3(x + 1, y, z),
+                    Vector3(x, y + 1, z),
+                    Vector3(x, y, z + 1),
+                ]
+                .iter()
+                .filter(|neighbour| self.is_box(neighbour))
+                .count() as i32;
+            }
+        }
+        contacts
+    }
+
+    // Metropolis moves at the temperature of the schedule, adding or removing with equal
+    // probability. Meant for finding low energy configurations, the proposals aren't
+    // balanced for sampling. The energy is logged every log_every steps and at the end.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub fn anneal(
+        &mut self,
+        energy: &dyn Energy,
+        schedule: &AnnealingSchedule,
+    ) -> Result<Vec<EnergySample>, AnnealingError> {
+        if schedule.initial_temperature <= 0.0 || schedule.final_temperature <= 0.0 {
+            return Err(AnnealingError::InvalidTemperature);
+        }
+        if self.symmetry.is_some() {
+            return Err(AnnealingError::Symmetry);
+        }
+
+        let mut current_energy = energy.get_energy(self);
+        let mut log = vec![EnergySample {
+            step: 0,
+            temperature: schedule.get_temperature(0),
+            energy: current_energy,
+        }];
+
+        for step in 0..schedule.steps {
+            let temperature = schedule.get_temperature(step);
+            let box_move = match self.rng.gen::<bool>() {
+                true => self
+                    .get_random_addable_box()
+                    .filter(|position| self.can_add_box(position))
+                    .map(BoxMove::Add),
+                false => self
+                    .get_random_removable_box()
+                    .filter(|position| self.can_remove_box(position))
+                    .map(BoxMove::Remove),
+            };
+
+            if let Some(box_move) = box_move {
+                let change = energy.get_change(self, &box_move);
+                if change <= 0.0 || self.rng.gen::<f64>() < (-change / temperature).exp() {
+                    self.apply_move(box_move);
+                    current_energy += change;
+                }
+            }
+
+            let done = step + 1;
+            if (schedule.log_every > 0 && done % schedule.log_every == 0) || done == schedule.steps
+            {
+                log.push(EnergySample {
+                    step: done,
+                    temperature,
+                    energy: current_energy,
+                });
+            }
+        }
+
+        Ok(log)
+    }
+}
+
+#[wasm_bindgen]
+impl PeriodicLozengeTiling {
+    // JSON of an InteractionEnergy and an AnnealingSchedule, returns the energy log as JSON
+    #[wasm_bindgen(js_name = anneal)]
+    pub fn anneal_js(&mut self, energy: &str, schedule: &str) -> Result<String, JsValue> {
+        let energy: InteractionEnergy =
+            serde_json::from_str(energy).map_err(|error| JsValue::from(error.to_string()))?;
+        let schedule: AnnealingSchedule =
+            serde_json::from_str(schedule).map_err(|error| JsValue::from(error.to_string()))?;
+        let log = self
+            .anneal(&energy, &schedule)
+            .map_err(|error| JsValue::from(error.to_string()))?;
+        Ok(serde_json::to_string(&log).unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AnnealingError, AnnealingSchedule, Cooling, Energy, InteractionEnergy};
+    use crate::{box_move::BoxMove, PeriodicLozengeTiling, SymmetryClass};
+
+    #[test]
+    fn tracks_energy_of_moves() {
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(1, 2, 3, 5, 5, 5);
+        lozenge_tiling.set_seed(6);
+        lozenge_tiling.generate_with_markov_chain(500, 0.9);
+        let energy = InteractionEnergy {
+            volume: 0.5,
+            neighbour: -1.0,
+        };
+
+        let schedule = AnnealingSchedule {
+            initial_temperature: 2.0,
+            final_temperature: 0.5,
+            steps: 300,
+            log_every: 100,
+            ..Default::default()
+        };
+        let log = lozenge_tiling.anneal(&energy, &schedule).unwrap();
+        assert_eq!(
+            log.iter().map(|sample| sample.step).collect::<Vec<_>>(),
+            vec![0, 100, 200, 300]
+        );
+        let final_energy = log.last().unwrap().energy;
+        assert!((final_energy - energy.get_energy(&lozenge_tiling)).abs() < 1e-9);
+
+        for position in lozenge_tiling.get_addable_boxes() {
+            let before = energy.get_energy(&lozenge_tiling);
+            let change = energy.get_change(&lozenge_tiling, &BoxMove::Add(position));
+            lozenge_tiling.add_box(position);
+            assert!((energy.get_energy(&lozenge_tiling) - before - change).abs() < 1e-9);
+            lozenge_tiling.remove_box(position);
+        }
+    }
+
+    #[test]
+    fn cooling_finds_the_extremes_of_the_volume() {
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(0, 0, 3, 6, 6, 6);
+        lozenge_tiling.set_seed(2);
+        lozenge_tiling.generate_with_markov_chain(2000, 1.0);
+        let schedule = AnnealingSchedule {
+            initial_temperature: 1.0,
+            final_temperature: 0.01,
+            steps: 20_000,
+            ..Default::default()
+        };
+
+        lozenge_tiling
+            .anneal(&InteractionEnergy::default(), &schedule)
+            .unwrap();
+        assert_eq!(lozenge_tiling.get_period_box_count(), 0);
+
+        let linear = AnnealingSchedule {
+            cooling: Cooling::Linear,
+            ..schedule
+        };
+        assert!((linear.get_temperature(10_000) - 0.505).abs() < 1e-3);
+        // the volume grows without a bound in x and y, so only look for a rise
+        let log = lozenge_tiling
+            .anneal(
+                &InteractionEnergy {
+                    volume: -1.0,
+                    neighbour: 0.0,
+                },
+                &linear,
+            )
+            .unwrap();
+        assert!(log.last().unwrap().energy < -50.0);
+    }
+
+    #[test]
+    fn rejects_bad_schedules_and_symmetries() {
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(0, 0, 3, 4, 4, 4);
+        let schedule = AnnealingSchedule {
+            final_temperature: 0.0,
+            ..Default::default()
+        };
+        assert_eq!(
+            lozenge_tiling.anneal(&InteractionEnergy::default(), &schedule),
+            Err(AnnealingError::InvalidTemperature)
+        );
+
+        lozenge_tiling
+            .set_symmetry(Some(SymmetryClass::Transpose))
+            .unwrap();
+        assert_eq!(
+            lozenge_tiling.anneal(&InteractionEnergy::default(), &AnnealingSchedule::default()),
+            Err(AnnealingError::Symmetry)
+        );
+    }
+}
@@ -0,0 +1,351 @@
+use std::{error::Error, fmt};
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+use crate::{vector3::Vector3, LozengeTilingPeriods, PeriodicLozengeTiling};
+
+// Swaps the two coordinates mirrored by a cut
+type Mirror = fn(&Vector3) -> Vector3;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Boundary {
+    // the walls meet along the side, as without boundary conditions
+    #[default]
+    Fixed,
+    // the domain is cut along the side, boxes on the cut need no support across it
+    Cut,
+    // a cut weighted as the configuration mirrored across it
+    MirrorCut,
+}
+
+// One boundary per side of the corner, named by the axis of the edge where two walls
+// meet, which is where the tiling is seen to have a side. A side that isn't fixed cuts
+// the domain along the diagonal plane through its edge: x keeps z <= y, y keeps z <= x
+// and z keeps y <= x. Mirroring across the cut swaps the other two coordinates, so a
+// mirror cut z side samples symmetric plane partitions and a plain cut one the tilings
+// of a halved hexagon with free boundary along the cut. Heights are never free along a
+// side of the corner itself, the walls there stay fixed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BoundaryConditions {
+    pub x: Boundary,
+    pub y: Boundary,
+    pub z: Boundary,
+}
+
+impl BoundaryConditions {
+    fn sides(&self) -> [(Boundary, Mirror); 3] {
+        [
+            (self.x, |Vector3(x, y, z)| Vector3(*x, *z, *y)),
+            (self.y, |Vector3(x, y, z)| Vector3(*z, *y, *x)),
+            (self.z, |Vector3(x, y, z)| Vector3(*y, *x, *z)),
+        ]
+    }
+
+    pub fn is_fixed(&self) -> bool {
+        self.sides()
+            .iter()
+            .all(|(boundary, _)| *boundary == Boundary::Fixed)
+    }
+
+    fn has_mirror_cut_side(&self) -> bool {
+        self.sides()
+            .iter()
+            .any(|(boundary, _)| *boundary == Boundary::MirrorCut)
+    }
+
+    pub fn is_outside(&self, vector: &Vector3) -> bool {
+        let Vector3(x, y, z) = *vector;
+        (self.x != Boundary::Fixed && z > y)
+            || (self.y != Boundary::Fixed && z > x)
+            || (self.z != Boundary::Fixed && y > x)
+    }
+
+    // Number of boxes a box stands for once the configuration is mirrored across all
+    // mirror cut sides
+    pub fn get_multiplicity(&self, vector: &Vector3) -> i32 {
+        let mut orbit = vec![*vector];
+        let mut index = 0;
+        while index < orbit.len() {
+            for (boundary, mirror) in self.sides() {
+                let image = mirror(&orbit[index]);
+                if boundary == Boundary::MirrorCut && !orbit.contains(&image) {
+                    orbit.push(image);
+                }
+            }
+            index += 1;
+        }
+        orbit.len() as i32
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BoundaryError {
+    // boundaries other than fixed need x_shift = y_shift = 0
+    NonZeroShift,
+    // a symmetry already restricts the moves
+    Symmetry,
+}
+
+impl fmt::Display for BoundaryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BoundaryError::NonZeroShift => {
+                write!(f, "cut sides need zero x and y shifts")
+            }
+            BoundaryError::Symmetry => {
+                write!(f, "boundary conditions can't be combined with a symmetry")
+            }
+        }
+    }
+}
+
+impl Error for BoundaryError {}
+
+impl PeriodicLozengeTiling {
+    pub fn get_boundary_conditions(&self) -> &BoundaryConditions {
+        &self.boundary_conditions
+    }
+
+    // The tiling is emptied, boxes outside a new cut would have no meaning
+    pub fn set_boundary_conditions(
+        &mut self,
+        boundary_conditions: BoundaryConditions,
+    ) -> Result<(), BoundaryError> {
+        let LozengeTilingPeriods {
+            x_shift, y_shift, ..
+        } = self.periods;
+
+        if !boundary_conditions.is_fixed() {
+            if x_shift != 0 || y_shift != 0 {
+                return Err(BoundaryError::NonZeroShift);
+            }
+            if self.symmetry.is_some() {
+                return Err(BoundaryError::Symmetry);
+            }
+        }
+
+        self.boundary_conditions = boundary_conditions;
+        self.reset();
+        Ok(())
+    }
+
+    pub(crate) fn is_outside_boundary(&self, vector: &Vector3) -> bool {
+        self.boundary_conditions.is_outside(vector)
+    }
+
+    // Like generate_with_markov_chain, with the acceptance making up the weight q^m of
+    // a box standing for m mirrored boxes
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub fn generate_with_mirror_cut_markov_chain(&mut self, iterations: i32, q: f32) {
+        for _ in 0..iterations {
+            let rn1 = self.rng.gen::<f32>();
+            let rn2 = self.rng.gen::<f32>();
+
+            let num1 = -(1.0 - rn1).ln() / self.addable_boxes_count() as f32 / q;
+            let num2 = -(1.0 - rn2).ln() / self.removable_boxes_count() as f32;
+
+            let add = num1 < num2;
+            let position = match add {
+                true => self.get_random_addable_box(),
+                false => self.get_random_removable_box(),
+            };
+            let Some(position) = position else {
+                continue;
+            };
+
+            let exponent = self.boundary_conditions.get_multiplicity(&position) - 1;
+            let acceptance = match add {
+                true => q.powi(exponent),
+                false => q.powi(-exponent),
+            };
+            if acceptance < 1.0 && self.rng.gen::<f32>() >= acceptance {
+                continue;
+            }
+
+            match add {
+                true => self.add_box(position),
+                false => self.remove_box(position),
+            }
+        }
+    }
+
+    pub(crate) fn has_mirror_cut_boundary(&self) -> bool {
+        self.boundary_conditions.has_mirror_cut_side()
+    }
+}
+
+#[wasm_bindgen]
+impl PeriodicLozengeTiling {
+    // JSON like {"x": "fixed", "y": "cut", "z": "mirror-cut"}, missing sides are fixed
+    #[wasm_bindgen(js_name = setBoundaryConditions)]
+    pub fn set_boundary_conditions_js(&mut self, boundary_conditions: &str) -> Result<(), JsValue> {
+        let boundary_conditions: BoundaryConditions = serde_json::from_str(boundary_conditions)
+            .map_err(|error| JsValue::from(error.to_string()))?;
+        self.set_boundary_conditions(boundary_conditions)
+            .map_err(|error| JsValue::from(error.to_string()))
+    }
+
+    #[wasm_bindgen(js_name = getBoundaryConditions)]
+    pub fn get_boundary_conditions_js(&self) -> String {
+        serde_json::to_string(&self.boundary_conditions).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rustc_hash::FxHashMap;
+
+    use super::{Boundary, BoundaryConditions, BoundaryError};
+    use crate::{heights::HeightsError, vector3::Vector3, PeriodicLozengeTiling, SymmetryClass};
+
+    #[test]
+    fn cut_sides_keep_boxes_inside_the_cut() {
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(0, 0, 6, 8, 8, 8);
+        lozenge_tiling.set_seed(4);
+        lozenge_tiling
+            .set_boundary_conditions(BoundaryConditions {
+                x: Boundary::Cut,
+                z: Boundary::Cut,
+                ..Default::default()
+            })
+            .unwrap();
+        lozenge_tiling.generate_with_markov_chain(3000, 0.95);
+
+        let boxes = lozenge_tiling.get_box_voxels();
+        assert!(boxes.iter().any(|Vector3(x, y, _)| x == y && *x > 0));
+        assert!(boxes.iter().all(|Vector3(x, y, z)| y <= x && z <= y));
+        assert_eq!(
+            lozenge_tiling.set_heights(&[[0, 1, 0]]),
+            Err(HeightsError::OutsideBoundary { x: 0, y: 1 })
+        );
+    }
+
+    #[test]
+    fn mirror_cut_side_weighs_mirrored_boxes() {
+        let mirror_cut = BoundaryConditions {
+            z: Boundary::MirrorCut,
+            ..Default::default()
+        };
+        assert_eq!(mirror_cut.get_multiplicity(&Vector3(2, 2, 5)), 1);
+        assert_eq!(mirror_cut.get_multiplicity(&Vector3(3, 1, 0)), 2);
+        let all_mirror_cut = BoundaryConditions {
+            x: Boundary::MirrorCut,
+            y: Boundary::MirrorCut,
+            z: Boundary::MirrorCut,
+        };
+        assert_eq!(all_mirror_cut.get_multiplicity(&Vector3(3, 2, 1)), 6);
+        assert_eq!(all_mirror_cut.get_multiplicity(&Vector3(3, 3, 1)), 3);
+    }
+
+    #[test]
+    fn mirror_cut_chain_matches_the_weights_of_small_configurations() {
+        let (q, max_weight, samples) = (0.6, 8, 20_000);
+        let boundary_conditions = BoundaryConditions {
+            z: Boundary::MirrorCut,
+            ..Default::default()
+        };
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(0, 0, 2, 8, 8, 8);
+        lozenge_tiling.set_seed(9);
+        lozenge_tiling
+            .set_boundary_conditions(boundary_conditions)
+            .unwrap();
+        let weigh = |heights: &[[i32; 3]]| {
+            heights
+                .iter()
+                .flat_map(|[x, y, height]| (0..=*height).map(move |z| Vector3(*x, *y, z)))
+                .map(|vector| boundary_conditions.get_multiplicity(&vector))
+                .sum::<i32>()
+        };
+        let key = |heights: &[[i32; 3]]| {
+            let mut heights = heights
+                .iter()
+                .filter(|[_, _, height]| *height >= 0)
+                .copied()
+                .collect::<Vec<_>>();
+            heights.sort_unstable();
+            heights
+        };
+
+        // every legal configuration up to the cutoff, columns further out weigh more.
+        // Per step the chain stays in a state in proportion to its rate of leaving it,
+        // A q + R with A addable and R removable boxes, times the weight q^(sum of
+        // multiplicities) of the mirrored configuration
+        let columns = (0..4)
+            .flat_map(|x| (0..=x).map(move |y| (x, y)))
+            .collect::<Vec<_>>();
+        let mut weights = FxHashMap::default();
+        for index in 0..3_i32.pow(columns.len() as u32) {
+            let heights = columns
+                .iter()
+                .enumerate()
+                .map(|(column, (x, y))| [*x, *y, index / 3_i32.pow(column as u32) % 3 - 1])
+                .collect::<Vec<_>>();
+            let weight = weigh(&heights);
+            if weight <= max_weight && lozenge_tiling.set_heights(&heights).is_ok() {
+                let rate = lozenge_tiling.addable_boxes_count() as f64 * f64::from(q)
+                    + lozenge_tiling.removable_boxes_count() as f64;
+                weights.insert(key(&heights), f64::from(q).powi(weight) * rate);
+            }
+        }
+        let total_weight = weights.values().sum::<f64>();
+        weights
+            .values_mut()
+            .for_each(|weight| *weight /= total_weight);
+
+        lozenge_tiling.reset();
+        lozenge_tiling.generate_with_markov_chain(2000, q);
+        let mut counts = FxHashMap::default();
+        for _ in 0..samples {
+            lozenge_tiling.generate_with_markov_chain(50, q);
+            let heights = lozenge_tiling.to_state().heights;
+            if weigh(&heights) <= max_weight {
+                let heights = key(&heights);
+                assert!(weights.contains_key(&heights));
+                *counts.entry(heights).or_insert(0) += 1;
+            }
+        }
+
+        // chi-square of the samples under the cutoff against the stationary weights
+        let kept = counts.values().sum::<i32>() as f64;
+        let chi_square = weights
+            .iter()
+            .map(|(heights, weight)| {
+                let expected = kept * weight;
+                let observed = counts.get(heights).copied().unwrap_or(0) as f64;
+                (observed - expected).powi(2) / expected
+            })
+            .sum::<f64>();
+        let degrees = (weights.len() - 1) as f64;
+        assert!(weights.len() > 10);
+        assert!(chi_square < degrees + 4.0 * (2.0 * degrees).sqrt());
+    }
+
+    #[test]
+    fn rejects_boundaries_with_shifts_or_symmetry() {
+        let cut = BoundaryConditions {
+            y: Boundary::Cut,
+            ..Default::default()
+        };
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(1, 2, 3, 4, 4, 4);
+        assert_eq!(
+            lozenge_tiling.set_boundary_conditions(cut),
+            Err(BoundaryError::NonZeroShift)
+        );
+        lozenge_tiling
+            .set_boundary_conditions(BoundaryConditions::default())
+            .unwrap();
+
+        lozenge_tiling.set_periods(0, 0, 3);
+        lozenge_tiling
+            .set_symmetry(Some(SymmetryClass::Cyclic))
+            .unwrap();
+        assert_eq!(
+            lozenge_tiling.set_boundary_conditions(cut),
+            Err(BoundaryError::Symmetry)
+        );
+    }
+}
@@ -9,7 +9,7 @@ const FLOOR_HEIGHT: i32 = -1;
 
 type BoxHashMap = FxHashMap<Vector2, i32>;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct BoxMap {
     data: BoxHashMap,
 }
@@ -42,6 +42,18 @@ impl BoxMap {
         }
     }
 
+    pub fn set(&mut self, position: &Vector2, height: i32) {
+        if height == FLOOR_HEIGHT {
+            self.data.remove(position);
+        } else {
+            self.data.insert(*position, height);
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Vector2, &i32)> {
+        self.data.iter()
+    }
+
     pub fn box_count(&self) -> i32 {
         self.data.values().map(|v| v + 1).sum()
     }
@@ -90,6 +102,15 @@ mod tests {
         assert_eq!(map.box_count(), 1);
     }
 
+    #[test]
+    fn setting_floor_height_removes_position() {
+        let mut map = BoxMap::new();
+        map.set(&Vector2(0, 0), 2);
+        assert_eq!(map.box_count(), 3);
+        map.set(&Vector2(0, 0), -1);
+        assert_eq!(map.iter().count(), 0);
+    }
+
     #[test]
     fn decremented_boxes_to_height_mins_1_are_counted_as_a_box() {
         let mut map = BoxMap::new();
@@ -0,0 +1,8 @@
+use crate::vector3::Vector3;
+
+// A single elementary step of a chain, position is normalized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BoxMove {
+    Add(Vector3),
+    Remove(Vector3),
+}
@@ -0,0 +1,190 @@
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use rand::Rng;
+use rustc_hash::FxHashSet;
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    box_move::BoxMove, vector2::Vector2, vector3::Vector3, LozengeTilingPeriods,
+    PeriodicLozengeTiling,
+};
+
+// Column picked for an update together with the random numbers deciding it.
+// Numbers are drawn up front so the result does not depend on thread count.
+struct CheckerboardSite {
+    column: Vector2,
+    direction: f32,
+    acceptance: f32,
+}
+
+impl PeriodicLozengeTiling {
+    // Whether a column can change depends only on its 4 neighbours (x +- 1, y +- 1),
+    // so columns colored by (x + y) mod colors never interact as long as the
+    // coloring survives the period shift, i.e. colors divides x_shift + y_shift.
+    // None means no such coloring exists (shift sum 1) and sites are updated one by one.
+    fn get_checkerboard_colors(&self) -> Option<i32> {
+        let LozengeTilingPeriods {
+            x_shift, y_shift, ..
+        } = self.periods;
+
+        let shift_sum = (x_shift + y_shift).abs();
+        if shift_sum == 0 {
+            return Some(2);
+        }
+
+        (2..=shift_sum).find(|colors| shift_sum % colors == 0)
+    }
+
+    // Only columns with an addable or removable box can change.
+    fn get_checkerboard_columns(&self) -> FxHashSet<Vector2> {
+        self.addable_boxes
+            .iter()
+            .chain(self.removable_boxes.iter())
+            .map(|Vector3(x, y, _)| Vector2(*x, *y))
+            .collect()
+    }
+
+    fn get_checkerboard_sites(&mut self, color: i32, colors: i32) -> Vec<CheckerboardSite> {
+        let columns = self.get_checkerboard_columns();
+        columns
+            .into_iter()
+            .filter(|Vector2(x, y)| (x + y).rem_euclid(colors) == color)
+            .map(|column| CheckerboardSite {
+                column,
+                direction: self.rng.gen::<f32>(),
+                acceptance: self.rng.gen::<f32>(),
+            })
+            .collect()
+    }
+
+    // Metropolis update of a single column, propose +1 or -1 with equal probability
+    // and accept with min(1, q^(volume change)).
+    fn get_checkerboard_move(&self, site: &CheckerboardSite, q: f32) -> Option<BoxMove> {
+        let Vector2(x, y) = site.column;
+        let height = self.get_height(&site.column);
+
+        if site.direction < 0.5 {
+            let vector = Vector3(x, y, height + 1);
+            (site.acceptance < q && self.can_add_box(&vector)).then_some(BoxMove::Add(vector))
+        } else {
+            let vector = Vector3(x, y, height);
+            (site.acceptance * q < 1.0 && self.can_remove_box(&vector))
+                .then_some(BoxMove::Remove(vector))
+        }
+    }
+
+    #[cfg(feature = "parallel")]
+    fn get_checkerboard_moves(&self, sites: &[CheckerboardSite], q: f32) -> Vec<BoxMove> {
+        sites
+            .par_iter()
+            .filter_map(|site| self.get_checkerboard_move(site, q))
+            .collect()
+    }
+
+    #[cfg(not(feature = "parallel"))]
+    fn get_checkerboard_moves(&self, sites: &[CheckerboardSite], q: f32) -> Vec<BoxMove> {
+        sites
+            .iter()
+            .filter_map(|site| self.get_checkerboard_move(site, q))
+            .collect()
+    }
+
+    fn run_checkerboard_sweep_sequentially(&mut self, q: f32) {
+        let sites = self.get_checkerboard_sites(0, 1);
+        for site in sites.iter() {
+            if let Some(box_move) = self.get_checkerboard_move(site, q) {
+                self.apply_move(box_move);
+            }
+        }
+    }
+
+    // One sweep visits every column that can change once. Moves of one color are
+    // decided in parallel (with the "parallel" feature) and then applied. Symmetric
+    // tilings and mirror cut sides need their own weights, the symmetric or mirror cut
+    // chain runs instead with sweeps taken as its iterations.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub fn generate_with_parallel_markov_chain(&mut self, sweeps: i32, q: f32) {
+        if self.symmetry.is_some() {
+            return self.generate_with_symmetric_markov_chain(sweeps, q);
+        }
+        if self.has_mirror_cut_boundary() {
+            return self.generate_with_mirror_cut_markov_chain(sweeps, q);
+        }
+        let colors = match self.get_checkerboard_colors() {
+            Some(colors) => colors,
+            None => {
+                for _ in 0..sweeps {
+                    self.run_checkerboard_sweep_sequentially(q);
+                }
+                return;
+            }
+        };
+
+        for _ in 0..sweeps {
+            for color in 0..colors {
+                let sites = self.get_checkerboard_sites(color, colors);
+                let moves = self.get_checkerboard_moves(&sites, q);
+                for box_move in moves {
+                    self.apply_move(box_move);
+                }
+            }
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl PeriodicLozengeTiling {
+    #[wasm_bindgen(js_name = generateWithParallelMarkovChain)]
+    pub fn generate_with_parallel_markov_chain_js(&mut self, sweeps: i32, q: f32) {
+        time!("generate_with_parallel_markov_chain_js", {
+            self.generate_with_parallel_markov_chain(sweeps, q)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{vector3::Vector3, PeriodicLozengeTiling};
+
+    #[test]
+    fn checkerboard_colors_divide_period_shift() {
+        assert_eq!(
+            PeriodicLozengeTiling::new(1, 2, 3, 1, 1, 1).get_checkerboard_colors(),
+            Some(3)
+        );
+        assert_eq!(
+            PeriodicLozengeTiling::new(2, 2, 3, 1, 1, 1).get_checkerboard_colors(),
+            Some(2)
+        );
+        assert_eq!(
+            PeriodicLozengeTiling::new(0, 0, 3, 1, 1, 1).get_checkerboard_colors(),
+            Some(2)
+        );
+        assert_eq!(
+            PeriodicLozengeTiling::new(0, 1, 3, 1, 1, 1).get_checkerboard_colors(),
+            None
+        );
+    }
+
+    #[test]
+    fn can_generate_with_parallel_markov_chain() {
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(1, 2, 3, 10, 10, 10);
+        lozenge_tiling.generate_with_parallel_markov_chain(50, 0.9);
+        assert!(lozenge_tiling.get_period_box_count() > 0);
+    }
+
+    #[test]
+    fn parallel_markov_chain_keeps_box_sets_consistent() {
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(1, 2, 3, 10, 10, 10);
+        lozenge_tiling.generate_with_parallel_markov_chain(50, 0.9);
+
+        for vector in lozenge_tiling.addable_boxes.iter() {
+            assert!(lozenge_tiling.can_add_box(vector));
+        }
+        for vector in lozenge_tiling.removable_boxes.iter() {
+            assert!(lozenge_tiling.can_remove_box(vector));
+        }
+        assert!(!lozenge_tiling.can_add_box(&Vector3(-1, 0, 0)));
+    }
+}
@@ -0,0 +1,665 @@
+use std::{error::Error, fmt};
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+use crate::{heights::HeightsError, vector2::Vector2, PeriodicLozengeTiling};
+
+// Geometric variables with a parameter below this are taken as 0, their sum is under the
+// resolution of the uniform f64 draws
+const NEGLIGIBLE: f64 = 1e-17;
+
+// Largest mean number of boxes per period the exact sampler takes on, past it the loops
+// run for minutes and volumes near i32::MAX
+const MAX_EXPECTED_VOLUME: f64 = 1e6;
+
+// A tiling with one zero shift seen along that axis, from where the wall is vertical and
+// the period keeps the heights. With the zero shift axis a and the other one b, entry
+// (b, z) of the cylindric partition is the number of boxes in the row of the a axis at
+// (b, z), so entries decrease along b and z and (b + shift, z - z_height) has the same
+// entry. Rows are z = 0..z_height, each from b = 0 until the first empty row.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CylindricPartition {
+    // one letter per diagonal slice b - z of a period, '+' where the next slice
+    // grows by a horizontal strip and '-' where it shrinks by one
+    pub profile: String,
+    pub shift: i32,
+    pub rows: Vec<Vec<i32>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CylindricError {
+    // periods without exactly one zero shift and a positive z period
+    NotCylindric,
+    // rows or shift not matching the periods of the tiling
+    WrongShape,
+    // rows that don't decrease along b or z
+    InvalidRows,
+    // q outside (0, 1), the volume isn't bounded
+    InvalidQ,
+    // q so close to 1 that the mean volume is above MAX_EXPECTED_VOLUME
+    TooLarge,
+    Heights(HeightsError),
+}
+
+impl fmt::Display for CylindricError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CylindricError::NotCylindric => {
+                write!(f, "periods need one zero shift and a positive z period")
+            }
+            CylindricError::WrongShape => {
+                write!(f, "shift and rows don't match the periods of the tiling")
+            }
+            CylindricError::InvalidRows => write!(f, "rows aren't a cylindric partition"),
+            CylindricError::InvalidQ => write!(f, "q must be between 0 and 1"),
+            CylindricError::TooLarge => write!(
+                f,
+                "q is too close to 1, the mean volume is above {}",
+                MAX_EXPECTED_VOLUME
+            ),
+            CylindricError::Heights(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for CylindricError {}
+
+impl From<HeightsError> for CylindricError {
+    fn from(error: HeightsError) -> Self {
+        CylindricError::Heights(error)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Cylinder {
+    shift: i32,
+    z_height: i32,
+    // the zero shift is along y
+    transposed: bool,
+}
+
+impl Cylinder {
+    fn new(x_shift: i32, y_shift: i32, z_height: i32) -> Result<Cylinder, CylindricError> {
+        match (x_shift, y_shift) {
+            (0, shift) if shift > 0 && z_height > 0 => Ok(Cylinder {
+                shift,
+                z_height,
+                transposed: false,
+            }),
+            (shift, 0) if shift > 0 && z_height > 0 => Ok(Cylinder {
+                shift,
+                z_height,
+                transposed: true,
+            }),
+            _ => Err(CylindricError::NotCylindric),
+        }
+    }
+
+    fn column(&self, a: i32, b: i32) -> Vector2 {
+        match self.transposed {
+            true => Vector2(b, a),
+            false => Vector2(a, b),
+        }
+    }
+
+    fn length(&self) -> usize {
+        (self.shift + self.z_height) as usize
+    }
+
+    // lowest z of the diagonal b - z = slice outside the wall
+    fn start(&self, slice: i32) -> i32 {
+        let floor = |b: i32| -b.div_euclid(self.shift) * self.z_height;
+        let mut z = floor(slice);
+        while z < floor(z + slice) {
+            z += 1;
+        }
+        while z > floor(z - 1 + slice) {
+            z -= 1;
+        }
+        z
+    }
+
+    // true where the next slice grows
+    fn profile(&self) -> Vec<bool> {
+        (0..self.length() as i32)
+            .map(|slice| self.start(slice + 1) < self.start(slice))
+            .collect()
+    }
+}
+
+impl CylindricPartition {
+    fn cylinder(&self) -> Cylinder {
+        Cylinder {
+            shift: self.shift,
+            z_height: self.rows.len() as i32,
+            transposed: false,
+        }
+    }
+
+    // entry (b, z) with z >= 0 and b inside the region
+    fn get(&self, b: i32, z: i32) -> i32 {
+        let z_height = self.rows.len() as i32;
+        let b = b + z.div_euclid(z_height) * self.shift;
+        let row = &self.rows[z.rem_euclid(z_height) as usize];
+        row.get(b as usize).copied().unwrap_or(0)
+    }
+
+    fn from_slices(cylinder: Cylinder, slices: &[Vec<i32>]) -> CylindricPartition {
+        let rows = (0..cylinder.z_height)
+            .map(|z| {
+                let mut row = Vec::new();
+                loop {
+                    let slice = row.len() as i32 - z;
+                    let index = (z - cylinder.start(slice)) as usize;
+                    let entry = slices[slice.rem_euclid(slices.len() as i32) as usize]
+                        .get(index)
+                        .copied()
+                        .unwrap_or(0);
+                    if entry == 0 {
+                        break row;
+                    }
+                    row.push(entry);
+                }
+            })
+            .collect();
+
+        CylindricPartition {
+            profile: profile_to_string(&cylinder.profile()),
+            shift: cylinder.shift,
+            rows,
+        }
+    }
+
+    // The partitions along the diagonals b - z = 0..profile length, consecutive ones
+    // interlace as the profile says
+    pub fn get_slices(&self) -> Vec<Vec<i32>> {
+        let cylinder = self.cylinder();
+        (0..cylinder.length() as i32)
+            .map(|slice| {
+                let start = cylinder.start(slice);
+                (0..)
+                    .map(|index| self.get(start + index + slice, start + index))
+                    .take_while(|entry| *entry > 0)
+                    .collect()
+            })
+            .collect()
+    }
+
+    pub fn get_volume(&self) -> i32 {
+        self.rows.iter().flatten().sum()
+    }
+
+    fn validate(&self) -> Result<(), CylindricError> {
+        let decreasing = |row: &Vec<i32>| {
+            row.iter().all(|entry| *entry > 0) && row.windows(2).all(|pair| pair[0] >= pair[1])
+        };
+        if !self.rows.iter().all(decreasing) {
+            return Err(CylindricError::InvalidRows);
+        }
+
+        // each row below the one above it, the last one below the first one shifted
+        let z_height = self.rows.len() as i32;
+        for z in 0..z_height {
+            for b in 0..self.rows[z as usize].len() as i32 + 1 {
+                if self.get(b, z + 1) > self.get(b, z) {
+                    return Err(CylindricError::InvalidRows);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn profile_to_string(profile: &[bool]) -> String {
+    profile
+        .iter()
+        .map(|grows| if *grows { '+' } else { '-' })
+        .collect()
+}
+
+// P(g) = (1 - r) r^g
+fn sample_geometric<R: Rng>(rng: &mut R, r: f64) -> i32 {
+    let uniform = 1.0 - rng.gen::<f64>();
+    (uniform.ln() / r.ln()).floor() as i32
+}
+
+// RSK local rule of a growth diagram, the partition containing mu and nu by horizontal
+// strips from the one inside both of them and the number of cells added on top
+fn grow(inner: &[i32], mu: &[i32], nu: &[i32], added: i32) -> Vec<i32> {
+    let part = |partition: &[i32], index: usize| partition.get(index).copied().unwrap_or(0);
+    let mut lambda = Vec::new();
+    for index in 0..mu.len().max(nu.len()) + 1 {
+        let entry = match index {
+            0 => part(mu, 0).max(part(nu, 0)) + added,
+            _ => {
+                part(mu, index).max(part(nu, index)) + part(mu, index - 1).min(part(nu, index - 1))
+                    - part(inner, index - 1)
+            }
+        };
+        if entry == 0 {
+            break;
+        }
+        lambda.push(entry);
+    }
+    lambda
+}
+
+// Mean volume of the cylindric partitions with the profile weighted by q^volume. The
+// generating function is a product of 1 / (1 - q^e) over turns of the cylinder and over
+// the growing letters before shrinking ones, each factor adds e q^e / (1 - q^e). Stops
+// adding once the sum passes max_volume.
+fn expected_volume(profile: &[bool], q: f64, max_volume: f64) -> f64 {
+    let length = profile.len() as i32;
+    // the exponents are offset + turn * length
+    let mut offsets = vec![length];
+    for (i, grows) in profile.iter().enumerate() {
+        for (j, other) in profile.iter().enumerate() {
+            if *grows && !*other {
+                offsets.push((j as i32 - i as i32).rem_euclid(length));
+            }
+        }
+    }
+
+    let mut volume = 0.0;
+    for offset in offsets {
+        let mut exponent = offset;
+        loop {
+            let weight = q.powi(exponent);
+            let term = exponent as f64 * weight / (1.0 - weight);
+            volume += term;
+            if volume > max_volume {
+                return volume;
+            }
+            if term < NEGLIGIBLE {
+                break;
+            }
+            exponent += length;
+        }
+    }
+    volume
+}
+
+// Exact sample of the slices of a cylindric partition with the profile, weighted by
+// q^volume. The slices start all equal to a partition with weight q^(length |partition|)
+// and the growing letters are moved around the cylinder one turn at a time, from the
+// turn far enough that nothing is added down to the last one, each time passing every
+// shrinking letter with the RSK local rule and a geometric number of added cells.
+fn sample_slices<R: Rng>(profile: &[bool], q: f64, rng: &mut R) -> Vec<Vec<i32>> {
+    let length = profile.len() as i32;
+    let growing = profile.iter().filter(|grows| **grows).count();
+    let shrinking = profile.len() - growing;
+    let turn_weight = q.powi(length);
+
+    let mut free_partition = Vec::new();
+    let mut part = 1;
+    while turn_weight.powi(part) / (1.0 - turn_weight) > NEGLIGIBLE {
+        let count = sample_geometric(rng, turn_weight.powi(part));
+        free_partition.extend(std::iter::repeat_n(part, count as usize));
+        part += 1;
+    }
+    free_partition.reverse();
+
+    let pairs = (growing * shrinking) as f64;
+    let mut turns = 0;
+    while pairs * turn_weight.powi(turns + 1) / (1.0 - turn_weight) > NEGLIGIBLE {
+        turns += 1;
+    }
+
+    // letters with the exponent of q of their variable, growing ones start `turns + 1`
+    // turns ahead
+    let mut letters = profile
+        .iter()
+        .enumerate()
+        .map(|(index, grows)| match grows {
+            true => (true, (turns + 1) * length - index as i32 - 1),
+            false => (false, index as i32 + 1),
+        })
+        .collect::<Vec<_>>();
+    let mut slices = vec![free_partition; profile.len()];
+
+    let size = profile.len();
+    for _ in 0..=turns {
+        for _ in 0..shrinking {
+            // every shrinking letter passes the growing ones right after it
+            let blocks = (0..size)
+                .filter(|index| !letters[*index].0)
+                .map(|index| {
+                    let block = (1..size)
+                        .take_while(|step| letters[(index + step) % size].0)
+                        .count();
+                    (index, block)
+                })
+                .collect::<Vec<_>>();
+
+            for (start, block) in blocks {
+                for step in 0..block {
+                    let index = (start + step) % size;
+                    let next = (index + 1) % size;
+                    // across the seam the shrinking letter is one turn back
+                    let seam = if next == 0 { length } else { 0 };
+                    let shrinking_exponent = letters[index].1 - seam;
+                    let growing_exponent = letters[next].1;
+                    let exponent = shrinking_exponent + growing_exponent;
+                    debug_assert!(exponent > 0);
+
+                    let added = sample_geometric(rng, q.powi(exponent));
+                    slices[next] = grow(
+                        &slices[next],
+                        &slices[index],
+                        &slices[(index + 2) % size],
+                        added,
+                    );
+                    letters[index] = (true, growing_exponent - seam);
+                    letters[next] = (false, shrinking_exponent);
+                }
+            }
+        }
+    }
+
+    // each turn moved the word by the number of growing letters, the first letter moved
+    // to the end is one turn further on
+    for _ in 0..(turns + 1) as usize * growing {
+        let (grows, exponent) = letters[0];
+        letters.rotate_left(1);
+        letters[size - 1] = match grows {
+            true => (true, exponent - length),
+            false => (false, exponent + length),
+        };
+        slices.rotate_left(1);
+    }
+    debug_assert!(letters.iter().enumerate().all(|(index, letter)| {
+        let position = index as i32 + 1;
+        *letter
+            == (
+                profile[index],
+                if profile[index] { -position } else { position },
+            )
+    }));
+
+    slices
+}
+
+impl PeriodicLozengeTiling {
+    fn get_cylinder(&self) -> Result<Cylinder, CylindricError> {
+        let periods = self.get_periods();
+        Cylinder::new(periods.x_shift, periods.y_shift, periods.z_height)
+    }
+
+    pub fn get_cylindric_partition(&self) -> Result<CylindricPartition, CylindricError> {
+        let cylinder = self.get_cylinder()?;
+        let rows = (0..cylinder.z_height)
+            .map(|z| {
+                let mut row = Vec::new();
+                loop {
+                    let b = row.len() as i32;
+                    let (turn, nb) = (b.div_euclid(cylinder.shift), b.rem_euclid(cylinder.shift));
+                    let nz = z + turn * cylinder.z_height;
+                    let entry = (0..)
+                        .take_while(|a| *self.data.get(&cylinder.column(*a, nb)) >= nz)
+                        .count() as i32;
+                    if entry == 0 {
+                        break row;
+                    }
+                    row.push(entry);
+                }
+            })
+            .collect();
+
+        Ok(CylindricPartition {
+            profile: profile_to_string(&cylinder.profile()),
+            shift: cylinder.shift,
+            rows,
+        })
+    }
+
+    // Replaces all boxes, the tiling is left unchanged on errors
+    pub fn set_cylindric_partition(
+        &mut self,
+        partition: &CylindricPartition,
+    ) -> Result<(), CylindricError> {
+        let cylinder = self.get_cylinder()?;
+        if partition.shift != cylinder.shift || partition.rows.len() as i32 != cylinder.z_height {
+            return Err(CylindricError::WrongShape);
+        }
+        partition.validate()?;
+
+        let mut heights = Vec::new();
+        for b in 0..cylinder.shift {
+            for a in 0.. {
+                let height = (0..).take_while(|z| partition.get(b, *z) > a).count() as i32 - 1;
+                if height < 0 {
+                    break;
+                }
+                let Vector2(x, y) = cylinder.column(a, b);
+                heights.push([x, y, height]);
+            }
+        }
+        self.set_heights(&heights)?;
+        Ok(())
+    }
+
+    // Replaces the tiling with an exact sample of the q^volume measure the Markov chain
+    // converges to
+    pub fn generate_cylindric_exact(&mut self, q: f32) -> Result<(), CylindricError> {
+        let cylinder = self.get_cylinder()?;
+        if !(q > 0.0 && q < 1.0) {
+            return Err(CylindricError::InvalidQ);
+        }
+        let profile = cylinder.profile();
+        if expected_volume(&profile, q as f64, MAX_EXPECTED_VOLUME) > MAX_EXPECTED_VOLUME {
+            return Err(CylindricError::TooLarge);
+        }
+        let slices = sample_slices(&profile, q as f64, &mut self.rng);
+        self.set_cylindric_partition(&CylindricPartition::from_slices(cylinder, &slices))
+    }
+}
+
+#[wasm_bindgen]
+impl PeriodicLozengeTiling {
+    // JSON of the profile, shift and rows
+    #[wasm_bindgen(js_name = getCylindricPartition)]
+    pub fn get_cylindric_partition_js(&self) -> Result<String, JsValue> {
+        let partition = self
+            .get_cylindric_partition()
+            .map_err(|error| JsValue::from(error.to_string()))?;
+        Ok(serde_json::to_string(&partition).unwrap())
+    }
+
+    #[wasm_bindgen(js_name = setCylindricPartition)]
+    pub fn set_cylindric_partition_js(&mut self, partition: &str) -> Result<(), JsValue> {
+        let partition: CylindricPartition =
+            serde_json::from_str(partition).map_err(|error| JsValue::from(error.to_string()))?;
+        self.set_cylindric_partition(&partition)
+            .map_err(|error| JsValue::from(error.to_string()))
+    }
+
+    #[wasm_bindgen(js_name = generateCylindricExact)]
+    pub fn generate_cylindric_exact_js(&mut self, q: f32) -> Result<(), JsValue> {
+        self.generate_cylindric_exact(q)
+            .map_err(|error| JsValue::from(error.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rustc_hash::FxHashMap;
+
+    use super::{expected_volume, CylindricError, CylindricPartition};
+    use crate::PeriodicLozengeTiling;
+
+    // partitions a and b with b / a a horizontal strip
+    fn strip(a: &[i32], b: &[i32]) -> bool {
+        let part = |partition: &[i32], index: usize| partition.get(index).copied().unwrap_or(0);
+        (0..b.len().max(a.len()))
+            .all(|index| part(b, index) >= part(a, index) && part(a, index) >= part(b, index + 1))
+    }
+
+    #[test]
+    fn partition_round_trip_and_slices_interlace() {
+        for periods in [(0, 2, 3), (3, 0, 2)] {
+            let mut lozenge_tiling =
+                PeriodicLozengeTiling::new(periods.0, periods.1, periods.2, 8, 8, 8);
+            lozenge_tiling.set_seed(4);
+            lozenge_tiling.generate_with_markov_chain(400, 0.9);
+            let partition = lozenge_tiling.get_cylindric_partition().unwrap();
+
+            assert_eq!(partition.profile.len(), 5);
+            assert_eq!(
+                partition.get_volume(),
+                lozenge_tiling.get_period_box_count()
+            );
+            let slices = partition.get_slices();
+            for (index, grows) in partition.profile.chars().enumerate() {
+                let (current, next) = (&slices[index], &slices[(index + 1) % slices.len()]);
+                match grows {
+                    '+' => assert!(strip(current, next)),
+                    _ => assert!(strip(next, current)),
+                }
+            }
+
+            let mut loaded_tiling =
+                PeriodicLozengeTiling::new(periods.0, periods.1, periods.2, 8, 8, 8);
+            loaded_tiling.set_cylindric_partition(&partition).unwrap();
+            assert_eq!(loaded_tiling.to_state(), lozenge_tiling.to_state());
+        }
+    }
+
+    #[test]
+    fn exact_sampler_has_the_mean_volume_of_the_product_formula() {
+        let q: f64 = 0.6;
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(0, 2, 1, 8, 8, 8);
+        lozenge_tiling.set_seed(9);
+        let profile = lozenge_tiling
+            .get_cylindric_partition()
+            .unwrap()
+            .profile
+            .chars()
+            .map(|letter| letter == '+')
+            .collect::<Vec<_>>();
+
+        let mean = expected_volume(&profile, q, f64::INFINITY);
+
+        let samples = 4000;
+        let mut total = 0.0;
+        for _ in 0..samples {
+            lozenge_tiling.generate_cylindric_exact(0.6).unwrap();
+            total += lozenge_tiling.get_period_box_count() as f64;
+        }
+        assert!((total / samples as f64 - mean).abs() < 0.05 * mean);
+    }
+
+    // partitions with at most max_volume cells
+    fn partitions_up_to(max_volume: i32, max_part: i32) -> Vec<Vec<i32>> {
+        let mut partitions = vec![Vec::new()];
+        for first in 1..=max_part.min(max_volume) {
+            for rest in partitions_up_to(max_volume - first, first) {
+                partitions.push([vec![first], rest].concat());
+            }
+        }
+        partitions
+    }
+
+    #[test]
+    fn exact_sampler_matches_the_weights_of_small_partitions() {
+        let (q, max_volume, samples) = (0.5, 5, 20_000);
+        for periods in [(0, 1, 2), (2, 0, 2)] {
+            let mut lozenge_tiling =
+                PeriodicLozengeTiling::new(periods.0, periods.1, periods.2, 8, 8, 8);
+            lozenge_tiling.set_seed(17);
+            let empty = lozenge_tiling.get_cylindric_partition().unwrap();
+
+            // every cylindric partition up to the cutoff, by its rows
+            let mut rows = vec![Vec::new()];
+            for _ in 0..periods.2 {
+                rows = rows
+                    .iter()
+                    .flat_map(|rows: &Vec<Vec<i32>>| {
+                        let volume = rows.iter().flatten().sum::<i32>();
+                        partitions_up_to(max_volume - volume, max_volume)
+                            .into_iter()
+                            .map(move |row| [rows.clone(), vec![row]].concat())
+                    })
+                    .collect();
+            }
+            let mut weights = rows
+                .into_iter()
+                .map(|rows| CylindricPartition {
+                    rows,
+                    ..empty.clone()
+                })
+                .filter(|partition| partition.validate().is_ok())
+                .map(|partition| {
+                    (
+                        partition.rows.clone(),
+                        f64::from(q).powi(partition.get_volume()),
+                    )
+                })
+                .collect::<FxHashMap<_, _>>();
+            let total_weight = weights.values().sum::<f64>();
+            weights
+                .values_mut()
+                .for_each(|weight| *weight /= total_weight);
+
+            let mut counts = FxHashMap::default();
+            for _ in 0..samples {
+                lozenge_tiling.generate_cylindric_exact(q).unwrap();
+                let partition = lozenge_tiling.get_cylindric_partition().unwrap();
+                if partition.get_volume() <= max_volume {
+                    assert!(weights.contains_key(&partition.rows));
+                    *counts.entry(partition.rows).or_insert(0) += 1;
+                }
+            }
+
+            // chi-square of the samples under the cutoff against q^volume / Z
+            let kept = counts.values().sum::<i32>() as f64;
+            let chi_square = weights
+                .iter()
+                .map(|(rows, weight)| {
+                    let expected = kept * weight;
+                    let observed = counts.get(rows).copied().unwrap_or(0) as f64;
+                    (observed - expected).powi(2) / expected
+                })
+                .sum::<f64>();
+            let degrees = (weights.len() - 1) as f64;
+            assert!(weights.len() > 10);
+            assert!(chi_square < degrees + 4.0 * (2.0 * degrees).sqrt());
+        }
+    }
+
+    #[test]
+    fn rejects_other_periods_and_invalid_rows() {
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(1, 2, 3, 8, 8, 8);
+        assert_eq!(
+            lozenge_tiling.get_cylindric_partition(),
+            Err(CylindricError::NotCylindric)
+        );
+        assert_eq!(
+            lozenge_tiling.generate_cylindric_exact(0.5),
+            Err(CylindricError::NotCylindric)
+        );
+
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(0, 1, 2, 8, 8, 8);
+        let partition = CylindricPartition {
+            profile: "+-+".to_string(),
+            shift: 1,
+            rows: vec![vec![1], vec![2]],
+        };
+        assert_eq!(
+            lozenge_tiling.set_cylindric_partition(&partition),
+            Err(CylindricError::InvalidRows)
+        );
+        assert_eq!(
+            lozenge_tiling.generate_cylindric_exact(1.0),
+            Err(CylindricError::InvalidQ)
+        );
+        // the mean volume grows like 1 / (1 - q)^2 here, too close to 1 is refused at once
+        assert_eq!(
+            lozenge_tiling.generate_cylindric_exact(0.9999),
+            Err(CylindricError::TooLarge)
+        );
+        assert_eq!(lozenge_tiling.get_period_box_count(), 0);
+        lozenge_tiling.generate_cylindric_exact(0.99).unwrap();
+    }
+}
@@ -0,0 +1,401 @@
+use std::{collections::VecDeque, error::Error, fmt};
+
+use rustc_hash::{FxHashMap, FxHashSet};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    faces::{Face, FaceKind, FaceOrientation},
+    heights::HeightsError,
+    vector2::Vector2,
+    vector3::Vector3,
+    PeriodicLozengeTiling,
+};
+
+// The honeycomb graph has a vertex per triangle of the triangular lattice the lozenges
+// are made of. Lattice points are the projections (x - z, y - z) of corners along
+// (1, 1, 1). White vertices are the triangles (p, q), (p + 1, q), (p + 1, q + 1)
+// pointing right in the isometric projection, black ones the triangles (p, q),
+// (p, q + 1), (p + 1, q + 1) pointing left, both named by their corner (p, q).
+// Every lozenge is a dimer on the edge between its two triangles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Dimer {
+    pub white: [i32; 2],
+    pub black: [i32; 2],
+}
+
+impl Dimer {
+    pub fn from_face(voxel: &Vector3, orientation: FaceOrientation) -> Dimer {
+        let Vector3(x, y, z) = *voxel;
+        let (p, q) = (x - z, y - z);
+        match orientation {
+            FaceOrientation::X => Dimer {
+                white: [p, q],
+                black: [p, q - 1],
+            },
+            FaceOrientation::Y => Dimer {
+                white: [p - 1, q],
+                black: [p, q],
+            },
+            FaceOrientation::Z => Dimer {
+                white: [p - 1, q - 1],
+                black: [p - 1, q - 1],
+            },
+        }
+    }
+
+    // None when the triangles aren't neighbors
+    pub fn orientation(&self) -> Option<FaceOrientation> {
+        let [p, q] = self.white;
+        match [self.black[0] - p, self.black[1] - q] {
+            [0, -1] => Some(FaceOrientation::X),
+            [1, 0] => Some(FaceOrientation::Y),
+            [0, 0] => Some(FaceOrientation::Z),
+            _ => None,
+        }
+    }
+
+    // the face of the lozenge with its voxel at z = 0
+    fn get_unlifted_face(&self, orientation: FaceOrientation) -> Face {
+        let [p, q] = self.white;
+        let voxel = match orientation {
+            FaceOrientation::X => Vector3(p, q, 0),
+            FaceOrientation::Y => Vector3(p + 1, q, 0),
+            FaceOrientation::Z => Vector3(p + 1, q + 1, 0),
+        };
+        Face {
+            voxel,
+            orientation,
+            kind: FaceKind::Box,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DimerError {
+    NotAdjacent(Dimer),
+    // a honeycomb vertex in more than one dimer
+    CoveredTwice(Dimer),
+    // the base point isn't a corner of any dimer's lozenge
+    BasePointOutside,
+    // lozenges that don't fit together as a surface
+    Inconsistent(Dimer),
+    // lozenges not connected to the base point through shared corners
+    Disconnected(Dimer),
+    Heights(HeightsError),
+}
+
+impl fmt::Display for DimerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DimerError::NotAdjacent(dimer) => write!(
+                f,
+                "triangles {:?} and {:?} aren't neighbors",
+                dimer.white, dimer.black
+            ),
+            DimerError::CoveredTwice(dimer) => write!(
+                f,
+                "dimer {:?} - {:?} covers a vertex covered before",
+                dimer.white, dimer.black
+            ),
+            DimerError::BasePointOutside => {
+                write!(f, "base point isn't a corner of any lozenge")
+            }
+            DimerError::Inconsistent(dimer) => write!(
+                f,
+                "dimer {:?} - {:?} doesn't fit the surface around it",
+                dimer.white, dimer.black
+            ),
+            DimerError::Disconnected(dimer) => write!(
+                f,
+                "dimer {:?} - {:?} isn't connected to the base point",
+                dimer.white, dimer.black
+            ),
+            DimerError::Heights(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for DimerError {}
+
+impl From<HeightsError> for DimerError {
+    fn from(error: HeightsError) -> Self {
+        DimerError::Heights(error)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DimerCovering {
+    pub dimers: Vec<Dimer>,
+    // a corner of the surface, the dimers alone fix it only up to moving along (1, 1, 1)
+    pub base_point: [i32; 3],
+}
+
+impl DimerCovering {
+    pub fn from_faces(faces: &[Face]) -> DimerCovering {
+        let mut dimers = faces
+            .iter()
+            .map(|face| Dimer::from_face(&face.voxel, face.orientation))
+            .collect::<Vec<_>>();
+        dimers.sort_unstable();
+        let base_point = match faces.first() {
+            Some(face) => {
+                let Vector3(x, y, z) = face.corners()[0];
+                [x, y, z]
+            }
+            None => [0, 0, 0],
+        };
+        DimerCovering { dimers, base_point }
+    }
+
+    // Voxels and orientations of the lozenges as faces of a surface through the base
+    // point. Checks that the dimers form a matching whose lozenges fit together.
+    pub fn lift(&self) -> Result<Vec<(Vector3, FaceOrientation)>, DimerError> {
+        let mut whites = FxHashSet::default();
+        let mut blacks = FxHashSet::default();
+        let mut faces = Vec::with_capacity(self.dimers.len());
+        for dimer in self.dimers.iter() {
+            let orientation = dimer.orientation().ok_or(DimerError::NotAdjacent(*dimer))?;
+            if !whites.insert(dimer.white) || !blacks.insert(dimer.black) {
+                return Err(DimerError::CoveredTwice(*dimer));
+            }
+            faces.push(dimer.get_unlifted_face(orientation));
+        }
+
+        // lozenges at each lattice point with the z of that corner before lifting
+        let mut corners: FxHashMap<[i32; 2], Vec<(usize, i32)>> = FxHashMap::default();
+        for (index, face) in faces.iter().enumerate() {
+            for Vector3(x, y, z) in face.corners() {
+                corners.entry([x - z, y - z]).or_default().push((index, z));
+            }
+        }
+
+        let [bx, by, bz] = self.base_point;
+        let (start, start_z) = *corners
+            .get(&[bx - bz, by - bz])
+            .and_then(|lozenges| lozenges.first())
+            .ok_or(DimerError::BasePointOutside)?;
+        let mut lifts = vec![None; faces.len()];
+        lifts[start] = Some(bz - start_z);
+
+        // lozenges sharing a corner agree on its z
+        let mut queue = VecDeque::from([start]);
+        while let Some(index) = queue.pop_front() {
+            let lift = lifts[index].unwrap();
+            for Vector3(x, y, z) in faces[index].corners() {
+                for (neighbor, corner_z) in corners[&[x - z, y - z]].iter() {
+                    let neighbor_lift = z + lift - corner_z;
+                    match lifts[*neighbor] {
+                        Some(previous) if previous != neighbor_lift => {
+                            return Err(DimerError::Inconsistent(self.dimers[*neighbor]))
+                        }
+                        Some(_) => {}
+                        None => {
+                            lifts[*neighbor] = Some(neighbor_lift);
+                            queue.push_back(*neighbor);
+                        }
+                    }
+                }
+            }
+        }
+
+        faces
+            .iter()
+            .zip(lifts)
+            .zip(self.dimers.iter())
+            .map(|((face, lift), dimer)| {
+                let lift = lift.ok_or(DimerError::Disconnected(*dimer))?;
+                let Vector3(x, y, z) = face.voxel;
+                Ok((Vector3(x + lift, y + lift, z + lift), face.orientation))
+            })
+            .collect()
+    }
+}
+
+impl PeriodicLozengeTiling {
+    // dimers of the visible faces in the draw box
+    pub fn get_dimers(&self) -> DimerCovering {
+        DimerCovering::from_faces(&self.get_visible_faces())
+    }
+
+    // dimers of the visible faces in the draw box whose voxels are in the
+    // fundamental domain, all of them without periodicity
+    pub fn get_period_dimers(&self) -> DimerCovering {
+        let faces = self
+            .get_visible_faces()
+            .into_iter()
+            .filter(|face| self.normalize3(&face.voxel) == face.voxel)
+            .collect::<Vec<_>>();
+        DimerCovering::from_faces(&faces)
+    }
+
+    // Sets the heights of the columns whose tops are among the lifted lozenges, the
+    // other columns are kept. The tiling is left unchanged on errors.
+    pub fn set_dimers(&mut self, covering: &DimerCovering) -> Result<(), DimerError> {
+        let z_max = self.get_voxel_boundaries().z_max;
+
+        let mut lifted_heights = FxHashMap::default();
+        // tops at the ceiling of the draw box may be cut off, they only raise columns
+        // that aren't lifted anywhere else
+        let mut ceiling_heights = FxHashMap::default();
+        for (voxel, orientation) in covering.lift()? {
+            if orientation != FaceOrientation::Z {
+                continue;
+            }
+            let Vector3(nx, ny, nz) = self.normalize3(&voxel);
+            let column = Vector2(nx, ny);
+            if self.is_wall_column(&column) {
+                continue;
+            }
+            let height = nz - self.get_wall_offset(&column);
+            if voxel.2 == z_max - 1 {
+                ceiling_heights.insert(column, height);
+            } else if *lifted_heights.entry(column).or_insert(height) != height {
+                let Vector3(x, y, _) = voxel;
+                return Err(HeightsError::Conflict { x, y }.into());
+            }
+        }
+        for (column, height) in ceiling_heights {
+            let current_height = *self.data.get(&column);
+            lifted_heights
+                .entry(column)
+                .or_insert(height.max(current_height));
+        }
+
+        let mut heights = self
+            .data
+            .iter()
+            .filter(|(column, _)| !lifted_heights.contains_key(*column))
+            .map(|(Vector2(x, y), height)| [*x, *y, *height])
+            .collect::<Vec<_>>();
+        heights.extend(
+            lifted_heights
+                .iter()
+                .map(|(Vector2(x, y), height)| [*x, *y, *height]),
+        );
+        self.set_heights(&heights)?;
+        Ok(())
+    }
+}
+
+#[wasm_bindgen]
+impl PeriodicLozengeTiling {
+    // JSON of the dimer covering of the draw box or of one period
+    #[wasm_bindgen(js_name = getDimers)]
+    pub fn get_dimers_js(&self, period: bool) -> String {
+        let covering = match period {
+            true => self.get_period_dimers(),
+            false => self.get_dimers(),
+        };
+        serde_json::to_string(&covering).unwrap()
+    }
+
+    #[wasm_bindgen(js_name = setDimers)]
+    pub fn set_dimers_js(&mut self, covering: &str) -> Result<(), JsValue> {
+        let covering: DimerCovering =
+            serde_json::from_str(covering).map_err(|error| JsValue::from(error.to_string()))?;
+        self.set_dimers(&covering)
+            .map_err(|error| JsValue::from(error.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rustc_hash::FxHashSet;
+
+    use super::{Dimer, DimerError};
+    use crate::PeriodicLozengeTiling;
+
+    #[test]
+    fn every_vertex_of_hexagon_is_covered_once() {
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(0, 0, 3, 3, 3, 3);
+        lozenge_tiling
+            .load_heights_matrix("3 2 1\n2 2 0\n1 0 0")
+            .unwrap();
+
+        // the hexagon of the 3 x 3 x 3 box, triangles scaled by 3 to have integer
+        // centers
+        let in_hexagon = |p: i32, q: i32| {
+            (-9..=9).contains(&p) && (-9..=9).contains(&q) && (-9..=9).contains(&(p - q))
+        };
+        let mut whites = FxHashSet::default();
+        let mut blacks = FxHashSet::default();
+        for p in -4..4 {
+            for q in -4..4 {
+                if in_hexagon(3 * p + 2, 3 * q + 1) {
+                    whites.insert([p, q]);
+                }
+                if in_hexagon(3 * p + 1, 3 * q + 2) {
+                    blacks.insert([p, q]);
+                }
+            }
+        }
+        assert_eq!(whites.len(), 27);
+
+        let dimers = lozenge_tiling
+            .get_dimers()
+            .dimers
+            .into_iter()
+            .filter(|Dimer { white: [p, q], .. }| whites.contains(&[*p, *q]))
+            .collect::<Vec<_>>();
+
+        assert_eq!(dimers.len(), whites.len());
+        let covered_blacks = dimers
+            .iter()
+            .map(|dimer| dimer.black)
+            .collect::<FxHashSet<_>>();
+        assert_eq!(covered_blacks, blacks);
+    }
+
+    #[test]
+    fn dimers_round_trip() {
+        // a draw box holding all the columns of the period that aren't empty
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(1, 2, 3, 10, 10, 10);
+        lozenge_tiling.set_seed(5);
+        lozenge_tiling.generate_with_markov_chain(400, 0.9);
+        let state = lozenge_tiling.to_state();
+
+        for covering in [
+            lozenge_tiling.get_dimers(),
+            lozenge_tiling.get_period_dimers(),
+        ] {
+            assert!(covering.lift().is_ok());
+
+            let mut loaded_tiling = PeriodicLozengeTiling::new(1, 2, 3, 10, 10, 10);
+            loaded_tiling.set_dimers(&covering).unwrap();
+
+            assert_eq!(loaded_tiling.to_state(), state);
+        }
+    }
+
+    #[test]
+    fn rejects_dimers_that_are_not_a_tiling() {
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(1, 2, 3, 4, 4, 4);
+        lozenge_tiling.generate_by_adding_only(5);
+        let state = lozenge_tiling.to_state();
+        let covering = lozenge_tiling.get_dimers();
+
+        let mut twice = covering.clone();
+        twice.dimers.push(twice.dimers[0]);
+        assert_eq!(
+            lozenge_tiling.set_dimers(&twice),
+            Err(DimerError::CoveredTwice(covering.dimers[0]))
+        );
+
+        let mut apart = covering.clone();
+        apart.dimers[0].black[0] += 2;
+        assert!(matches!(
+            lozenge_tiling.set_dimers(&apart),
+            Err(DimerError::NotAdjacent(_))
+        ));
+
+        let mut outside = covering.clone();
+        outside.base_point = [100, 0, 0];
+        assert_eq!(
+            lozenge_tiling.set_dimers(&outside),
+            Err(DimerError::BasePointOutside)
+        );
+
+        assert_eq!(lozenge_tiling.to_state(), state);
+    }
+}
@@ -0,0 +1,185 @@
+use crate::{vector3::Vector3, PeriodicLozengeTiling, VoxelBoundaries};
+
+// Outward normal of a face, only +x, +y and +z faces are visible from (1, 1, 1).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FaceOrientation {
+    X,
+    Y,
+    Z,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FaceKind {
+    Box,
+    Wall,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Face {
+    pub voxel: Vector3,
+    pub orientation: FaceOrientation,
+    pub kind: FaceKind,
+}
+
+impl Face {
+    // Corners counterclockwise when looking at the face from outside.
+    pub fn corners(&self) -> [Vector3; 4] {
+        let Vector3(x, y, z) = self.voxel;
+        match self.orientation {
+            FaceOrientation::X => [
+                Vector3(x + 1, y, z),
+                Vector3(x + 1, y + 1, z),
+                Vector3(x + 1, y + 1, z + 1),
+                Vector3(x + 1, y, z + 1),
+            ],
+            FaceOrientation::Y => [
+                Vector3(x, y + 1, z),
+                Vector3(x, y + 1, z + 1),
+                Vector3(x + 1, y + 1, z + 1),
+                Vector3(x + 1, y + 1, z),
+            ],
+            FaceOrientation::Z => [
+                Vector3(x, y, z + 1),
+                Vector3(x + 1, y, z + 1),
+                Vector3(x + 1, y + 1, z + 1),
+                Vector3(x, y + 1, z + 1),
+            ],
+        }
+    }
+}
+
+// Isometric projection looking from (1, 1, 1), x goes down right, y down left and z up.
+pub fn project_isometric(point: &Vector3) -> (f64, f64) {
+    let Vector3(x, y, z) = *point;
+    let u = f64::from(x - y) * 3f64.sqrt() / 2.0;
+    let v = f64::from(z) - f64::from(x + y) / 2.0;
+    (u, v)
+}
+
+impl PeriodicLozengeTiling {
+    // Walls and boxes form a down-closed set, so every column is solid up to its top.
+    fn get_column_top(&self, x: i32, y: i32, boundaries: &VoxelBoundaries) -> Option<i32> {
+        let VoxelBoundaries { z_min, z_max, .. } = *boundaries;
+
+        if !self.is_wall_or_box(&Vector3(x, y, z_min)) {
+            return None;
+        }
+
+        // binary search for the last solid z
+        let (mut solid, mut empty) = (z_min, z_max);
+        while empty - solid > 1 {
+            let middle = solid + (empty - solid) / 2;
+            if self.is_wall_or_box(&Vector3(x, y, middle)) {
+                solid = middle;
+            } else {
+                empty = middle;
+            }
+        }
+
+        Some(solid)
+    }
+
+    // Faces of walls and boxes inside the boundaries visible from (1, 1, 1).
+    // Faces cut by the boundaries are included, so the faces always form a lozenge tiling.
+    pub(crate) fn get_visible_faces_in(&self, boundaries: &VoxelBoundaries) -> Vec<Face> {
+        let VoxelBoundaries {
+            x_min,
+            x_max,
+            y_min,
+            y_max,
+            z_min,
+            ..
+        } = *boundaries;
+
+        let width = (x_max - x_min).max(0) as usize;
+        let depth = (y_max - y_min).max(0) as usize;
+        let mut tops = vec![None; width * depth];
+        for x in x_min..x_max {
+            for y in y_min..y_max {
+                tops[(x - x_min) as usize * depth + (y - y_min) as usize] =
+                    self.get_column_top(x, y, boundaries);
+            }
+        }
+
+        let get_top = |x: i32, y: i32| -> i32 {
+            if x < x_min || x >= x_max || y < y_min || y >= y_max {
+                return z_min - 1;
+            }
+            tops[(x - x_min) as usize * depth + (y - y_min) as usize].unwrap_or(z_min - 1)
+        };
+
+        let mut faces = Vec::new();
+        for x in x_min..x_max {
+            for y in y_min..y_max {
+                let top = get_top(x, y);
+                if top < z_min {
+                    continue;
+                }
+
+                let mut push_face = |voxel: Vector3, orientation: FaceOrientation| {
+                    let kind = match self.is_wall(&voxel) {
+                        true => FaceKind::Wall,
+                        false => FaceKind::Box,
+                    };
+                    faces.push(Face {
+                        voxel,
+                        orientation,
+                        kind,
+                    });
+                };
+
+                push_face(Vector3(x, y, top), FaceOrientation::Z);
+                for z in (get_top(x + 1, y) + 1).max(z_min)..=top {
+                    push_face(Vector3(x, y, z), FaceOrientation::X);
+                }
+                for z in (get_top(x, y + 1) + 1).max(z_min)..=top {
+                    push_face(Vector3(x, y, z), FaceOrientation::Y);
+                }
+            }
+        }
+
+        faces
+    }
+
+    pub fn get_visible_faces(&self) -> Vec<Face> {
+        self.get_visible_faces_in(&self.get_voxel_boundaries())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        faces::{FaceKind, FaceOrientation},
+        vector3::Vector3,
+        PeriodicLozengeTiling,
+    };
+
+    #[test]
+    fn single_box_shows_three_box_faces() {
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(0, 0, 0, 3, 3, 3);
+        lozenge_tiling.add_box(Vector3(0, 0, 0));
+
+        let box_faces = lozenge_tiling
+            .get_visible_faces()
+            .into_iter()
+            .filter(|face| face.kind == FaceKind::Box)
+            .collect::<Vec<_>>();
+
+        assert_eq!(box_faces.len(), 3);
+        for orientation in [FaceOrientation::X, FaceOrientation::Y, FaceOrientation::Z] {
+            assert!(box_faces
+                .iter()
+                .any(|face| face.orientation == orientation && face.voxel == Vector3(0, 0, 0)));
+        }
+    }
+
+    #[test]
+    fn visible_faces_do_not_depend_on_boxes_count() {
+        // every lattice line along (1, 1, 1) crosses the surface once,
+        // so adding boxes only changes which faces are visible, not how many
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(0, 0, 0, 5, 5, 5);
+        let empty_count = lozenge_tiling.get_visible_faces().len();
+        lozenge_tiling.generate_by_adding_only(20);
+        assert_eq!(lozenge_tiling.get_visible_faces().len(), empty_count);
+    }
+}
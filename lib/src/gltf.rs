@@ -0,0 +1,204 @@
+use serde_json::{json, Value};
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    faces::{FaceKind, FaceOrientation},
+    mesh::{get_material_name, Mesh},
+    raster::ColorScheme,
+    vector3::Vector3,
+    PeriodicLozengeTiling,
+};
+
+const GLB_MAGIC: &[u8; 4] = b"glTF";
+const JSON_CHUNK: &[u8; 4] = b"JSON";
+const BIN_CHUNK: &[u8; 4] = b"BIN\0";
+// glTF enums
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+
+// sRGB byte to the linear value used by baseColorFactor
+fn to_linear(value: u8) -> f64 {
+    let value = f64::from(value) / 255.0;
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn pad_to_4(bytes: &mut Vec<u8>, padding: u8) {
+    while !bytes.len().is_multiple_of(4) {
+        bytes.push(padding);
+    }
+}
+
+impl Mesh {
+    // Self-contained binary glTF 2.0 with one primitive per face kind and orientation.
+    // No normals are stored, viewers compute flat ones as the spec requires.
+    pub fn to_glb(&self, color_scheme: ColorScheme) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        let mut buffer_views = Vec::new();
+        let mut accessors = Vec::new();
+        let mut materials = Vec::new();
+        let mut primitives = Vec::new();
+
+        let (mut min, mut max) = ([i32::MAX; 3], [i32::MIN; 3]);
+        for Vector3(x, y, z) in self.vertices.iter() {
+            for (axis, value) in [x, y, z].into_iter().enumerate() {
+                min[axis] = min[axis].min(*value);
+                max[axis] = max[axis].max(*value);
+                buffer.extend_from_slice(&(*value as f32).to_le_bytes());
+            }
+        }
+        buffer_views.push(json!({
+            "buffer": 0,
+            "byteOffset": 0,
+            "byteLength": buffer.len(),
+            "target": ARRAY_BUFFER,
+        }));
+        accessors.push(json!({
+            "bufferView": 0,
+            "componentType": FLOAT,
+            "count": self.vertices.len(),
+            "type": "VEC3",
+            "min": min,
+            "max": max,
+        }));
+
+        let (box_colors, wall_colors) = color_scheme.get_colors();
+        for (kind, colors) in [(FaceKind::Box, box_colors), (FaceKind::Wall, wall_colors)] {
+            let orientations = [FaceOrientation::X, FaceOrientation::Y, FaceOrientation::Z];
+            for (orientation, [r, g, b]) in orientations.into_iter().zip(colors) {
+                let indices = self
+                    .faces
+                    .iter()
+                    .filter(|face| face.kind == kind && face.orientation == orientation)
+                    .flat_map(|face| {
+                        let [a, b, c, d] = face.vertices;
+                        [a, b, c, a, c, d]
+                    })
+                    .collect::<Vec<_>>();
+                if indices.is_empty() {
+                    continue;
+                }
+
+                let byte_offset = buffer.len();
+                for index in indices.iter() {
+                    buffer.extend_from_slice(&index.to_le_bytes());
+                }
+                buffer_views.push(json!({
+                    "buffer": 0,
+                    "byteOffset": byte_offset,
+                    "byteLength": buffer.len() - byte_offset,
+                    "target": ELEMENT_ARRAY_BUFFER,
+                }));
+                accessors.push(json!({
+                    "bufferView": buffer_views.len() - 1,
+                    "componentType": UNSIGNED_INT,
+                    "count": indices.len(),
+                    "type": "SCALAR",
+                }));
+                materials.push(json!({
+                    "name": get_material_name(kind, orientation),
+                    "pbrMetallicRoughness": {
+                        "baseColorFactor": [to_linear(r), to_linear(g), to_linear(b), 1.0],
+                        "metallicFactor": 0.0,
+                        "roughnessFactor": 1.0,
+                    },
+                }));
+                primitives.push(json!({
+                    "attributes": { "POSITION": 0 },
+                    "indices": accessors.len() - 1,
+                    "material": materials.len() - 1,
+                }));
+            }
+        }
+
+        let gltf: Value = json!({
+            "asset": { "version": "2.0", "generator": "lozenge_tilings" },
+            "scene": 0,
+            "scenes": [{ "nodes": [0] }],
+            // z is up in the tiling and y in glTF
+            "nodes": [{
+                "mesh": 0,
+                "rotation": [-std::f64::consts::FRAC_1_SQRT_2, 0.0, 0.0, std::f64::consts::FRAC_1_SQRT_2],
+            }],
+            "meshes": [{ "name": "lozenge tiling", "primitives": primitives }],
+            "materials": materials,
+            "accessors": accessors,
+            "bufferViews": buffer_views,
+            "buffers": [{ "byteLength": buffer.len() }],
+        });
+
+        let mut json_chunk = serde_json::to_vec(&gltf).unwrap();
+        pad_to_4(&mut json_chunk, b' ');
+        pad_to_4(&mut buffer, 0);
+
+        let length = 12 + 8 + json_chunk.len() + 8 + buffer.len();
+        let mut glb = Vec::with_capacity(length);
+        glb.extend_from_slice(GLB_MAGIC);
+        glb.extend_from_slice(&2u32.to_le_bytes());
+        glb.extend_from_slice(&(length as u32).to_le_bytes());
+        glb.extend_from_slice(&(json_chunk.len() as u32).to_le_bytes());
+        glb.extend_from_slice(JSON_CHUNK);
+        glb.extend_from_slice(&json_chunk);
+        glb.extend_from_slice(&(buffer.len() as u32).to_le_bytes());
+        glb.extend_from_slice(BIN_CHUNK);
+        glb.extend_from_slice(&buffer);
+
+        glb
+    }
+}
+
+#[wasm_bindgen]
+impl PeriodicLozengeTiling {
+    #[wasm_bindgen(js_name = toGlb)]
+    pub fn to_glb_js(&self, include_walls: bool) -> Vec<u8> {
+        self.get_mesh(include_walls).to_glb(ColorScheme::Default)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use crate::{raster::ColorScheme, PeriodicLozengeTiling};
+
+    fn read_u32(bytes: &[u8], offset: usize) -> usize {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap()) as usize
+    }
+
+    #[test]
+    fn glb_has_valid_chunks() {
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(1, 2, 3, 4, 4, 4);
+        lozenge_tiling.set_seed(4);
+        lozenge_tiling.generate_with_markov_chain(300, 0.9);
+        let mesh = lozenge_tiling.get_mesh(true);
+
+        let glb = mesh.to_glb(ColorScheme::Default);
+
+        assert_eq!(&glb[0..4], b"glTF");
+        assert_eq!(read_u32(&glb, 4), 2);
+        assert_eq!(read_u32(&glb, 8), glb.len());
+        let json_length = read_u32(&glb, 12);
+        assert_eq!(&glb[16..20], b"JSON");
+        let gltf: Value = serde_json::from_slice(&glb[20..20 + json_length]).unwrap();
+        let bin_length = read_u32(&glb, 20 + json_length);
+        assert_eq!(&glb[24 + json_length..28 + json_length], b"BIN\0");
+        assert_eq!(28 + json_length + bin_length, glb.len());
+
+        assert_eq!(gltf["accessors"][0]["count"], mesh.vertices.len());
+        let primitives = gltf["meshes"][0]["primitives"].as_array().unwrap();
+        assert_eq!(primitives.len(), 6);
+        let indices_count = primitives
+            .iter()
+            .map(|primitive| {
+                let accessor = primitive["indices"].as_u64().unwrap() as usize;
+                gltf["accessors"][accessor]["count"].as_u64().unwrap()
+            })
+            .sum::<u64>();
+        assert_eq!(indices_count as usize, 6 * mesh.faces.len());
+    }
+}
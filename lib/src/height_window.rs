@@ -0,0 +1,292 @@
+use js_sys::Int32Array;
+use rustc_hash::FxHashMap;
+use wasm_bindgen::prelude::*;
+
+use crate::{heights::HeightsError, vector2::Vector2, vector3::Vector3, PeriodicLozengeTiling};
+
+// Height of the columns that are wall all the way up, written as is (2147483647) in
+// the CSV and npy exports of a window
+pub const WALL_COLUMN_HEIGHT: i32 = i32::MAX;
+
+const NPY_MAGIC: &[u8; 6] = b"\x93NUMPY";
+
+// Heights of the columns x_start..x_start + x_size, y_start..y_start + y_size with
+// periodicity unwrapped: the z of the top box, including the wall below it, and -1
+// for empty columns. Stored row by row, a row per x.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeightWindow {
+    pub x_start: i32,
+    pub y_start: i32,
+    pub x_size: usize,
+    pub y_size: usize,
+    pub heights: Vec<i32>,
+}
+
+impl HeightWindow {
+    pub fn get(&self, x: i32, y: i32) -> i32 {
+        let row = (x - self.x_start) as usize;
+        let column = (y - self.y_start) as usize;
+        self.heights[row * self.y_size + column]
+    }
+
+    // x,y,unwrapped_height rows. Unlike the saved heights of TilingState::heights_to_csv
+    // these are z values of the top boxes, and wall columns are WALL_COLUMN_HEIGHT.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("x,y,unwrapped_height\n");
+        for (index, height) in self.heights.iter().enumerate() {
+            let x = self.x_start + (index / self.y_size) as i32;
+            let y = self.y_start + (index % self.y_size) as i32;
+            csv.push_str(&format!("{},{},{}\n", x, y, height));
+        }
+        csv
+    }
+
+    // NumPy .npy version 1.0 of an int32 array with shape (x_size, y_size), wall
+    // columns are WALL_COLUMN_HEIGHT
+    pub fn to_npy(&self) -> Vec<u8> {
+        let mut header = format!(
+            "{{'descr': '<i4', 'fortran_order': False, 'shape': ({}, {}), }}",
+            self.x_size, self.y_size
+        );
+        // magic, version and header length take 10 bytes, the data starts at a
+        // multiple of 64 after the newline ending the header
+        while (10 + header.len() + 1) % 64 != 0 {
+            header.push(' ');
+        }
+        header.push('\n');
+
+        let mut npy = Vec::with_capacity(10 + header.len() + 4 * self.heights.len());
+        npy.extend_from_slice(NPY_MAGIC);
+        npy.extend_from_slice(&[1, 0]);
+        npy.extend_from_slice(&(header.len() as u16).to_le_bytes());
+        npy.extend_from_slice(header.as_bytes());
+        for height in self.heights.iter() {
+            npy.extend_from_slice(&height.to_le_bytes());
+        }
+        npy
+    }
+}
+
+impl PeriodicLozengeTiling {
+    pub fn get_height_window(
+        &self,
+        x_start: i32,
+        y_start: i32,
+        x_size: usize,
+        y_size: usize,
+    ) -> HeightWindow {
+        let mut heights = Vec::with_capacity(x_size * y_size);
+        for x in x_start..x_start + x_size as i32 {
+            for y in y_start..y_start + y_size as i32 {
+                // normalizing moves the column by whole periods, and z along with it
+                let Vector3(nx, ny, z_offset) = self.normalize3(&Vector3(x, y, 0));
+                let column = Vector2(nx, ny);
+                heights.push(match self.is_wall_column(&column) {
+                    true => WALL_COLUMN_HEIGHT,
+                    false => self.get_height(&column) - z_offset,
+                });
+            }
+        }
+
+        HeightWindow {
+            x_start,
+            y_start,
+            x_size,
+            y_size,
+            heights,
+        }
+    }
+
+    // Sets the heights of the columns in the window, the other columns are kept.
+    // Columns that are wall all the way up are skipped. The tiling is left unchanged
+    // when the heights don't form a legal configuration.
+    pub fn set_height_window(&mut self, window: &HeightWindow) -> Result<(), HeightsError> {
+        let mut window_heights = FxHashMap::default();
+        for (index, height) in window.heights.iter().enumerate() {
+            let x = window.x_start + (index / window.y_size) as i32;
+            let y = window.y_start + (index % window.y_size) as i32;
+            let Vector3(nx, ny, z_offset) = self.normalize3(&Vector3(x, y, 0));
+            let column = Vector2(nx, ny);
+            if *height == WALL_COLUMN_HEIGHT || self.is_wall_column(&column) {
+                continue;
+            }
+            let saved_height = height + z_offset - self.get_wall_offset(&column);
+            if *window_heights.entry(column).or_insert(saved_height) != saved_height {
+                return Err(HeightsError::Conflict { x, y });
+            }
+        }
+
+        let mut heights = self
+            .data
+            .iter()
+            .filter(|(column, _)| !window_heights.contains_key(*column))
+            .map(|(Vector2(x, y), height)| [*x, *y, *height])
+            .collect::<Vec<_>>();
+        heights.extend(
+            window_heights
+                .iter()
+                .map(|(Vector2(x, y), height)| [*x, *y, *height]),
+        );
+        self.set_heights(&heights)
+    }
+
+    // The columns of the draw box that aren't wall all the way up
+    pub fn get_draw_height_window(&self) -> HeightWindow {
+        let draw_distance = self.get_draw_distance();
+        let x_start = match self.periods.x_shift {
+            0 => 0,
+            _ => -draw_distance.x,
+        };
+        let y_start = match self.periods.y_shift {
+            0 => 0,
+            _ => -draw_distance.y,
+        };
+        self.get_height_window(
+            x_start,
+            y_start,
+            (draw_distance.x - x_start).max(0) as usize,
+            (draw_distance.y - y_start).max(0) as usize,
+        )
+    }
+}
+
+#[wasm_bindgen]
+impl PeriodicLozengeTiling {
+    // row by row, a row of y_size heights per x
+    #[wasm_bindgen(js_name = getHeightWindow)]
+    pub fn get_height_window_js(
+        &self,
+        x_start: i32,
+        y_start: i32,
+        x_size: usize,
+        y_size: usize,
+    ) -> Int32Array {
+        let window = self.get_height_window(x_start, y_start, x_size, y_size);
+        Int32Array::from(&window.heights[..])
+    }
+
+    #[wasm_bindgen(js_name = setHeightWindow)]
+    pub fn set_height_window_js(
+        &mut self,
+        x_start: i32,
+        y_start: i32,
+        x_size: usize,
+        y_size: usize,
+        heights: Int32Array,
+    ) -> Result<(), JsValue> {
+        let heights = heights.to_vec();
+        if heights.len() != x_size * y_size {
+            return Err(JsValue::from("heights don't match the window size"));
+        }
+        self.set_height_window(&HeightWindow {
+            x_start,
+            y_start,
+            x_size,
+            y_size,
+            heights,
+        })
+        .map_err(|error| JsValue::from(error.to_string()))
+    }
+
+    #[wasm_bindgen(js_name = getHeightWindowCsv)]
+    pub fn get_height_window_csv_js(
+        &self,
+        x_start: i32,
+        y_start: i32,
+        x_size: usize,
+        y_size: usize,
+    ) -> String {
+        self.get_height_window(x_start, y_start, x_size, y_size)
+            .to_csv()
+    }
+
+    #[wasm_bindgen(js_name = getHeightWindowNpy)]
+    pub fn get_height_window_npy_js(
+        &self,
+        x_start: i32,
+        y_start: i32,
+        x_size: usize,
+        y_size: usize,
+    ) -> Vec<u8> {
+        self.get_height_window(x_start, y_start, x_size, y_size)
+            .to_npy()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WALL_COLUMN_HEIGHT;
+    use crate::PeriodicLozengeTiling;
+
+    #[test]
+    fn window_unwraps_periods() {
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(1, 2, 3, 5, 5, 5);
+        lozenge_tiling.set_seed(3);
+        lozenge_tiling.generate_with_markov_chain(500, 0.9);
+
+        let window = lozenge_tiling.get_height_window(-4, -4, 9, 10);
+
+        assert_eq!(window.heights.len(), 90);
+        // one period over shifts the column by the z period
+        for x in -4..3 {
+            for y in -4..4 {
+                assert_eq!(window.get(x + 1, y + 2), window.get(x, y) - 3);
+            }
+        }
+        assert_eq!(
+            window.to_csv().lines().nth(1),
+            Some(format!("-4,-4,{}", window.get(-4, -4)).as_str())
+        );
+    }
+
+    #[test]
+    fn window_of_hexagon_marks_walls() {
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(0, 0, 0, 3, 3, 3);
+        lozenge_tiling.load_heights_matrix("2 1\n1").unwrap();
+
+        let window = lozenge_tiling.get_height_window(-1, 0, 3, 3);
+
+        assert_eq!(window.heights[..3], [WALL_COLUMN_HEIGHT; 3]);
+        assert_eq!(window.heights[3..], [1, 0, -1, 0, -1, -1]);
+        let csv = window.to_csv();
+        assert_eq!(
+            csv.lines().take(2).collect::<Vec<_>>(),
+            vec!["x,y,unwrapped_height", "-1,0,2147483647"]
+        );
+        assert_eq!(lozenge_tiling.get_draw_height_window().heights.len(), 9);
+    }
+
+    #[test]
+    fn setting_window_restores_heights() {
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(1, 2, 3, 8, 8, 8);
+        lozenge_tiling.set_seed(9);
+        lozenge_tiling.generate_with_markov_chain(300, 0.9);
+        let window = lozenge_tiling.get_draw_height_window();
+        let state = lozenge_tiling.to_state();
+
+        let mut loaded_tiling = PeriodicLozengeTiling::new(1, 2, 3, 8, 8, 8);
+        loaded_tiling.set_height_window(&window).unwrap();
+        assert_eq!(loaded_tiling.to_state(), state);
+
+        let mut conflicting = window.clone();
+        conflicting.heights[0] += 1;
+        assert!(loaded_tiling.set_height_window(&conflicting).is_err());
+        assert_eq!(loaded_tiling.to_state(), state);
+    }
+
+    #[test]
+    fn npy_header_is_aligned() {
+        let lozenge_tiling = PeriodicLozengeTiling::new(1, 2, 3, 3, 3, 3);
+        let window = lozenge_tiling.get_height_window(0, 0, 2, 3);
+
+        let npy = window.to_npy();
+
+        assert_eq!(&npy[..8], b"\x93NUMPY\x01\x00");
+        let header_length = u16::from_le_bytes([npy[8], npy[9]]) as usize;
+        assert_eq!((10 + header_length) % 64, 0);
+        let header = std::str::from_utf8(&npy[10..10 + header_length]).unwrap();
+        assert!(header.contains("'shape': (2, 3)"));
+        assert!(header.ends_with('\n'));
+        assert_eq!(npy.len(), 10 + header_length + 4 * 6);
+    }
+}
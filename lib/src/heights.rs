@@ -0,0 +1,335 @@
+use std::{error::Error, fmt};
+
+use js_sys::Int32Array;
+use rustc_hash::FxHashMap;
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    box_map::BoxMap, vector2::Vector2, vector3::Vector3, LozengeTilingPeriods,
+    PeriodicLozengeTiling,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HeightsError {
+    // line numbers start at 1
+    Parse { line: usize, message: String },
+    // heights below -1, the value of an empty column
+    NegativeHeight { x: i32, y: i32 },
+    // two entries of the same column after normalization
+    Conflict { x: i32, y: i32 },
+    // boxes in a column that is all wall
+    InsideWall { x: i32, y: i32 },
+    // boxes above the z period when there is no shift
+    TooHigh { x: i32, y: i32 },
+    // the top box has no box or wall to its -x or -y side
+    Unsupported { x: i32, y: i32 },
+    // the column breaks the symmetry set on the tiling
+    NotSymmetric { x: i32, y: i32 },
+    // boxes beyond a cut side
+    OutsideBoundary { x: i32, y: i32 },
+    // boxes in a frozen column or a hole
+    Obstructed { x: i32, y: i32 },
+}
+
+impl fmt::Display for HeightsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HeightsError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            HeightsError::NegativeHeight { x, y } => {
+                write!(f, "column ({}, {}) has a height below -1", x, y)
+            }
+            HeightsError::Conflict { x, y } => {
+                write!(f, "column ({}, {}) is given different heights", x, y)
+            }
+            HeightsError::InsideWall { x, y } => {
+                write!(f, "column ({}, {}) has boxes inside the wall", x, y)
+            }
+            HeightsError::TooHigh { x, y } => {
+                write!(f, "column ({}, {}) is higher than the z period", x, y)
+            }
+            HeightsError::Unsupported { x, y } => write!(
+                f,
+                "column ({}, {}) is higher than its -x or -y neighbor",
+                x, y
+            ),
+            HeightsError::NotSymmetric { x, y } => {
+                write!(f, "column ({}, {}) breaks the symmetry", x, y)
+            }
+            HeightsError::OutsideBoundary { x, y } => {
+                write!(f, "column ({}, {}) has boxes beyond a boundary cut", x, y)
+            }
+            HeightsError::Obstructed { x, y } => {
+                write!(f, "column ({}, {}) has boxes in an obstacle", x, y)
+            }
+        }
+    }
+}
+
+impl Error for HeightsError {}
+
+// [x, y, height] rows of the CSV written by TilingState::heights_to_csv, the header
+// line is optional
+pub fn parse_heights_csv(csv: &str) -> Result<Vec<[i32; 3]>, HeightsError> {
+    let mut heights = Vec::new();
+    for (index, line) in csv.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || (index == 0 && line.starts_with('x')) {
+            continue;
+        }
+        let values = line
+            .split(',')
+            .map(|value| value.trim().parse::<i32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|error| HeightsError::Parse {
+                line: index + 1,
+                message: error.to_string(),
+            })?;
+        match values[..] {
+            [x, y, height] => heights.push([x, y, height]),
+            _ => {
+                return Err(HeightsError::Parse {
+                    line: index + 1,
+                    message: "expected x,y,height".to_string(),
+                })
+            }
+        }
+    }
+    Ok(heights)
+}
+
+// A plane partition: the entry in row x and column y is the number of boxes in
+// column (x, y). Entries are separated by whitespace or commas.
+pub fn parse_heights_matrix(matrix: &str) -> Result<Vec<[i32; 3]>, HeightsError> {
+    let mut heights = Vec::new();
+    let rows = matrix
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty());
+    for (x, (index, line)) in rows.enumerate() {
+        let counts = line
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|value| !value.is_empty())
+            .map(|value| value.parse::<i32>());
+        for (y, count) in counts.enumerate() {
+            let count = count.map_err(|error| HeightsError::Parse {
+                line: index + 1,
+                message: error.to_string(),
+            })?;
+            if count != 0 {
+                heights.push([x as i32, y as i32, count - 1]);
+            }
+        }
+    }
+    Ok(heights)
+}
+
+impl PeriodicLozengeTiling {
+    // Replaces all boxes with the given [x, y, height] columns, heights as in
+    // TilingState. Columns are normalized, missing ones are empty. The tiling is left
+    // unchanged when the heights don't form a legal configuration.
+    pub fn set_heights(&mut self, heights: &[[i32; 3]]) -> Result<(), HeightsError> {
+        let mut normalized_heights = FxHashMap::default();
+        for [x, y, height] in heights.iter() {
+            if *height < -1 {
+                return Err(HeightsError::NegativeHeight { x: *x, y: *y });
+            }
+            let column = self.normalize2(&Vector2(*x, *y));
+            match normalized_heights.insert(column, *height) {
+                Some(previous) if previous != *height => {
+                    return Err(HeightsError::Conflict { x: *x, y: *y })
+                }
+                _ => {}
+            }
+        }
+
+        let mut data = BoxMap::new();
+        for (column, height) in normalized_heights.iter() {
+            data.set(column, *height);
+        }
+        let previous_data = std::mem::replace(&mut self.data, data);
+        if let Err(error) = self.validate_heights() {
+            self.data = previous_data;
+            return Err(error);
+        }
+
+        self.rebuild_box_sets();
+        self.clear_history();
+        Ok(())
+    }
+
+    // Checks the top box of every column, and the support of every box in it since a
+    // box on a cut needs none while the ones below it do
+    pub(crate) fn validate_heights(&self) -> Result<(), HeightsError> {
+        let LozengeTilingPeriods {
+            x_shift,
+            y_shift,
+            z_height,
+        } = self.periods;
+
+        if let Some(Vector2(x, y)) = self.find_obstructed_column() {
+            return Err(HeightsError::Obstructed { x, y });
+        }
+        for (column, height) in self.data.iter() {
+            let Vector2(x, y) = *column;
+            if self.is_wall_column(column) {
+                return Err(HeightsError::InsideWall { x, y });
+            }
+            let top_box = Vector3(x, y, self.get_height(column));
+            let Vector3(_, _, z) = top_box;
+
+            if self.is_wall(&top_box) {
+                return Err(HeightsError::InsideWall { x, y });
+            }
+            if x_shift == 0 && y_shift == 0 && z_height > 0 && z > z_height - 1 {
+                return Err(HeightsError::TooHigh { x, y });
+            }
+            if self.is_outside_boundary(&top_box) {
+                return Err(HeightsError::OutsideBoundary { x, y });
+            }
+            if (z - height..=z).any(|z| {
+                !self.is_support(&Vector3(x - 1, y, z)) || !self.is_support(&Vector3(x, y - 1, z))
+            }) {
+                return Err(HeightsError::Unsupported { x, y });
+            }
+        }
+        if let Some(Vector2(x, y)) = self.find_asymmetric_column() {
+            return Err(HeightsError::NotSymmetric { x, y });
+        }
+        Ok(())
+    }
+
+    // Columns that are wall all the way up, get_height isn't defined for them
+    pub(crate) fn is_wall_column(&self, normalized_column: &Vector2) -> bool {
+        let Vector2(x, y) = *normalized_column;
+        (self.periods.x_shift == 0 && x < 0) || (self.periods.y_shift == 0 && y < 0)
+    }
+
+    pub fn load_heights_csv(&mut self, csv: &str) -> Result<(), HeightsError> {
+        self.set_heights(&parse_heights_csv(csv)?)
+    }
+
+    pub fn load_heights_matrix(&mut self, matrix: &str) -> Result<(), HeightsError> {
+        self.set_heights(&parse_heights_matrix(matrix)?)
+    }
+}
+
+#[wasm_bindgen]
+impl PeriodicLozengeTiling {
+    // flat x, y, height triples
+    #[wasm_bindgen(js_name = setHeights)]
+    pub fn set_heights_js(&mut self, heights: Int32Array) -> Result<(), JsValue> {
+        let heights = heights
+            .to_vec()
+            .chunks_exact(3)
+            .map(|chunk| [chunk[0], chunk[1], chunk[2]])
+            .collect::<Vec<_>>();
+        self.set_heights(&heights)
+            .map_err(|error| JsValue::from(error.to_string()))
+    }
+
+    #[wasm_bindgen(js_name = loadHeightsCsv)]
+    pub fn load_heights_csv_js(&mut self, csv: &str) -> Result<(), JsValue> {
+        self.load_heights_csv(csv)
+            .map_err(|error| JsValue::from(error.to_string()))
+    }
+
+    #[wasm_bindgen(js_name = loadHeightsMatrix)]
+    pub fn load_heights_matrix_js(&mut self, matrix: &str) -> Result<(), JsValue> {
+        self.load_heights_matrix(matrix)
+            .map_err(|error| JsValue::from(error.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HeightsError;
+    use crate::{vector3::Vector3, Boundary, BoundaryConditions, PeriodicLozengeTiling};
+
+    #[test]
+    fn loads_plane_partition_and_rebuilds_box_sets() {
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(0, 0, 0, 4, 4, 4);
+        lozenge_tiling.load_heights_matrix("2 1\n1 0\n").unwrap();
+
+        let mut built_tiling = PeriodicLozengeTiling::new(0, 0, 0, 4, 4, 4);
+        for position in [
+            Vector3(0, 0, 0),
+            Vector3(0, 0, 1),
+            Vector3(1, 0, 0),
+            Vector3(0, 1, 0),
+        ] {
+            built_tiling.add_box(position);
+        }
+
+        assert_eq!(lozenge_tiling.get_period_box_count(), 4);
+        assert_eq!(lozenge_tiling.to_state(), built_tiling.to_state());
+        let sorted = |mut boxes: Vec<Vector3>| {
+            boxes.sort_unstable_by_key(|Vector3(x, y, z)| (*x, *y, *z));
+            boxes
+        };
+        assert_eq!(
+            sorted(lozenge_tiling.get_addable_boxes()),
+            sorted(built_tiling.get_addable_boxes())
+        );
+        assert_eq!(
+            sorted(lozenge_tiling.get_removable_boxes()),
+            sorted(built_tiling.get_removable_boxes())
+        );
+    }
+
+    #[test]
+    fn rejects_illegal_heights_and_keeps_state() {
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(0, 0, 0, 4, 4, 4);
+        lozenge_tiling.load_heights_matrix("1").unwrap();
+
+        assert_eq!(
+            lozenge_tiling.load_heights_matrix("1 2"),
+            Err(HeightsError::Unsupported { x: 0, y: 1 })
+        );
+        assert_eq!(
+            lozenge_tiling.set_heights(&[[-1, 0, 0]]),
+            Err(HeightsError::InsideWall { x: -1, y: 0 })
+        );
+        assert!(matches!(
+            lozenge_tiling.load_heights_matrix("1 a"),
+            Err(HeightsError::Parse { line: 1, .. })
+        ));
+        assert_eq!(lozenge_tiling.get_period_box_count(), 1);
+
+        // the top box leans on the cut, the one below it floats
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(0, 0, 0, 4, 4, 4);
+        lozenge_tiling
+            .set_boundary_conditions(BoundaryConditions {
+                y: Boundary::Cut,
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(
+            lozenge_tiling.set_heights(&[[1, 0, 1]]),
+            Err(HeightsError::Unsupported { x: 1, y: 0 })
+        );
+        lozenge_tiling.set_heights(&[[0, 0, 0], [1, 0, 1]]).unwrap();
+    }
+
+    #[test]
+    fn loads_csv_written_by_state() {
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(1, 2, 3, 5, 5, 5);
+        lozenge_tiling.set_seed(8);
+        lozenge_tiling.generate_with_markov_chain(500, 0.9);
+        let state = lozenge_tiling.to_state();
+
+        let mut loaded_tiling = PeriodicLozengeTiling::new(1, 2, 3, 5, 5, 5);
+        loaded_tiling
+            .load_heights_csv(&state.heights_to_csv())
+            .unwrap();
+
+        assert_eq!(loaded_tiling.to_state(), state);
+        assert_eq!(
+            loaded_tiling.get_addable_boxes().len(),
+            lozenge_tiling.get_addable_boxes().len()
+        );
+        assert_eq!(
+            loaded_tiling.get_removable_boxes().len(),
+            lozenge_tiling.get_removable_boxes().len()
+        );
+    }
+}
@@ -0,0 +1,309 @@
+use std::collections::VecDeque;
+
+use wasm_bindgen::prelude::*;
+
+use crate::{box_move::BoxMove, PeriodicLozengeTiling};
+
+// Applied and undone moves of a tiling. A step is a single move or a group of moves
+// undone together, like the orbit of a symmetric tiling. Steps are counted from the
+// last reset, steps older than capacity are forgotten and can't be undone any more.
+#[derive(Debug, Clone)]
+pub(crate) struct MoveHistory {
+    moves: VecDeque<Vec<BoxMove>>,
+    // number of moves in `moves` that are currently applied, the rest can be redone
+    applied: usize,
+    // number of forgotten moves before the first one in `moves`
+    forgotten: usize,
+    capacity: usize,
+}
+
+impl MoveHistory {
+    pub(crate) fn new(capacity: usize) -> MoveHistory {
+        MoveHistory {
+            moves: VecDeque::new(),
+            applied: 0,
+            forgotten: 0,
+            capacity,
+        }
+    }
+
+    fn record(&mut self, moves: Vec<BoxMove>) {
+        // a new move discards the moves that could be redone
+        self.moves.truncate(self.applied);
+        if self.capacity == 0 {
+            self.forgotten += 1;
+            return;
+        }
+        if self.moves.len() == self.capacity {
+            self.moves.pop_front();
+            self.forgotten += 1;
+        }
+        self.moves.push_back(moves);
+        self.applied = self.moves.len();
+    }
+
+    fn step(&self) -> usize {
+        self.forgotten + self.applied
+    }
+
+    fn first_step(&self) -> usize {
+        self.forgotten
+    }
+
+    fn last_step(&self) -> usize {
+        self.forgotten + self.moves.len()
+    }
+}
+
+impl BoxMove {
+    fn inverse(self) -> BoxMove {
+        match self {
+            BoxMove::Add(vector) => BoxMove::Remove(vector),
+            BoxMove::Remove(vector) => BoxMove::Add(vector),
+        }
+    }
+}
+
+impl PeriodicLozengeTiling {
+    // Starts recording moves made through add_box and remove_box, keeping at most
+    // capacity of them. An existing history is discarded.
+    pub fn enable_history(&mut self, capacity: usize) {
+        self.history = Some(MoveHistory::new(capacity));
+    }
+
+    pub fn disable_history(&mut self) {
+        self.history = None;
+    }
+
+    pub fn is_history_enabled(&self) -> bool {
+        self.history.is_some()
+    }
+
+    pub(crate) fn record_move(&mut self, box_move: BoxMove) {
+        if let Some(history) = &mut self.history {
+            history.record(vec![box_move]);
+        }
+        if let Some(recording) = &mut self.recording {
+            recording.moves.push(box_move);
+        }
+    }
+
+    // Applies the moves as a single step of the history, they are still recorded one
+    // by one in a trajectory
+    pub(crate) fn apply_move_group(&mut self, moves: Vec<BoxMove>) {
+        let Some(mut history) = self.history.take() else {
+            for box_move in moves {
+                self.apply_move(box_move);
+            }
+            return;
+        };
+        for box_move in moves.iter() {
+            self.apply_move(*box_move);
+        }
+        history.record(moves);
+        self.history = Some(history);
+    }
+
+    pub(crate) fn clear_history(&mut self) {
+        if let Some(history) = &mut self.history {
+            *history = MoveHistory::new(history.capacity);
+        }
+    }
+
+    // Number of steps applied since the last reset, 0 without history
+    pub fn get_history_step(&self) -> usize {
+        self.history.as_ref().map_or(0, MoveHistory::step)
+    }
+
+    // Range of steps reachable with jump_to, both ends inclusive
+    pub fn get_history_range(&self) -> (usize, usize) {
+        self.history.as_ref().map_or((0, 0), |history| {
+            (history.first_step(), history.last_step())
+        })
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.history
+            .as_ref()
+            .is_some_and(|history| history.applied > 0)
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.history
+            .as_ref()
+            .is_some_and(|history| history.applied < history.moves.len())
+    }
+
+    // Applies the inverses of the last applied step, returns false if there is none
+    pub fn undo(&mut self) -> bool {
+        if !self.can_undo() {
+            return false;
+        }
+        // taken out so that replaying the moves doesn't record them
+        let mut history = self.history.take().unwrap();
+        history.applied -= 1;
+        for box_move in history.moves[history.applied].iter().rev() {
+            self.apply_move(box_move.inverse());
+        }
+        self.history = Some(history);
+        true
+    }
+
+    // Applies the last undone step again, returns false if there is none
+    pub fn redo(&mut self) -> bool {
+        if !self.can_redo() {
+            return false;
+        }
+        let mut history = self.history.take().unwrap();
+        for box_move in history.moves[history.applied].iter() {
+            self.apply_move(*box_move);
+        }
+        history.applied += 1;
+        self.history = Some(history);
+        true
+    }
+
+    // Undoes or redoes steps until step is reached, returns false and does nothing
+    // if step is outside of get_history_range
+    pub fn jump_to(&mut self, step: usize) -> bool {
+        let (first_step, last_step) = self.get_history_range();
+        if self.history.is_none() || step < first_step || step > last_step {
+            return false;
+        }
+        while self.get_history_step() > step {
+            self.undo();
+        }
+        while self.get_history_step() < step {
+            self.redo();
+        }
+        true
+    }
+}
+
+#[wasm_bindgen]
+impl PeriodicLozengeTiling {
+    #[wasm_bindgen(js_name = enableHistory)]
+    pub fn enable_history_js(&mut self, capacity: usize) {
+        self.enable_history(capacity);
+    }
+
+    #[wasm_bindgen(js_name = disableHistory)]
+    pub fn disable_history_js(&mut self) {
+        self.disable_history();
+    }
+
+    #[wasm_bindgen(js_name = undo)]
+    pub fn undo_js(&mut self) -> bool {
+        self.undo()
+    }
+
+    #[wasm_bindgen(js_name = redo)]
+    pub fn redo_js(&mut self) -> bool {
+        self.redo()
+    }
+
+    #[wasm_bindgen(js_name = canUndo)]
+    pub fn can_undo_js(&self) -> bool {
+        self.can_undo()
+    }
+
+    #[wasm_bindgen(js_name = canRedo)]
+    pub fn can_redo_js(&self) -> bool {
+        self.can_redo()
+    }
+
+    #[wasm_bindgen(js_name = jumpTo)]
+    pub fn jump_to_js(&mut self, step: usize) -> bool {
+        self.jump_to(step)
+    }
+
+    #[wasm_bindgen(js_name = getHistoryStep)]
+    pub fn get_history_step_js(&self) -> usize {
+        self.get_history_step()
+    }
+
+    #[wasm_bindgen(js_name = getHistoryFirstStep)]
+    pub fn get_history_first_step_js(&self) -> usize {
+        self.get_history_range().0
+    }
+
+    #[wasm_bindgen(js_name = getHistoryLastStep)]
+    pub fn get_history_last_step_js(&self) -> usize {
+        self.get_history_range().1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{PeriodicLozengeTiling, SymmetryClass};
+
+    #[test]
+    fn can_undo_and_redo_generated_moves() {
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(1, 2, 3, 5, 5, 5);
+        lozenge_tiling.set_seed(3);
+        lozenge_tiling.enable_history(1000);
+
+        let initial_voxels = lozenge_tiling.get_box_voxels();
+        lozenge_tiling.generate_with_markov_chain(50, 0.9);
+        let middle_voxels = lozenge_tiling.get_box_voxels();
+        let middle_step = lozenge_tiling.get_history_step();
+        lozenge_tiling.generate_with_markov_chain(50, 0.9);
+        let final_voxels = lozenge_tiling.get_box_voxels();
+        let final_step = lozenge_tiling.get_history_step();
+
+        assert!(lozenge_tiling.jump_to(middle_step));
+        assert_eq!(lozenge_tiling.get_box_voxels(), middle_voxels);
+        assert!(lozenge_tiling.jump_to(0));
+        assert_eq!(lozenge_tiling.get_box_voxels(), initial_voxels);
+        assert!(!lozenge_tiling.undo());
+        assert!(lozenge_tiling.jump_to(final_step));
+        assert_eq!(lozenge_tiling.get_box_voxels(), final_voxels);
+        assert!(!lozenge_tiling.redo());
+    }
+
+    #[test]
+    fn forgets_old_moves_and_discards_redo_on_new_move() {
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(0, 0, 0, 5, 5, 5);
+        lozenge_tiling.enable_history(2);
+        for _ in 0..4 {
+            lozenge_tiling.add_random_box();
+        }
+        assert_eq!(lozenge_tiling.get_history_range(), (2, 4));
+
+        assert!(lozenge_tiling.undo());
+        assert!(lozenge_tiling.undo());
+        assert!(!lozenge_tiling.undo());
+        assert!(!lozenge_tiling.jump_to(1));
+        assert_eq!(lozenge_tiling.get_period_box_count(), 2);
+
+        lozenge_tiling.add_random_box();
+        assert!(!lozenge_tiling.can_redo());
+        assert_eq!(lozenge_tiling.get_history_range(), (2, 3));
+    }
+
+    #[test]
+    fn undoes_orbits_of_symmetric_tilings_as_one_step() {
+        for symmetry in [SymmetryClass::Transpose, SymmetryClass::Cyclic] {
+            let mut lozenge_tiling = PeriodicLozengeTiling::new(0, 0, 4, 5, 5, 5);
+            lozenge_tiling.set_seed(8);
+            lozenge_tiling.set_symmetry(Some(symmetry)).unwrap();
+            lozenge_tiling.enable_history(1000);
+            lozenge_tiling.generate_with_markov_chain(300, 0.9);
+            let states = (0..20)
+                .map(|_| {
+                    lozenge_tiling.generate_with_markov_chain(1, 0.9);
+                    (lozenge_tiling.get_history_step(), lozenge_tiling.to_state())
+                })
+                .collect::<Vec<_>>();
+
+            while lozenge_tiling.undo() {
+                assert_eq!(lozenge_tiling.validate_heights(), Ok(()));
+            }
+            assert_eq!(lozenge_tiling.get_period_box_count(), 0);
+            for (step, state) in states {
+                assert!(lozenge_tiling.jump_to(step));
+                assert_eq!(lozenge_tiling.to_state(), state);
+            }
+        }
+    }
+}
@@ -0,0 +1,516 @@
+use std::{error::Error, fmt};
+
+use rustc_hash::FxHashMap;
+use wasm_bindgen::prelude::*;
+
+use crate::{dimers::Dimer, faces::FaceOrientation};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KasteleynError {
+    // sides or torus sizes the graph can't be built from
+    InvalidSize,
+    NoTilings,
+}
+
+impl fmt::Display for KasteleynError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KasteleynError::InvalidSize => write!(f, "invalid region size"),
+            KasteleynError::NoTilings => write!(f, "the region has no lozenge tilings"),
+        }
+    }
+}
+
+impl Error for KasteleynError {}
+
+#[derive(Debug, Clone)]
+struct HoneycombEdge {
+    white: usize,
+    black: usize,
+    // black triangle not wrapped around the torus
+    dimer: Dimer,
+    weight: f64,
+    // crossing the seams of the torus along p and q
+    crossings: [bool; 2],
+}
+
+// A finite piece of the honeycomb graph, or one wrapped on a torus, with triangles
+// named as in Dimer. All weights are positive, every face is a hexagon, so the
+// weighted adjacency matrix is a Kasteleyn matrix without any signs.
+#[derive(Debug, Clone)]
+pub struct HoneycombGraph {
+    whites: usize,
+    blacks: usize,
+    edges: Vec<HoneycombEdge>,
+    torus: bool,
+}
+
+impl HoneycombGraph {
+    // The hexagon of the a x b x c box with the q^volume weights of the Markov chain,
+    // the volume is a constant minus the sum of p over Z lozenges
+    pub fn hexagon(a: i32, b: i32, c: i32, q: f64) -> Result<HoneycombGraph, KasteleynError> {
+        if a < 0 || b < 0 || c < 0 || q <= 0.0 {
+            return Err(KasteleynError::InvalidSize);
+        }
+
+        // triangle centers scaled by 3 to be integers
+        let in_hexagon = |p: i32, q: i32| {
+            (-3 * c..=3 * a).contains(&p)
+                && (-3 * c..=3 * b).contains(&q)
+                && (-3 * b..=3 * a).contains(&(p - q))
+        };
+        let mut whites = FxHashMap::default();
+        let mut blacks = FxHashMap::default();
+        for p in -c..a {
+            for q in -c..b {
+                if in_hexagon(3 * p + 2, 3 * q + 1) {
+                    whites.insert([p, q], whites.len());
+                }
+                if in_hexagon(3 * p + 1, 3 * q + 2) {
+                    blacks.insert([p, q], blacks.len());
+                }
+            }
+        }
+
+        if whites.is_empty() {
+            return Err(KasteleynError::InvalidSize);
+        }
+
+        let mut edges = Vec::new();
+        for (white, white_index) in whites.iter() {
+            let [wp, wq] = *white;
+            for (black, weight) in [
+                ([wp, wq - 1], 1.0),
+                ([wp + 1, wq], 1.0),
+                ([wp, wq], q.powi(-wp)),
+            ] {
+                if let Some(black_index) = blacks.get(&black) {
+                    edges.push(HoneycombEdge {
+                        white: *white_index,
+                        black: *black_index,
+                        dimer: Dimer {
+                            white: *white,
+                            black,
+                        },
+                        weight,
+                        crossings: [false, false],
+                    });
+                }
+            }
+        }
+        edges.sort_unstable_by_key(|edge| edge.dimer);
+
+        Ok(HoneycombGraph {
+            whites: whites.len(),
+            blacks: blacks.len(),
+            edges,
+            torus: false,
+        })
+    }
+
+    // The honeycomb graph of n x m white triangles wrapped on a torus, with weights of
+    // X, Y and Z lozenges
+    pub fn torus(n: i32, m: i32, weights: [f64; 3]) -> Result<HoneycombGraph, KasteleynError> {
+        // smaller tori have double edges
+        if n < 2 || m < 2 || weights.iter().any(|weight| *weight <= 0.0) {
+            return Err(KasteleynError::InvalidSize);
+        }
+
+        let index = |p: i32, q: i32| (p.rem_euclid(n) * m + q.rem_euclid(m)) as usize;
+        let mut edges = Vec::new();
+        for p in 0..n {
+            for q in 0..m {
+                for (black, weight, crossings) in [
+                    ([p, q - 1], weights[0], [false, q == 0]),
+                    ([p + 1, q], weights[1], [p == n - 1, false]),
+                    ([p, q], weights[2], [false, false]),
+                ] {
+                    edges.push(HoneycombEdge {
+                        white: index(p, q),
+                        black: index(black[0], black[1]),
+                        dimer: Dimer {
+                            white: [p, q],
+                            black,
+                        },
+                        weight,
+                        crossings,
+                    });
+                }
+            }
+        }
+
+        Ok(HoneycombGraph {
+            whites: (n * m) as usize,
+            blacks: (n * m) as usize,
+            edges,
+            torus: true,
+        })
+    }
+
+    // Kasteleyn matrix with the edges crossing the seams of the torus negated
+    fn get_matrix(&self, twists: [bool; 2]) -> Vec<f64> {
+        let size = self.whites;
+        let mut matrix = vec![0.0; size * size];
+        for edge in self.edges.iter() {
+            let negated = (twists[0] && edge.crossings[0]) != (twists[1] && edge.crossings[1]);
+            let weight = if negated { -edge.weight } else { edge.weight };
+            matrix[edge.white * size + edge.black] += weight;
+        }
+        matrix
+    }
+
+    // Weighted count of tilings and the probability of every edge
+    fn solve(&self) -> Result<(f64, Vec<f64>), KasteleynError> {
+        if self.whites != self.blacks || self.whites == 0 {
+            return Err(KasteleynError::NoTilings);
+        }
+        let size = self.whites;
+
+        if !self.torus {
+            let lu = LuDecomposition::new(self.get_matrix([false, false]), size);
+            if lu.perturbed {
+                return Err(KasteleynError::NoTilings);
+            }
+            let inverse = lu.inverse();
+            let probabilities = self
+                .edges
+                .iter()
+                .map(|edge| edge.weight * inverse[edge.black * size + edge.white])
+                .collect();
+            // the sign of the determinant depends on the order of the triangles
+            return Ok((lu.log_abs_det.exp(), probabilities));
+        }
+
+        // The sign of a matching in a determinant only depends on the parities of its
+        // crossings of the seams, its class. Combining the four twisted determinants
+        // gives the signed count of each class, and as counts aren't negative the
+        // absolute values are the counts.
+        let twists = [[false, false], [false, true], [true, false], [true, true]];
+        let solutions = twists
+            .iter()
+            .map(|twist| {
+                let lu = LuDecomposition::new(self.get_matrix(*twist), size);
+                let inverse = lu.inverse();
+                (lu, inverse)
+            })
+            .collect::<Vec<_>>();
+        let max_log = solutions
+            .iter()
+            .map(|(lu, _)| lu.log_abs_det)
+            .fold(f64::NEG_INFINITY, f64::max);
+        // determinants relative to the largest one
+        let determinants = solutions
+            .iter()
+            .map(|(lu, _)| lu.sign * (lu.log_abs_det - max_log).exp())
+            .collect::<Vec<_>>();
+        let get_class_sum = |values: &[f64], class: [bool; 2]| {
+            twists
+                .iter()
+                .zip(values)
+                .map(|(twist, value)| {
+                    let odd = (twist[0] && class[0]) != (twist[1] && class[1]);
+                    if odd {
+                        -value
+                    } else {
+                        *value
+                    }
+                })
+                .sum::<f64>()
+                / 4.0
+        };
+
+        let class_sums = twists.map(|class| get_class_sum(&determinants, class));
+        let largest = class_sums.iter().fold(0.0f64, |a, b| a.max(b.abs()));
+        if largest == 0.0 {
+            return Err(KasteleynError::NoTilings);
+        }
+        let signs = class_sums.map(|sum| match sum.abs() > 1e-9 * largest {
+            true => sum.signum(),
+            false => 0.0,
+        });
+        let total = class_sums
+            .iter()
+            .zip(signs)
+            .map(|(sum, sign)| sum * sign)
+            .sum::<f64>();
+
+        let probabilities = self
+            .edges
+            .iter()
+            .map(|edge| {
+                // determinant times inverse, the adjugate, with the edge's sign
+                let terms = solutions
+                    .iter()
+                    .zip(twists)
+                    .zip(determinants.iter())
+                    .map(|(((_, inverse), twist), determinant)| {
+                        let negated =
+                            (twist[0] && edge.crossings[0]) != (twist[1] && edge.crossings[1]);
+                        let weight = if negated { -edge.weight } else { edge.weight };
+                        determinant * weight * inverse[edge.black * size + edge.white]
+                    })
+                    .collect::<Vec<_>>();
+                twists
+                    .iter()
+                    .zip(signs)
+                    .map(|(class, sign)| sign * get_class_sum(&terms, *class))
+                    .sum::<f64>()
+                    / total
+            })
+            .collect();
+
+        Ok((total * max_log.exp(), probabilities))
+    }
+
+    // weighted number of tilings
+    pub fn get_partition_function(&self) -> Result<f64, KasteleynError> {
+        Ok(self.solve()?.0)
+    }
+
+    // probability of every lozenge, ordered by dimer
+    pub fn get_probabilities(&self) -> Result<Vec<(Dimer, f64)>, KasteleynError> {
+        let (_, probabilities) = self.solve()?;
+        Ok(self
+            .edges
+            .iter()
+            .map(|edge| edge.dimer)
+            .zip(probabilities)
+            .collect())
+    }
+}
+
+pub fn probabilities_to_csv(probabilities: &[(Dimer, f64)]) -> String {
+    let mut csv = String::from("white_p,white_q,black_p,black_q,orientation,probability\n");
+    for (dimer, probability) in probabilities {
+        let orientation = match dimer.orientation() {
+            Some(FaceOrientation::X) => "x",
+            Some(FaceOrientation::Y) => "y",
+            Some(FaceOrientation::Z) => "z",
+            None => "",
+        };
+        csv.push_str(&format!(
+            "{},{},{},{},{},{}\n",
+            dimer.white[0],
+            dimer.white[1],
+            dimer.black[0],
+            dimer.black[1],
+            orientation,
+            probability
+        ));
+    }
+    csv
+}
+
+// LU decomposition with partial pivoting of a dense row major matrix
+struct LuDecomposition {
+    size: usize,
+    lu: Vec<f64>,
+    // row of the matrix moved to each row
+    permutation: Vec<usize>,
+    sign: f64,
+    log_abs_det: f64,
+    // Zero pivots of singular matrices are replaced by tiny ones. The determinant
+    // times the inverse, the adjugate, stays accurate as it's continuous.
+    perturbed: bool,
+}
+
+impl LuDecomposition {
+    fn new(mut lu: Vec<f64>, size: usize) -> LuDecomposition {
+        let scale = lu
+            .iter()
+            .fold(0.0f64, |a, b| a.max(b.abs()))
+            .max(f64::MIN_POSITIVE);
+        let mut permutation = (0..size).collect::<Vec<_>>();
+        let mut sign = 1.0;
+        let mut log_abs_det = 0.0;
+        let mut perturbed = false;
+
+        for k in 0..size {
+            let pivot_row = (k..size)
+                .max_by(|i, j| lu[i * size + k].abs().total_cmp(&lu[j * size + k].abs()))
+                .unwrap();
+            if pivot_row != k {
+                for j in 0..size {
+                    lu.swap(k * size + j, pivot_row * size + j);
+                }
+                permutation.swap(k, pivot_row);
+                sign = -sign;
+            }
+            if lu[k * size + k] == 0.0 {
+                lu[k * size + k] = f64::EPSILON * scale;
+                perturbed = true;
+            }
+
+            let pivot = lu[k * size + k];
+            sign *= pivot.signum();
+            log_abs_det += pivot.abs().ln();
+            for i in k + 1..size {
+                let factor = lu[i * size + k] / pivot;
+                lu[i * size + k] = factor;
+                if factor != 0.0 {
+                    for j in k + 1..size {
+                        lu[i * size + j] -= factor * lu[k * size + j];
+                    }
+                }
+            }
+        }
+
+        LuDecomposition {
+            size,
+            lu,
+            permutation,
+            sign,
+            log_abs_det,
+            perturbed,
+        }
+    }
+
+    fn inverse(&self) -> Vec<f64> {
+        let size = self.size;
+        let mut inverse = vec![0.0; size * size];
+        let mut column = vec![0.0; size];
+
+        for c in 0..size {
+            for (i, value) in column.iter_mut().enumerate() {
+                *value = if self.permutation[i] == c { 1.0 } else { 0.0 };
+            }
+            for i in 0..size {
+                let sum = (0..i)
+                    .map(|j| self.lu[i * size + j] * column[j])
+                    .sum::<f64>();
+                column[i] -= sum;
+            }
+            for i in (0..size).rev() {
+                let sum = (i + 1..size)
+                    .map(|j| self.lu[i * size + j] * column[j])
+                    .sum::<f64>();
+                column[i] = (column[i] - sum) / self.lu[i * size + i];
+            }
+            for i in 0..size {
+                inverse[i * size + c] = column[i];
+            }
+        }
+
+        inverse
+    }
+}
+
+// CSV of the exact lozenge probabilities of a hexagon
+#[wasm_bindgen(js_name = getHexagonProbabilities)]
+pub fn get_hexagon_probabilities_js(a: i32, b: i32, c: i32, q: f64) -> Result<String, JsValue> {
+    HoneycombGraph::hexagon(a, b, c, q)
+        .and_then(|graph| graph.get_probabilities())
+        .map(|probabilities| probabilities_to_csv(&probabilities))
+        .map_err(|error| JsValue::from(error.to_string()))
+}
+
+// CSV of the exact lozenge probabilities of a torus
+#[wasm_bindgen(js_name = getTorusProbabilities)]
+pub fn get_torus_probabilities_js(
+    n: i32,
+    m: i32,
+    x_weight: f64,
+    y_weight: f64,
+    z_weight: f64,
+) -> Result<String, JsValue> {
+    HoneycombGraph::torus(n, m, [x_weight, y_weight, z_weight])
+        .and_then(|graph| graph.get_probabilities())
+        .map(|probabilities| probabilities_to_csv(&probabilities))
+        .map_err(|error| JsValue::from(error.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HoneycombGraph, KasteleynError};
+
+    // weighted count of matchings and of the matchings with each edge
+    fn enumerate(graph: &HoneycombGraph) -> (f64, Vec<f64>) {
+        fn extend(
+            graph: &HoneycombGraph,
+            white: usize,
+            used: &mut Vec<bool>,
+            chosen: &mut Vec<usize>,
+            counts: &mut (f64, Vec<f64>),
+        ) {
+            if white == graph.whites {
+                let weight = chosen
+                    .iter()
+                    .map(|edge| graph.edges[*edge].weight)
+                    .product::<f64>();
+                counts.0 += weight;
+                for edge in chosen.iter() {
+                    counts.1[*edge] += weight;
+                }
+                return;
+            }
+            for (index, edge) in graph.edges.iter().enumerate() {
+                if edge.white == white && !used[edge.black] {
+                    used[edge.black] = true;
+                    chosen.push(index);
+                    extend(graph, white + 1, used, chosen, counts);
+                    chosen.pop();
+                    used[edge.black] = false;
+                }
+            }
+        }
+
+        let mut counts = (0.0, vec![0.0; graph.edges.len()]);
+        extend(
+            graph,
+            0,
+            &mut vec![false; graph.blacks],
+            &mut Vec::new(),
+            &mut counts,
+        );
+        let (total, edge_counts) = counts;
+        (
+            total,
+            edge_counts.iter().map(|count| count / total).collect(),
+        )
+    }
+
+    fn assert_matches_enumeration(graph: &HoneycombGraph) {
+        let (total, probabilities) = enumerate(graph);
+        let exact_total = graph.get_partition_function().unwrap();
+        assert!((exact_total - total).abs() < 1e-9 * total);
+        for ((_, exact), expected) in graph.get_probabilities().unwrap().iter().zip(probabilities) {
+            assert!((exact - expected).abs() < 1e-9, "{} {}", exact, expected);
+        }
+    }
+
+    #[test]
+    fn hexagon_counts_plane_partitions() {
+        let graph = HoneycombGraph::hexagon(3, 3, 3, 1.0).unwrap();
+        assert!((graph.get_partition_function().unwrap() - 980.0).abs() < 1e-6);
+
+        assert_matches_enumeration(&HoneycombGraph::hexagon(2, 2, 2, 1.0).unwrap());
+        assert_matches_enumeration(&HoneycombGraph::hexagon(2, 3, 2, 0.7).unwrap());
+    }
+
+    #[test]
+    fn torus_matches_enumeration() {
+        for (n, m) in [(2, 2), (2, 3), (3, 3)] {
+            assert_matches_enumeration(&HoneycombGraph::torus(n, m, [1.0, 2.0, 3.0]).unwrap());
+            assert_matches_enumeration(&HoneycombGraph::torus(n, m, [1.0, 1.0, 1.0]).unwrap());
+        }
+    }
+
+    #[test]
+    fn rejects_empty_regions() {
+        assert_eq!(
+            HoneycombGraph::torus(1, 3, [1.0; 3]).unwrap_err(),
+            KasteleynError::InvalidSize
+        );
+        assert_eq!(
+            HoneycombGraph::hexagon(2, 0, 0, 1.0).unwrap_err(),
+            KasteleynError::InvalidSize
+        );
+        // a parallelogram has a single tiling
+        let graph = HoneycombGraph::hexagon(2, 0, 3, 1.0).unwrap();
+        assert!((graph.get_partition_function().unwrap() - 1.0).abs() < 1e-12);
+        let probabilities = graph.get_probabilities().unwrap();
+        assert!(probabilities.iter().all(|(_, probability)| {
+            probability.abs() < 1e-12 || (probability - 1.0).abs() < 1e-12
+        }));
+    }
+}
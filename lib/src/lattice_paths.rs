@@ -0,0 +1,291 @@
+use std::{error::Error, fmt};
+
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    height_window::{HeightWindow, WALL_COLUMN_HEIGHT},
+    heights::HeightsError,
+    PeriodicLozengeTiling,
+};
+
+// The level lines of the heights over a window of columns, one non-intersecting path
+// per level through the X and Y lozenges. The path of level k separates the columns
+// with tops at k or above from the lower ones. It starts at (x_start, y_start +
+// y_size), takes x_size unit steps along +x and y_size along -y, and ends at
+// (x_start + x_size, y_start). Vertices are projected along (1, 1, 1) like the
+// lozenge corners, (x, y) of level k becomes (x - k, y - k).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LatticePaths {
+    pub x_start: i32,
+    pub y_start: i32,
+    pub x_size: usize,
+    pub y_size: usize,
+    // height of the columns below all paths
+    pub base_height: i32,
+    // the path of level base_height + 1 first
+    pub paths: Vec<Vec<[i32; 2]>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LatticePathError {
+    // a path not going from the start to the end corner of the window
+    WrongEnds { level: i32 },
+    // a step that isn't +x or -y
+    InvalidStep { level: i32, index: usize },
+    // a path above the one of the level below it
+    Intersecting { level: i32 },
+    Heights(HeightsError),
+}
+
+impl fmt::Display for LatticePathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LatticePathError::WrongEnds { level } => write!(
+                f,
+                "path of level {} doesn't join the corners of the window",
+                level
+            ),
+            LatticePathError::InvalidStep { level, index } => write!(
+                f,
+                "step {} of the path of level {} isn't +x or -y",
+                index, level
+            ),
+            LatticePathError::Intersecting { level } => {
+                write!(f, "path of level {} crosses the path below it", level)
+            }
+            LatticePathError::Heights(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for LatticePathError {}
+
+impl From<HeightsError> for LatticePathError {
+    fn from(error: HeightsError) -> Self {
+        LatticePathError::Heights(error)
+    }
+}
+
+impl HeightWindow {
+    pub fn to_lattice_paths(&self) -> LatticePaths {
+        let heights = self
+            .heights
+            .iter()
+            .filter(|height| **height != WALL_COLUMN_HEIGHT);
+        let base_height = heights.clone().min().copied().unwrap_or(-1);
+        let top_height = heights.max().copied().unwrap_or(-1);
+
+        let paths = (base_height + 1..=top_height)
+            .map(|level| {
+                // columns at the level or above in each row, they're a prefix
+                let counts = (0..self.x_size).map(|row| {
+                    self.heights[row * self.y_size..(row + 1) * self.y_size]
+                        .iter()
+                        .filter(|height| **height >= level)
+                        .count() as i32
+                });
+
+                let (mut x, mut y) = (self.x_start, self.y_start + self.y_size as i32);
+                let mut path = vec![[x - level, y - level]];
+                for count in counts.chain([0]) {
+                    while y > self.y_start + count {
+                        y -= 1;
+                        path.push([x - level, y - level]);
+                    }
+                    if x < self.x_start + self.x_size as i32 {
+                        x += 1;
+                        path.push([x - level, y - level]);
+                    }
+                }
+                path
+            })
+            .collect();
+
+        LatticePaths {
+            x_start: self.x_start,
+            y_start: self.y_start,
+            x_size: self.x_size,
+            y_size: self.y_size,
+            base_height,
+            paths,
+        }
+    }
+}
+
+impl LatticePaths {
+    pub fn to_height_window(&self) -> Result<HeightWindow, LatticePathError> {
+        let mut heights = vec![self.base_height; self.x_size * self.y_size];
+        let mut previous_counts: Option<Vec<i32>> = None;
+
+        for (index, path) in self.paths.iter().enumerate() {
+            let level = self.base_height + 1 + index as i32;
+            let unprojected = path
+                .iter()
+                .map(|[p, q]| (p + level, q + level))
+                .collect::<Vec<_>>();
+            let start = (self.x_start, self.y_start + self.y_size as i32);
+            let end = (self.x_start + self.x_size as i32, self.y_start);
+            if unprojected.first() != Some(&start) || unprojected.last() != Some(&end) {
+                return Err(LatticePathError::WrongEnds { level });
+            }
+
+            // the y of each +x step is the count of columns of its row at the level
+            let mut counts = Vec::with_capacity(self.x_size);
+            for (step, pair) in unprojected.windows(2).enumerate() {
+                let [(x0, y0), (x1, y1)] = [pair[0], pair[1]];
+                match (x1 - x0, y1 - y0) {
+                    (1, 0) => counts.push(y0 - self.y_start),
+                    (0, -1) => {}
+                    _ => return Err(LatticePathError::InvalidStep { level, index: step }),
+                }
+            }
+
+            if let Some(previous_counts) = previous_counts {
+                if counts.iter().zip(previous_counts).any(|(a, b)| *a > b) {
+                    return Err(LatticePathError::Intersecting { level });
+                }
+            }
+            for (row, count) in counts.iter().enumerate() {
+                for column in 0..*count as usize {
+                    heights[row * self.y_size + column] = level;
+                }
+            }
+            previous_counts = Some(counts);
+        }
+
+        Ok(HeightWindow {
+            x_start: self.x_start,
+            y_start: self.y_start,
+            x_size: self.x_size,
+            y_size: self.y_size,
+            heights,
+        })
+    }
+}
+
+impl PeriodicLozengeTiling {
+    pub fn get_lattice_paths(
+        &self,
+        x_start: i32,
+        y_start: i32,
+        x_size: usize,
+        y_size: usize,
+    ) -> LatticePaths {
+        self.get_height_window(x_start, y_start, x_size, y_size)
+            .to_lattice_paths()
+    }
+
+    // Sets the heights of the columns in the window of the paths, the other columns
+    // are kept. The tiling is left unchanged on errors.
+    pub fn set_lattice_paths(&mut self, paths: &LatticePaths) -> Result<(), LatticePathError> {
+        self.set_height_window(&paths.to_height_window()?)?;
+        Ok(())
+    }
+}
+
+#[wasm_bindgen]
+impl PeriodicLozengeTiling {
+    // JSON of the paths over a window of columns
+    #[wasm_bindgen(js_name = getLatticePaths)]
+    pub fn get_lattice_paths_js(
+        &self,
+        x_start: i32,
+        y_start: i32,
+        x_size: usize,
+        y_size: usize,
+    ) -> String {
+        let paths = self.get_lattice_paths(x_start, y_start, x_size, y_size);
+        serde_json::to_string(&paths).unwrap()
+    }
+
+    #[wasm_bindgen(js_name = setLatticePaths)]
+    pub fn set_lattice_paths_js(&mut self, paths: &str) -> Result<(), JsValue> {
+        let paths: LatticePaths =
+            serde_json::from_str(paths).map_err(|error| JsValue::from(error.to_string()))?;
+        self.set_lattice_paths(&paths)
+            .map_err(|error| JsValue::from(error.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rustc_hash::FxHashSet;
+
+    use super::LatticePathError;
+    use crate::PeriodicLozengeTiling;
+
+    #[test]
+    fn plane_partition_paths_do_not_intersect() {
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(0, 0, 4, 4, 4, 4);
+        lozenge_tiling
+            .load_heights_matrix("4 3 1\n3 1 1\n2")
+            .unwrap();
+
+        let paths = lozenge_tiling.get_lattice_paths(0, 0, 3, 3);
+
+        assert_eq!(paths.base_height, -1);
+        assert_eq!(paths.paths.len(), 4);
+        // the level 0 path around the columns with at least one box
+        assert_eq!(
+            paths.paths[0],
+            [[0, 3], [1, 3], [2, 3], [2, 2], [2, 1], [3, 1], [3, 0]]
+        );
+        let mut vertices = FxHashSet::default();
+        for path in paths.paths.iter() {
+            assert_eq!(path.len(), 3 + 3 + 1);
+            for vertex in path {
+                assert!(vertices.insert(*vertex));
+            }
+        }
+    }
+
+    #[test]
+    fn paths_round_trip() {
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(1, 2, 3, 8, 8, 8);
+        lozenge_tiling.set_seed(2);
+        lozenge_tiling.generate_with_markov_chain(300, 0.9);
+        let paths = lozenge_tiling.get_lattice_paths(-8, -8, 16, 16);
+
+        let mut loaded_tiling = PeriodicLozengeTiling::new(1, 2, 3, 8, 8, 8);
+        loaded_tiling.set_lattice_paths(&paths).unwrap();
+
+        assert_eq!(loaded_tiling.to_state(), lozenge_tiling.to_state());
+        assert_eq!(loaded_tiling.get_lattice_paths(-8, -8, 16, 16), paths);
+    }
+
+    #[test]
+    fn rejects_crossing_paths() {
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(0, 0, 4, 4, 4, 4);
+        lozenge_tiling.load_heights_matrix("2 1\n1").unwrap();
+        let paths = lozenge_tiling.get_lattice_paths(0, 0, 2, 2);
+
+        let mut swapped = paths.clone();
+        swapped.paths.swap(0, 1);
+        // moved to their new levels, so the ends are right
+        for (index, shift) in [(0, 1), (1, -1)] {
+            for vertex in swapped.paths[index].iter_mut() {
+                vertex[0] += shift;
+                vertex[1] += shift;
+            }
+        }
+        assert_eq!(
+            swapped.to_height_window(),
+            Err(LatticePathError::Intersecting { level: 1 })
+        );
+
+        let mut cut = paths.clone();
+        cut.paths[1].pop();
+        assert_eq!(
+            cut.to_height_window(),
+            Err(LatticePathError::WrongEnds { level: 1 })
+        );
+
+        let mut diagonal = paths;
+        diagonal.paths[0].remove(1);
+        assert!(matches!(
+            diagonal.to_height_window(),
+            Err(LatticePathError::InvalidStep { level: 0, .. })
+        ));
+    }
+}
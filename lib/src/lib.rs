@@ -1,33 +1,112 @@
+#[macro_use]
+mod time;
+
+mod annealing;
+mod boundary;
 mod box_map;
+mod box_move;
+mod checkerboard;
+mod cylindric;
+mod dimers;
+mod faces;
+mod gltf;
+mod height_window;
+mod heights;
+mod history;
+mod kasteleyn;
+mod lattice_paths;
+mod mesh;
+mod obstacles;
+mod particles;
+mod progress;
+mod raster;
+mod state;
+mod stats;
+mod svg;
+#[cfg(not(target_arch = "wasm32"))]
+mod sweep;
+mod symmetry;
+mod tikz;
+mod torus;
+mod trajectory;
 mod vector2;
 mod vector3;
 mod vector3_set;
 
-#[macro_use]
-mod time;
-
 use crate::{vector2::Vector2, vector3_set::Vector3Set};
 
+pub use annealing::{
+    energy_log_to_csv, AnnealingError, AnnealingSchedule, Cooling, Energy, EnergySample,
+    InteractionEnergy,
+};
+pub use boundary::{Boundary, BoundaryConditions, BoundaryError};
 use box_map::BoxMap;
-use rand::Rng;
-use vector3::Vector3;
+pub use box_move::BoxMove;
+pub use cylindric::{CylindricError, CylindricPartition};
+pub use dimers::{Dimer, DimerCovering, DimerError};
+pub use faces::{Face, FaceKind, FaceOrientation};
+pub use height_window::{HeightWindow, WALL_COLUMN_HEIGHT};
+pub use heights::{parse_heights_csv, parse_heights_matrix, HeightsError};
+use history::MoveHistory;
+pub use kasteleyn::{probabilities_to_csv, HoneycombGraph, KasteleynError};
+pub use lattice_paths::{LatticePathError, LatticePaths};
+pub use mesh::{Mesh, MeshFace};
+use obstacles::ObstacleLayer;
+pub use obstacles::Obstacles;
+pub use particles::{ParticleDensity, ParticleSlice};
+pub use progress::GenerationProgress;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+pub use raster::{
+    ColorScheme, RasterError, RasterImage, RasterOptions, Rgb, MAX_SUPERSAMPLED_PIXELS,
+    MAX_SUPERSAMPLING,
+};
+use rustc_hash::FxHashSet;
+use serde::{Deserialize, Serialize};
+pub use state::{StateError, TilingState};
+pub use stats::VolumeStatistics;
+pub use svg::SvgOptions;
+#[cfg(not(target_arch = "wasm32"))]
+pub use sweep::{
+    run_sweep, SweepKey, SweepPoint, SweepRow, SweepSpec, SweepValues, SWEEP_CSV_HEADER,
+};
+pub use symmetry::{SymmetryClass, SymmetryError};
+pub use tikz::{TikzOptions, TikzStyle};
+#[cfg(feature = "tracing")]
+pub use time::{init_tracing, SpanTiming, TimingLayer, Timings};
+pub use torus::{
+    lozenge_entropy, surface_tension, ToroidalLozengeTiling, TorusError, TorusPeriods,
+};
+pub use trajectory::{Trajectory, TrajectoryReplay};
+pub use vector3::Vector3;
 use wasm_bindgen::prelude::*;
 use web_sys::console;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct DrawDistance {
     pub x: i32,
     pub y: i32,
     pub z: i32,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct LozengeTilingPeriods {
     pub x_shift: i32,
     pub y_shift: i32,
     pub z_height: i32,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Algorithm {
+    #[serde(alias = "adding")]
+    AddingOnly,
+    #[serde(alias = "markov")]
+    MarkovChain,
+    // iterations are sweeps over all columns
+    #[serde(alias = "parallel")]
+    ParallelMarkovChain,
+}
+
 struct VoxelBoundaries {
     x_min: i32,
     x_max: i32,
@@ -37,7 +116,7 @@ struct VoxelBoundaries {
     z_max: i32,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[wasm_bindgen]
 pub struct PeriodicLozengeTiling {
     data: BoxMap,
@@ -45,8 +124,17 @@ pub struct PeriodicLozengeTiling {
     periods: LozengeTilingPeriods,
     addable_boxes: Vector3Set,
     removable_boxes: Vector3Set,
+    rng: StdRng,
+    history: Option<MoveHistory>,
+    recording: Option<Trajectory>,
+    symmetry: Option<SymmetryClass>,
+    boundary_conditions: BoundaryConditions,
+    obstacles: ObstacleLayer,
 }
 
+#[cfg(all(feature = "parallel", target_arch = "wasm32"))]
+pub use wasm_bindgen_rayon::init_thread_pool;
+
 impl PeriodicLozengeTiling {
     pub fn new(
         periods_x_shift: i32,
@@ -70,6 +158,12 @@ impl PeriodicLozengeTiling {
             },
             addable_boxes: Vector3Set::new(Some(vec![Vector3(0, 0, 0)])),
             removable_boxes: Vector3Set::new(None),
+            rng: StdRng::from_entropy(),
+            history: None,
+            recording: None,
+            symmetry: None,
+            boundary_conditions: BoundaryConditions::default(),
+            obstacles: ObstacleLayer::default(),
         }
     }
 
@@ -77,12 +171,21 @@ impl PeriodicLozengeTiling {
         self.data.clear();
         self.addable_boxes.reset();
         self.removable_boxes.reset();
+        self.clear_history();
+        if self.symmetry.is_some() {
+            self.reset_to_symmetric_start();
+        } else if self.has_obstacles() {
+            self.rebuild_box_sets();
+        }
     }
 
     pub fn set_periods(&mut self, x_shift: i32, y_shift: i32, z_height: i32) {
         self.periods.x_shift = x_shift;
         self.periods.y_shift = y_shift;
         self.periods.z_height = z_height;
+        self.symmetry = None;
+        self.boundary_conditions = BoundaryConditions::default();
+        self.obstacles = ObstacleLayer::default();
         self.reset();
     }
 
@@ -92,6 +195,18 @@ impl PeriodicLozengeTiling {
         self.draw_distance.z = z;
     }
 
+    pub fn get_periods(&self) -> &LozengeTilingPeriods {
+        &self.periods
+    }
+
+    pub fn get_draw_distance(&self) -> &DrawDistance {
+        &self.draw_distance
+    }
+
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
     //normalize(x,y,z): (x,y,z) - (y div yShift)(xShift,yShift,-zHeight)
     fn normalize3(&self, vector: &Vector3) -> Vector3 {
         let LozengeTilingPeriods {
@@ -143,8 +258,11 @@ impl PeriodicLozengeTiling {
     }
 
     fn get_height(&self, normalized_vector: &Vector2) -> i32 {
-        let saved_height = self.data.get(normalized_vector);
+        self.data.get(normalized_vector) + self.get_wall_offset(normalized_vector)
+    }
 
+    // Height of the wall below normalized columns left of the fundamental domain
+    fn get_wall_offset(&self, normalized_vector: &Vector2) -> i32 {
         let Vector2(nx, ny) = normalized_vector;
 
         let LozengeTilingPeriods {
@@ -155,12 +273,12 @@ impl PeriodicLozengeTiling {
 
         match y_shift >= x_shift {
             true => match *nx >= 0 {
-                true => *saved_height,
-                false => saved_height + z_height * ((-nx - 1) / x_shift + 1),
+                true => 0,
+                false => z_height * ((-nx - 1) / x_shift + 1),
             },
             false => match *ny >= 0 {
-                true => *saved_height,
-                false => saved_height + z_height * ((-ny - 1) / y_shift + 1),
+                true => 0,
+                false => z_height * ((-ny - 1) / y_shift + 1),
             },
         }
     }
@@ -182,6 +300,10 @@ impl PeriodicLozengeTiling {
 
         let Vector3(x, y, z) = vector;
 
+        if self.is_obstacle(vector) {
+            return true;
+        }
+
         if x_shift == 0 && y_shift == 0 {
             return x < &0 || y < &0 || z < &0;
         }
@@ -209,7 +331,12 @@ impl PeriodicLozengeTiling {
         self.is_wall(vector) || self.is_box(vector)
     }
 
-    fn can_add_box(&self, vector: &Vector3) -> bool {
+    // Boxes on a cut need no support from across it
+    fn is_support(&self, vector: &Vector3) -> bool {
+        self.is_wall_or_box(vector) || self.is_outside_boundary(vector)
+    }
+
+    pub fn can_add_box(&self, vector: &Vector3) -> bool {
         let LozengeTilingPeriods {
             x_shift,
             y_shift,
@@ -224,13 +351,15 @@ impl PeriodicLozengeTiling {
         }
 
         !self.is_wall_or_box(vector) && // no box in tested position
+        !self.is_outside_boundary(vector) && // inside the boundary cuts
+        !self.is_forbidden(vector) && // not in a hole or on a frozen column
         // looking from +y
-        self.is_wall_or_box(&Vector3(x - 1, *y, *z)) && // box or wall to left
-        self.is_wall_or_box(&Vector3(*x, y - 1, *z)) && // box or wall behind
-        self.is_wall_or_box(&Vector3(*x, *y, z - 1)) // box or wall below
+        self.is_support(&Vector3(x - 1, *y, *z)) && // box or wall to left
+        self.is_support(&Vector3(*x, y - 1, *z)) && // box or wall behind
+        self.is_support(&Vector3(*x, *y, z - 1)) // box or wall below
     }
 
-    fn can_remove_box(&self, vector: &Vector3) -> bool {
+    pub fn can_remove_box(&self, vector: &Vector3) -> bool {
         let Vector3(x, y, z) = vector;
 
         self.is_box(vector) && // box in tested position
@@ -241,7 +370,7 @@ impl PeriodicLozengeTiling {
         !self.is_box(&Vector3(*x, *y, z + 1)) // no box above
     }
 
-    fn add_box(&mut self, vector: Vector3) {
+    pub fn add_box(&mut self, vector: Vector3) {
         if self.can_add_box(&vector) {
             let Vector3(x, y, z) = vector;
             let Vector3(nx, ny, nz) = self.normalize3(&vector);
@@ -251,6 +380,7 @@ impl PeriodicLozengeTiling {
             // just added box
             self.remove_addable_box(&Vector3(nx, ny, nz)); // can't be added again
             self.add_removable_box(Vector3(nx, ny, nz)); // can be removed
+            self.record_move(BoxMove::Add(Vector3(nx, ny, nz)));
 
             // TODO fn for plus and minus 1 vectors
             // update addable boxes
@@ -279,7 +409,7 @@ impl PeriodicLozengeTiling {
         }
     }
 
-    fn remove_box(&mut self, vector: Vector3) {
+    pub fn remove_box(&mut self, vector: Vector3) {
         if self.can_remove_box(&vector) {
             let Vector3(x, y, z) = vector;
             let Vector3(nx, ny, nz) = self.normalize3(&vector);
@@ -289,6 +419,7 @@ impl PeriodicLozengeTiling {
             // just removed box
             self.remove_removable_box(&Vector3(nx, ny, nz)); // can't be removed again
             self.add_addable_box(Vector3(nx, ny, nz)); // can be added
+            self.record_move(BoxMove::Remove(Vector3(nx, ny, nz)));
 
             // update addable boxes
             let addable_boxes = [
@@ -316,12 +447,53 @@ impl PeriodicLozengeTiling {
         }
     }
 
+    fn apply_move(&mut self, box_move: BoxMove) {
+        match box_move {
+            BoxMove::Add(vector) => self.add_box(vector),
+            BoxMove::Remove(vector) => self.remove_box(vector),
+        }
+    }
+
+    // Recomputes addable and removable boxes from data alone. A box can only be
+    // added on top of a column, and only next to existing boxes or in the corner.
+    fn rebuild_box_sets(&mut self) {
+        self.addable_boxes.clear();
+        self.removable_boxes.clear();
+
+        let mut columns = FxHashSet::default();
+        columns.insert(Vector2(0, 0));
+        for Vector2(x, y) in self.get_obstacle_columns() {
+            columns.insert(self.normalize2(&Vector2(x + 1, y)));
+            columns.insert(self.normalize2(&Vector2(x, y + 1)));
+        }
+        for (Vector2(x, y), _) in self.data.iter() {
+            columns.insert(Vector2(*x, *y));
+            columns.insert(self.normalize2(&Vector2(x + 1, *y)));
+            columns.insert(self.normalize2(&Vector2(*x, y + 1)));
+        }
+
+        for column in columns {
+            let Vector2(x, y) = column;
+            let height = self.get_height(&column);
+
+            let top_box = Vector3(x, y, height);
+            if self.can_remove_box(&top_box) {
+                self.add_removable_box(top_box);
+            }
+
+            let box_above = Vector3(x, y, height + 1);
+            if self.can_add_box(&box_above) {
+                self.add_addable_box(box_above);
+            }
+        }
+    }
+
     fn get_random_addable_box(&mut self) -> Option<Vector3> {
-        self.addable_boxes.get_random()
+        self.addable_boxes.get_random(&mut self.rng)
     }
 
     fn get_random_removable_box(&mut self) -> Option<Vector3> {
-        self.removable_boxes.get_random()
+        self.removable_boxes.get_random(&mut self.rng)
     }
 
     pub fn add_random_box(&mut self) {
@@ -332,6 +504,14 @@ impl PeriodicLozengeTiling {
         }
     }
 
+    pub fn get_addable_boxes(&self) -> Vec<Vector3> {
+        self.addable_boxes.iter().copied().collect()
+    }
+
+    pub fn get_removable_boxes(&self) -> Vec<Vector3> {
+        self.removable_boxes.iter().copied().collect()
+    }
+
     fn addable_boxes_count(&self) -> usize {
         self.addable_boxes.len()
     }
@@ -414,10 +594,12 @@ impl PeriodicLozengeTiling {
         voxels
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
     pub fn get_wall_voxels(&self) -> Vec<Vector3> {
         self.get_voxels(PeriodicLozengeTiling::is_wall, false)
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
     pub fn get_box_voxels(&self) -> Vec<Vector3> {
         self.get_voxels(PeriodicLozengeTiling::is_box, true)
     }
@@ -426,21 +608,40 @@ impl PeriodicLozengeTiling {
         self.data.box_count()
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
     pub fn generate_by_adding_only(&mut self, iterations: i32) {
+        if self.symmetry.is_some() {
+            return self.generate_by_adding_orbits_only(iterations);
+        }
         for _ in 0..iterations {
             self.add_random_box();
         }
     }
 
-    pub fn generate_with_markov_chain(&mut self, iterations: i32, q: f32) {
-        let mut rng = rand::thread_rng();
+    pub fn generate(&mut self, algorithm: Algorithm, iterations: i32, q: f32) {
+        match algorithm {
+            Algorithm::AddingOnly => self.generate_by_adding_only(iterations),
+            Algorithm::MarkovChain => self.generate_with_markov_chain(iterations, q),
+            Algorithm::ParallelMarkovChain => {
+                self.generate_with_parallel_markov_chain(iterations, q)
+            }
+        }
+    }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub fn generate_with_markov_chain(&mut self, iterations: i32, q: f32) {
+        if self.symmetry.is_some() {
+            return self.generate_with_symmetric_markov_chain(iterations, q);
+        }
+        if self.has_mirror_cut_boundary() {
+            return self.generate_with_mirror_cut_markov_chain(iterations, q);
+        }
         for _ in 0..iterations {
-            let rn1 = rng.gen::<f32>();
-            let rn2 = rng.gen::<f32>();
+            let rn1 = self.rng.gen::<f32>();
+            let rn2 = self.rng.gen::<f32>();
 
-            let num1 = (-1.0 * (1.0 - rn1).ln()) / self.addable_boxes_count() as f32 / q;
-            let num2 = (-1.0 * (1.0 - rn2).ln()) / self.removable_boxes_count() as f32;
+            let num1 = -(1.0 - rn1).ln() / self.addable_boxes_count() as f32 / q;
+            let num2 = -(1.0 - rn2).ln() / self.removable_boxes_count() as f32;
 
             if num1 < num2 {
                 self.add_random_box();
@@ -492,13 +693,20 @@ impl PeriodicLozengeTiling {
         self.set_draw_distance(x, y, z);
     }
 
+    // symmetric tilings move a whole orbit
     #[wasm_bindgen(js_name = addRandomBox)]
     pub fn add_random_box_js(&mut self) {
+        if self.symmetry.is_some() {
+            return self.move_random_orbit(true, 1.0);
+        }
         self.add_random_box();
     }
 
     #[wasm_bindgen(js_name = removeRandomBox)]
     pub fn remove_random_box_js(&mut self) {
+        if self.symmetry.is_some() {
+            return self.move_random_orbit(false, 1.0);
+        }
         self.remove_random_box();
     }
 
@@ -1,46 +1,1410 @@
-use std::{env, time::Instant};
+use std::{
+    error::Error,
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use serde::Deserialize;
+
 extern crate lozenge_tilings;
 
-pub fn main() {
-    let args: Vec<String> = env::args().collect();
+use lozenge_tilings::{
+    energy_log_to_csv, probabilities_to_csv, run_sweep, Algorithm, AnnealingSchedule, Boundary,
+    BoundaryConditions, ColorScheme, Cooling, HoneycombGraph, InteractionEnergy, Mesh,
+    PeriodicLozengeTiling, RasterOptions, SvgOptions, SweepRow, SweepSpec, SymmetryClass,
+    TikzOptions, TikzStyle, TilingState, ToroidalLozengeTiling, TorusPeriods, Trajectory,
+    TrajectoryReplay, VolumeStatistics, SWEEP_CSV_HEADER,
+};
+
+const DEFAULT_ITERATIONS: i32 = 10000;
+const DEFAULT_Q: f32 = 0.9;
+const DEFAULT_DRAW_DISTANCE: i32 = 100;
+const DEFAULT_PERIODS: [i32; 3] = [1, 2, 3];
+
+#[derive(Parser)]
+#[command(about = "Sample and inspect periodic lozenge tilings")]
+struct Cli {
+    /// TOML file with defaults for the tiling and run options
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+
+    /// Print a summary of the instrumented spans to stderr when done
+    #[cfg(feature = "tracing")]
+    #[arg(long, global = true)]
+    timings: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Generate a tiling and write it out
+    Sample {
+        #[command(flatten)]
+        tiling: TilingArgs,
+        #[command(flatten)]
+        run: RunArgs,
+        #[command(flatten)]
+        output: OutputArgs,
+        /// Continue from a saved state instead of an empty tiling, with its periods,
+        /// symmetry, boundary and obstacles
+        #[arg(
+            long,
+            conflicts_with_all = ["periods", "symmetry", "symmetry_box", "boundary", "obstacles"]
+        )]
+        state_in: Option<PathBuf>,
+        /// Start from column heights, x,y,height CSV for .csv files and a plane
+        /// partition matrix of box counts otherwise
+        #[arg(long, conflicts_with = "state_in")]
+        heights_in: Option<PathBuf>,
+        /// Record every move of the run as a binary trajectory log
+        #[arg(long)]
+        trajectory_out: Option<PathBuf>,
+    },
+    /// Sample the volume along a run and report its statistics
+    Stats {
+        #[command(flatten)]
+        tiling: TilingArgs,
+        #[command(flatten)]
+        run: RunArgs,
+        /// Iterations between volume samples
+        #[arg(long, default_value_t = 100)]
+        sample_every: i32,
+        /// Iterations run before sampling starts
+        #[arg(long, default_value_t = 0)]
+        burn_in: i32,
+        /// Write the sampled volumes as CSV
+        #[arg(long)]
+        csv: Option<PathBuf>,
+    },
+    /// Render a saved state
+    Render {
+        /// State file written by `sample --state-out`
+        #[arg(long)]
+        state_in: PathBuf,
+        /// Draw distance overriding the saved one, one value or x,y,z
+        #[arg(long, value_delimiter = ',')]
+        draw_distance: Option<Vec<i32>>,
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Write frames of a recorded trajectory
+    Frames {
+        /// Trajectory log written by `sample --trajectory-out`
+        #[arg(long)]
+        trajectory: PathBuf,
+        /// Directory for the numbered frame files, created when missing
+        #[arg(long)]
+        out_dir: PathBuf,
+        /// Moves between frames
+        #[arg(long, default_value_t = 100)]
+        every: usize,
+        /// Moves between replay snapshots
+        #[arg(long, default_value_t = 1000)]
+        keyframe_interval: usize,
+        /// Draw distance overriding the recorded one, one value or x,y,z
+        #[arg(long, value_delimiter = ',')]
+        draw_distance: Option<Vec<i32>>,
+        #[command(flatten)]
+        frame: FrameArgs,
+    },
+    /// Write frames of a tiling growing by adding boxes only
+    Animate {
+        #[command(flatten)]
+        tiling: TilingArgs,
+        /// Boxes added in total
+        #[arg(long, short = 'n')]
+        iterations: Option<i32>,
+        /// Boxes added between frames
+        #[arg(long, default_value_t = 100)]
+        every: i32,
+        /// Seed for a reproducible run
+        #[arg(long)]
+        seed: Option<u64>,
+        /// Directory for the numbered frame files, created when missing
+        #[arg(long)]
+        out_dir: PathBuf,
+        #[command(flatten)]
+        frame: FrameArgs,
+    },
+    /// Run a grid of parameters, resuming from rows already in the output
+    Sweep {
+        /// Sweep spec, TOML or JSON
+        #[arg(long)]
+        spec: PathBuf,
+        /// Results table, appended to when it exists
+        #[arg(long)]
+        out: PathBuf,
+        /// Output format, guessed from the extension of --out when missing
+        #[arg(long, value_enum)]
+        format: Option<SweepFormat>,
+    },
+    /// Exact probabilities of every lozenge from the Kasteleyn matrix
+    Exact {
+        /// Hexagon with sides a,b,c, lozenges in the coordinates of the zero periods
+        /// tiling with z_height c
+        #[arg(long, value_delimiter = ',', conflicts_with = "torus")]
+        hexagon: Option<Vec<i32>>,
+        /// Volume weight of the hexagon
+        #[arg(short, default_value_t = 1.0)]
+        q: f64,
+        /// Torus of n by m fundamental domains
+        #[arg(long, value_delimiter = ',')]
+        torus: Option<Vec<i32>>,
+        /// Weights x,y,z of the lozenge orientations on the torus
+        #[arg(long, value_delimiter = ',', default_value = "1,1,1")]
+        weights: Vec<f64>,
+        /// Probabilities table, printed when missing
+        #[arg(long)]
+        csv: Option<PathBuf>,
+    },
+    /// Sample a doubly periodic tiling of a torus and print its slope with the
+    /// theoretical surface tension there
+    Torus {
+        /// First period vector of the columns as x,y
+        #[arg(long, value_delimiter = ',', allow_hyphen_values = true)]
+        u: Vec<i32>,
+        /// Height change along the first period
+        #[arg(long, allow_hyphen_values = true)]
+        u_height: i32,
+        /// Second period vector of the columns as x,y
+        #[arg(long, value_delimiter = ',', allow_hyphen_values = true)]
+        v: Vec<i32>,
+        /// Height change along the second period
+        #[arg(long, allow_hyphen_values = true)]
+        v_height: i32,
+        #[arg(long, short = 'n', default_value_t = DEFAULT_ITERATIONS)]
+        iterations: i32,
+        /// Seed for a reproducible run
+        #[arg(long)]
+        seed: Option<u64>,
+        /// Write the unwrapped heights over a window of columns as
+        /// x,y,unwrapped_height CSV
+        #[arg(long)]
+        height_window_csv: Option<PathBuf>,
+        /// Window of the height export as x,y,x_size,y_size
+        #[arg(
+            long,
+            value_delimiter = ',',
+            allow_hyphen_values = true,
+            default_value = "0,0,16,16"
+        )]
+        height_window: Vec<i32>,
+    },
+    /// Sample a tiling with one zero shift and print it as a cylindric partition
+    Cylindric {
+        #[command(flatten)]
+        tiling: TilingArgs,
+        #[command(flatten)]
+        run: RunArgs,
+        /// Use the exact q^volume sampler instead of the chain, iterations and algorithm
+        /// are ignored
+        #[arg(long)]
+        exact: bool,
+        /// Write the profile, shift and rows as JSON, printed when missing
+        #[arg(long)]
+        partition_out: Option<PathBuf>,
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Cool the tiling toward a configuration of low energy
+    Anneal {
+        #[command(flatten)]
+        tiling: TilingArgs,
+        /// Continue from a saved state instead of an empty tiling, with its periods,
+        /// symmetry, boundary and obstacles
+        #[arg(
+            long,
+            conflicts_with_all = ["periods", "symmetry", "symmetry_box", "boundary", "obstacles"]
+        )]
+        state_in: Option<PathBuf>,
+        /// Seed for a reproducible run
+        #[arg(long)]
+        seed: Option<u64>,
+        /// Moves proposed over the whole schedule
+        #[arg(long, short = 'n', default_value_t = 100_000)]
+        steps: i32,
+        /// Energy of a box, negative to fill the tiling
+        #[arg(long, default_value_t = 1.0, allow_negative_numbers = true)]
+        volume: f64,
+        /// Energy of a pair of boxes sharing a face
+        #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+        neighbour: f64,
+        #[arg(long, default_value_t = 10.0)]
+        initial_temperature: f64,
+        #[arg(long, default_value_t = 0.01)]
+        final_temperature: f64,
+        #[arg(long, value_enum, default_value = "geometric")]
+        cooling: CoolingArg,
+        /// Steps between entries of the energy log
+        #[arg(long, default_value_t = 1000)]
+        log_every: i32,
+        /// Write the energy log as CSV
+        #[arg(long)]
+        energy_csv: Option<PathBuf>,
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Time generation and voxel extraction
+    Bench {
+        #[command(flatten)]
+        tiling: TilingArgs,
+        #[command(flatten)]
+        run: RunArgs,
+    },
+}
+
+#[derive(Args)]
+struct TilingArgs {
+    /// Periods as x_shift,y_shift,z_height
+    #[arg(long, value_delimiter = ',')]
+    periods: Option<Vec<i32>>,
+    /// Draw distance, one value or x,y,z
+    #[arg(long, value_delimiter = ',')]
+    draw_distance: Option<Vec<i32>>,
+    /// Move whole orbits of a plane partition symmetry, needs zero x and y shifts
+    #[arg(long, value_enum)]
+    symmetry: Option<SymmetryArg>,
+    /// Box sides x,y for the self-complementary symmetry, z is the z period
+    #[arg(long, value_delimiter = ',')]
+    symmetry_box: Option<Vec<i32>>,
+    /// Boundary of the x,y,z sides of the corner, cuts along the diagonal unless fixed
+    #[arg(long, value_enum, value_delimiter = ',')]
+    boundary: Option<Vec<BoundaryArg>>,
+    /// JSON file with frozen columns and forbidden regions
+    #[arg(long)]
+    obstacles: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct RunArgs {
+    /// Steps of the chain (sweeps for the parallel chain)
+    #[arg(long, short = 'n')]
+    iterations: Option<i32>,
+    /// Weight of a box in the q^volume measure
+    #[arg(long, short)]
+    q: Option<f32>,
+    /// Seed for a reproducible run
+    #[arg(long)]
+    seed: Option<u64>,
+    #[arg(long, value_enum)]
+    algorithm: Option<AlgorithmArg>,
+}
+
+#[derive(Args)]
+struct OutputArgs {
+    /// Write the tiling state as JSON
+    #[arg(long)]
+    state_out: Option<PathBuf>,
+    /// Write the visible faces as SVG
+    #[arg(long)]
+    svg: Option<PathBuf>,
+    /// Write the column heights as CSV
+    #[arg(long)]
+    csv: Option<PathBuf>,
+    /// Write the unwrapped heights over a window of columns as x,y,unwrapped_height
+    /// CSV, columns that are wall all the way up are 2147483647
+    #[arg(long)]
+    height_window_csv: Option<PathBuf>,
+    /// Write the unwrapped heights over a window of columns as a NumPy int32 array,
+    /// columns that are wall all the way up are 2147483647
+    #[arg(long)]
+    height_window_npy: Option<PathBuf>,
+    /// Write the level lines of the heights over the window as non-intersecting
+    /// lattice paths in JSON
+    #[arg(long)]
+    lattice_paths: Option<PathBuf>,
+    /// Window of the height and lattice path exports as x,y,x_size,y_size, the
+    /// columns of the draw box by default
+    #[arg(long, value_delimiter = ',', allow_hyphen_values = true)]
+    height_window: Option<Vec<i32>>,
+    /// Write the dimers of the visible faces on the honeycomb graph as JSON
+    #[arg(long)]
+    dimers: Option<PathBuf>,
+    /// Only write the dimers of one period
+    #[arg(long)]
+    dimers_period: bool,
+    /// Write the visible faces as PNG
+    #[arg(long)]
+    png: Option<PathBuf>,
+    #[command(flatten)]
+    raster: RasterArgs,
+    /// Write the boxes as an OBJ mesh, with materials in a .mtl file next to it
+    #[arg(long)]
+    obj: Option<PathBuf>,
+    /// Write the boxes as a binary STL mesh
+    #[arg(long)]
+    stl: Option<PathBuf>,
+    /// Write the boxes as a binary PLY mesh
+    #[arg(long)]
+    ply: Option<PathBuf>,
+    /// Write the boxes as a binary glTF scene
+    #[arg(long)]
+    glb: Option<PathBuf>,
+    /// Include the walls inside the draw box in meshes
+    #[arg(long)]
+    mesh_walls: bool,
+    /// Write the visible faces as a TikZ picture
+    #[arg(long)]
+    tikz: Option<PathBuf>,
+    #[command(flatten)]
+    tikz_options: TikzArgs,
+}
+
+#[derive(Args)]
+struct TikzArgs {
+    #[arg(long, value_enum, default_value_t = TikzStyleArg::Lozenges)]
+    tikz_style: TikzStyleArg,
+    /// Leave TikZ faces white instead of coloring them by orientation
+    #[arg(long)]
+    tikz_no_colors: bool,
+    /// Draw the dual dimer graph in TikZ pictures
+    #[arg(long)]
+    tikz_dimers: bool,
+    /// Outline the fundamental domain of the periods in TikZ pictures
+    #[arg(long)]
+    tikz_domain: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum TikzStyleArg {
+    /// Flat lozenges
+    Lozenges,
+    /// Boxes in 3D coordinates
+    Boxes,
+}
+
+impl From<&TikzArgs> for TikzOptions {
+    fn from(tikz: &TikzArgs) -> Self {
+        TikzOptions {
+            style: match tikz.tikz_style {
+                TikzStyleArg::Lozenges => TikzStyle::Lozenges,
+                TikzStyleArg::Boxes => TikzStyle::Boxes,
+            },
+            color_by_type: !tikz.tikz_no_colors,
+            dimers: tikz.tikz_dimers,
+            fundamental_domain: tikz.tikz_domain,
+            ..TikzOptions::default()
+        }
+    }
+}
+
+#[derive(Args)]
+struct RasterArgs {
+    /// Size of PNG images as width,height
+    #[arg(long, value_delimiter = ',', default_values_t = [1600, 1200])]
+    png_size: Vec<u32>,
+    /// Colors of PNG images
+    #[arg(long, value_enum, default_value_t = ColorSchemeArg::Default)]
+    color_scheme: ColorSchemeArg,
+    /// Samples per pixel side used for antialiasing PNG images, at most 16
+    #[arg(long, default_value_t = 3)]
+    supersampling: u32,
+}
+
+#[derive(Args)]
+struct FrameArgs {
+    #[arg(long, value_enum, default_value_t = View::Boxes)]
+    view: View,
+    #[arg(long, value_enum, default_value_t = FrameFormat::Svg)]
+    format: FrameFormat,
+    #[command(flatten)]
+    raster: RasterArgs,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ColorSchemeArg {
+    Default,
+    Lozenges,
+    Grayscale,
+}
+
+impl From<ColorSchemeArg> for ColorScheme {
+    fn from(color_scheme: ColorSchemeArg) -> Self {
+        match color_scheme {
+            ColorSchemeArg::Default => ColorScheme::Default,
+            ColorSchemeArg::Lozenges => ColorScheme::Lozenges,
+            ColorSchemeArg::Grayscale => ColorScheme::Grayscale,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum FrameFormat {
+    Svg,
+    Png,
+}
+
+// How frames are drawn
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum View {
+    /// Isometric boxes, walls colored apart from boxes
+    Boxes,
+    /// Flat lozenges colored by orientation only
+    Lozenges,
+}
+
+impl View {
+    fn get_svg_options(self) -> SvgOptions {
+        match self {
+            View::Boxes => SvgOptions::default(),
+            View::Lozenges => SvgOptions::lozenges(),
+        }
+    }
+}
+
+impl RasterArgs {
+    fn get_raster_options(&self) -> Result<RasterOptions, Box<dyn Error>> {
+        let [width, height] = self.png_size[..] else {
+            return Err("PNG size needs 2 values: width,height".into());
+        };
+        Ok(RasterOptions {
+            width,
+            height,
+            supersampling: self.supersampling,
+            ..RasterOptions::with_color_scheme(self.color_scheme.into())
+        })
+    }
+}
+
+// Writes numbered frames in the chosen format
+struct FrameWriter {
+    out_dir: PathBuf,
+    format: FrameFormat,
+    svg_options: SvgOptions,
+    raster_options: RasterOptions,
+}
+
+impl FrameWriter {
+    fn new(out_dir: PathBuf, frame: &FrameArgs) -> Result<Self, Box<dyn Error>> {
+        let mut raster_options = frame.raster.get_raster_options()?;
+        if frame.view == View::Lozenges {
+            raster_options.wall_colors = raster_options.box_colors;
+        }
+        fs::create_dir_all(&out_dir)?;
+
+        Ok(FrameWriter {
+            out_dir,
+            format: frame.format,
+            svg_options: frame.view.get_svg_options(),
+            raster_options,
+        })
+    }
+
+    fn write(
+        &self,
+        frame: usize,
+        lozenge_tiling: &PeriodicLozengeTiling,
+    ) -> Result<(), Box<dyn Error>> {
+        match self.format {
+            FrameFormat::Svg => fs::write(
+                self.out_dir.join(format!("frame_{:06}.svg", frame)),
+                lozenge_tiling.to_svg(&self.svg_options),
+            )?,
+            FrameFormat::Png => fs::write(
+                self.out_dir.join(format!("frame_{:06}.png", frame)),
+                lozenge_tiling.to_png(&self.raster_options)?,
+            )?,
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum SweepFormat {
+    Csv,
+    Jsonl,
+}
+
+#[derive(Clone, Copy, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum AlgorithmArg {
+    Markov,
+    Adding,
+    Parallel,
+}
+
+#[derive(Clone, Copy, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum SymmetryArg {
+    Transpose,
+    Cyclic,
+    SelfComplementary,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum CoolingArg {
+    Geometric,
+    Linear,
+}
+
+impl From<CoolingArg> for Cooling {
+    fn from(cooling: CoolingArg) -> Self {
+        match cooling {
+            CoolingArg::Geometric => Cooling::Geometric,
+            CoolingArg::Linear => Cooling::Linear,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum BoundaryArg {
+    Fixed,
+    Cut,
+    MirrorCut,
+}
+
+impl From<BoundaryArg> for Boundary {
+    fn from(boundary: BoundaryArg) -> Self {
+        match boundary {
+            BoundaryArg::Fixed => Boundary::Fixed,
+            BoundaryArg::Cut => Boundary::Cut,
+            BoundaryArg::MirrorCut => Boundary::MirrorCut,
+        }
+    }
+}
+
+impl From<AlgorithmArg> for Algorithm {
+    fn from(algorithm: AlgorithmArg) -> Self {
+        match algorithm {
+            AlgorithmArg::Markov => Algorithm::MarkovChain,
+            AlgorithmArg::Adding => Algorithm::AddingOnly,
+            AlgorithmArg::Parallel => Algorithm::ParallelMarkovChain,
+        }
+    }
+}
+
+// Defaults read from --config, flags take precedence.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    periods: Option<Vec<i32>>,
+    draw_distance: Option<Vec<i32>>,
+    iterations: Option<i32>,
+    q: Option<f32>,
+    seed: Option<u64>,
+    algorithm: Option<AlgorithmArg>,
+    symmetry: Option<SymmetryArg>,
+    symmetry_box: Option<Vec<i32>>,
+    boundary: Option<Vec<BoundaryArg>>,
+    obstacles: Option<PathBuf>,
+}
+
+struct RunSettings {
+    iterations: i32,
+    q: f32,
+    algorithm: Algorithm,
+}
+
+fn read_config(path: &Option<PathBuf>) -> Result<Config, Box<dyn Error>> {
+    match path {
+        Some(path) => Ok(toml::from_str(&fs::read_to_string(path)?)?),
+        None => Ok(Config::default()),
+    }
+}
+
+fn parse_periods(periods: Option<Vec<i32>>) -> Result<[i32; 3], Box<dyn Error>> {
+    match periods.as_deref() {
+        None => Ok(DEFAULT_PERIODS),
+        Some([x_shift, y_shift, z_height]) => Ok([*x_shift, *y_shift, *z_height]),
+        Some(_) => Err("periods need 3 values: x_shift,y_shift,z_height".into()),
+    }
+}
+
+fn parse_draw_distance(draw_distance: Option<Vec<i32>>) -> Result<[i32; 3], Box<dyn Error>> {
+    match draw_distance.as_deref() {
+        None => Ok([DEFAULT_DRAW_DISTANCE; 3]),
+        Some([distance]) => Ok([*distance; 3]),
+        Some([x, y, z]) => Ok([*x, *y, *z]),
+        Some(_) => Err("draw distance needs 1 or 3 values".into()),
+    }
+}
+
+fn parse_boundary(
+    boundary: Option<Vec<BoundaryArg>>,
+) -> Result<Option<BoundaryConditions>, Box<dyn Error>> {
+    match boundary.as_deref() {
+        None => Ok(None),
+        Some([x, y, z]) => Ok(Some(BoundaryConditions {
+            x: (*x).into(),
+            y: (*y).into(),
+            z: (*z).into(),
+        })),
+        Some(_) => Err("boundary needs 3 values for the x,y,z sides".into()),
+    }
+}
+
+fn parse_symmetry(
+    symmetry: Option<SymmetryArg>,
+    symmetry_box: Option<Vec<i32>>,
+) -> Result<Option<SymmetryClass>, Box<dyn Error>> {
+    match (symmetry, symmetry_box.as_deref()) {
+        (None, _) => Ok(None),
+        (Some(SymmetryArg::Transpose), _) => Ok(Some(SymmetryClass::Transpose)),
+        (Some(SymmetryArg::Cyclic), _) => Ok(Some(SymmetryClass::Cyclic)),
+        (Some(SymmetryArg::SelfComplementary), Some([x_size, y_size])) => {
+            Ok(Some(SymmetryClass::SelfComplementary {
+                x_size: *x_size,
+                y_size: *y_size,
+            }))
+        }
+        (Some(SymmetryArg::SelfComplementary), _) => {
+            Err("self-complementary needs --symmetry-box x,y".into())
+        }
+    }
+}
+
+fn create_tiling(
+    tiling: TilingArgs,
+    config: &Config,
+) -> Result<PeriodicLozengeTiling, Box<dyn Error>> {
+    let [x_shift, y_shift, z_height] =
+        parse_periods(tiling.periods.or_else(|| config.periods.clone()))?;
+    let [x, y, z] = parse_draw_distance(
+        tiling
+            .draw_distance
+            .or_else(|| config.draw_distance.clone()),
+    )?;
+
+    let mut lozenge_tiling = PeriodicLozengeTiling::new(x_shift, y_shift, z_height, x, y, z);
+    if let Some(boundary_conditions) =
+        parse_boundary(tiling.boundary.or_else(|| config.boundary.clone()))?
+    {
+        lozenge_tiling.set_boundary_conditions(boundary_conditions)?;
+    }
+    let symmetry = parse_symmetry(
+        tiling.symmetry.or(config.symmetry),
+        tiling.symmetry_box.or_else(|| config.symmetry_box.clone()),
+    )?;
+    if symmetry.is_some() {
+        lozenge_tiling.set_symmetry(symmetry)?;
+    }
+    if let Some(path) = tiling.obstacles.or_else(|| config.obstacles.clone()) {
+        lozenge_tiling.load_obstacles_json(&fs::read_to_string(path)?)?;
+    }
+
+    Ok(lozenge_tiling)
+}
 
-    let default_iterations = 10000;
-    let default_q = 0.9;
-    let default_draw_distance = 100;
+fn resolve_run(
+    run: RunArgs,
+    config: &Config,
+    lozenge_tiling: &mut PeriodicLozengeTiling,
+) -> RunSettings {
+    if let Some(seed) = run.seed.or(config.seed) {
+        lozenge_tiling.set_seed(seed);
+    }
 
-    let iterations = match args.get(1) {
-        Some(iterations) => iterations.parse::<i32>().unwrap_or(default_iterations),
-        None => default_iterations,
+    RunSettings {
+        iterations: run
+            .iterations
+            .or(config.iterations)
+            .unwrap_or(DEFAULT_ITERATIONS),
+        q: run.q.or(config.q).unwrap_or(DEFAULT_Q),
+        algorithm: run
+            .algorithm
+            .or(config.algorithm)
+            .unwrap_or(AlgorithmArg::Markov)
+            .into(),
+    }
+}
+
+fn read_state(path: &PathBuf) -> Result<TilingState, Box<dyn Error>> {
+    Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+}
+
+// Saved tiling with the draw distance overridden when one is given
+fn read_tiling(
+    path: &PathBuf,
+    draw_distance: Option<Vec<i32>>,
+) -> Result<PeriodicLozengeTiling, Box<dyn Error>> {
+    let mut lozenge_tiling = PeriodicLozengeTiling::from_state(&read_state(path)?)?;
+    if draw_distance.is_some() {
+        let [x, y, z] = parse_draw_distance(draw_distance)?;
+        lozenge_tiling.set_draw_distance(x, y, z);
+    }
+    Ok(lozenge_tiling)
+}
+
+fn write_outputs(
+    lozenge_tiling: &PeriodicLozengeTiling,
+    output: &OutputArgs,
+) -> Result<(), Box<dyn Error>> {
+    if let Some(path) = &output.state_out {
+        fs::write(path, serde_json::to_string(&lozenge_tiling.to_state())?)?;
+    }
+    if let Some(path) = &output.svg {
+        fs::write(path, lozenge_tiling.to_svg(&SvgOptions::default()))?;
+    }
+    if let Some(path) = &output.csv {
+        fs::write(path, lozenge_tiling.to_state().heights_to_csv())?;
+    }
+    let window_paths = [
+        &output.height_window_csv,
+        &output.height_window_npy,
+        &output.lattice_paths,
+    ];
+    if window_paths.iter().any(|path| path.is_some()) {
+        let window = match output.height_window.as_deref() {
+            Some(&[x, y, x_size, y_size]) if x_size >= 0 && y_size >= 0 => {
+                lozenge_tiling.get_height_window(x, y, x_size as usize, y_size as usize)
+            }
+            Some(_) => return Err("height window needs x,y,x_size,y_size with sizes >= 0".into()),
+            None => lozenge_tiling.get_draw_height_window(),
+        };
+        if let Some(path) = &output.height_window_csv {
+            fs::write(path, window.to_csv())?;
+        }
+        if let Some(path) = &output.height_window_npy {
+            fs::write(path, window.to_npy())?;
+        }
+        if let Some(path) = &output.lattice_paths {
+            fs::write(path, serde_json::to_string(&window.to_lattice_paths())?)?;
+        }
+    }
+    if let Some(path) = &output.dimers {
+        let covering = match output.dimers_period {
+            true => lozenge_tiling.get_period_dimers(),
+            false => lozenge_tiling.get_dimers(),
+        };
+        fs::write(path, serde_json::to_string(&covering)?)?;
+    }
+    if let Some(path) = &output.png {
+        fs::write(
+            path,
+            lozenge_tiling.to_png(&output.raster.get_raster_options()?)?,
+        )?;
+    }
+    if let Some(path) = &output.tikz {
+        fs::write(path, lozenge_tiling.to_tikz(&(&output.tikz_options).into()))?;
+    }
+    let mesh_paths = [&output.obj, &output.stl, &output.ply, &output.glb];
+    if mesh_paths.iter().any(|path| path.is_some()) {
+        let mesh = lozenge_tiling.get_mesh(output.mesh_walls);
+        if let Some(path) = &output.obj {
+            let mtl_path = path.with_extension("mtl");
+            let mtl_file_name = mtl_path.file_name().and_then(|name| name.to_str());
+            fs::write(path, mesh.to_obj(mtl_file_name))?;
+            fs::write(&mtl_path, Mesh::to_mtl(output.raster.color_scheme.into()))?;
+        }
+        if let Some(path) = &output.stl {
+            fs::write(path, mesh.to_stl())?;
+        }
+        if let Some(path) = &output.ply {
+            fs::write(path, mesh.to_ply())?;
+        }
+        if let Some(path) = &output.glb {
+            fs::write(path, mesh.to_glb(output.raster.color_scheme.into()))?;
+        }
+    }
+    Ok(())
+}
+
+fn sample(
+    tiling: TilingArgs,
+    run: RunArgs,
+    output: OutputArgs,
+    state_in: Option<PathBuf>,
+    heights_in: Option<PathBuf>,
+    trajectory_out: Option<PathBuf>,
+    config: &Config,
+) -> Result<(), Box<dyn Error>> {
+    let mut lozenge_tiling = match state_in {
+        Some(path) => read_tiling(&path, tiling.draw_distance)?,
+        None => create_tiling(tiling, config)?,
+    };
+    if let Some(path) = heights_in {
+        let content = fs::read_to_string(&path)?;
+        match path.extension().is_some_and(|extension| extension == "csv") {
+            true => lozenge_tiling.load_heights_csv(&content)?,
+            false => lozenge_tiling.load_heights_matrix(&content)?,
+        }
+    }
+    let settings = resolve_run(run, config, &mut lozenge_tiling);
+
+    if trajectory_out.is_some() {
+        lozenge_tiling.start_recording();
+    }
+    lozenge_tiling.generate(settings.algorithm, settings.iterations, settings.q);
+    println!("volume: {}", lozenge_tiling.get_period_box_count());
+
+    if let (Some(path), Some(trajectory)) = (trajectory_out, lozenge_tiling.stop_recording()) {
+        let mut writer = BufWriter::new(File::create(path)?);
+        trajectory.write_to(&mut writer)?;
+        writer.flush()?;
+        println!("moves: {}", trajectory.get_step_count());
+    }
+
+    write_outputs(&lozenge_tiling, &output)
+}
+
+fn stats(
+    tiling: TilingArgs,
+    run: RunArgs,
+    sample_every: i32,
+    burn_in: i32,
+    csv: Option<PathBuf>,
+    config: &Config,
+) -> Result<(), Box<dyn Error>> {
+    let mut lozenge_tiling = create_tiling(tiling, config)?;
+    let settings = resolve_run(run, config, &mut lozenge_tiling);
+    let sample_every = sample_every.max(1);
+
+    lozenge_tiling.generate(settings.algorithm, burn_in, settings.q);
+    let volumes = lozenge_tiling.sample_volumes(
+        settings.algorithm,
+        settings.iterations,
+        settings.q,
+        sample_every,
+    );
+
+    let statistics = VolumeStatistics::from_samples(&volumes);
+    println!("samples: {}", statistics.samples);
+    println!("volume mean: {:.4}", statistics.mean);
+    println!("volume variance: {:.4}", statistics.variance);
+    println!("volume standard error: {:.4}", statistics.standard_error());
+    println!("lag 1 autocorrelation: {:.4}", statistics.autocorrelation);
+    println!(
+        "integrated autocorrelation time: {:.4}",
+        statistics.integrated_autocorrelation_time
+    );
+
+    if let Some(path) = csv {
+        let mut content = String::from("step,volume\n");
+        for (index, volume) in volumes.iter().enumerate() {
+            let step = burn_in + (sample_every * (index as i32 + 1)).min(settings.iterations);
+            content.push_str(&format!("{},{}\n", step, volume));
+        }
+        fs::write(path, content)?;
+    }
+
+    Ok(())
+}
+
+fn frames(
+    trajectory: PathBuf,
+    out_dir: PathBuf,
+    every: usize,
+    keyframe_interval: usize,
+    draw_distance: Option<Vec<i32>>,
+    frame: FrameArgs,
+) -> Result<(), Box<dyn Error>> {
+    let trajectory = Trajectory::read_from(&mut BufReader::new(File::open(trajectory)?))?;
+    let mut replay = TrajectoryReplay::new(trajectory, keyframe_interval)?;
+    if draw_distance.is_some() {
+        let [x, y, z] = parse_draw_distance(draw_distance)?;
+        replay.set_draw_distance(x, y, z);
+    }
+    let frame_writer = FrameWriter::new(out_dir, &frame)?;
+
+    let step_count = replay.get_step_count();
+    let mut steps = (0..step_count).step_by(every.max(1)).collect::<Vec<_>>();
+    steps.push(step_count);
+    let frames_count = steps.len();
+    for (frame, step) in steps.into_iter().enumerate() {
+        frame_writer.write(frame, replay.seek(step))?;
+    }
+    println!("frames: {}", frames_count);
+
+    Ok(())
+}
+
+fn animate(
+    tiling: TilingArgs,
+    iterations: Option<i32>,
+    every: i32,
+    seed: Option<u64>,
+    out_dir: PathBuf,
+    frame: FrameArgs,
+    config: &Config,
+) -> Result<(), Box<dyn Error>> {
+    let mut lozenge_tiling = create_tiling(tiling, config)?;
+    if let Some(seed) = seed.or(config.seed) {
+        lozenge_tiling.set_seed(seed);
+    }
+    let iterations = iterations
+        .or(config.iterations)
+        .unwrap_or(DEFAULT_ITERATIONS);
+    let frame_writer = FrameWriter::new(out_dir, &frame)?;
+    frame_writer.write(0, &lozenge_tiling)?;
+
+    let mut frame = 1;
+    let mut steps_done = 0;
+    while steps_done < iterations {
+        let steps = every.max(1).min(iterations - steps_done);
+        lozenge_tiling.generate_by_adding_only(steps);
+        steps_done += steps;
+        frame_writer.write(frame, &lozenge_tiling)?;
+        frame += 1;
+    }
+    println!("frames: {}", frame);
+    println!("volume: {}", lozenge_tiling.get_period_box_count());
+
+    Ok(())
+}
+
+fn render(
+    state_in: PathBuf,
+    draw_distance: Option<Vec<i32>>,
+    output: OutputArgs,
+) -> Result<(), Box<dyn Error>> {
+    let lozenge_tiling = read_tiling(&state_in, draw_distance)?;
+    write_outputs(&lozenge_tiling, &output)
+}
+
+fn read_sweep_spec(path: &Path) -> Result<SweepSpec, Box<dyn Error>> {
+    let content = fs::read_to_string(path)?;
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("json") => Ok(serde_json::from_str(&content)?),
+        _ => Ok(toml::from_str(&content)?),
+    }
+}
+
+fn sweep(spec: PathBuf, out: PathBuf, format: Option<SweepFormat>) -> Result<(), Box<dyn Error>> {
+    let spec = read_sweep_spec(&spec)?;
+    let format = format.unwrap_or(
+        match out.extension().and_then(|extension| extension.to_str()) {
+            Some("jsonl") | Some("json") => SweepFormat::Jsonl,
+            _ => SweepFormat::Csv,
+        },
+    );
+
+    // drop a row cut by an interruption before appending
+    let mut content = match fs::read_to_string(&out) {
+        Ok(content) => content,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(error) => return Err(error.into()),
+    };
+    if !content.is_empty() && !content.ends_with('\n') {
+        content.truncate(content.rfind('\n').map_or(0, |index| index + 1));
+        fs::write(&out, &content)?;
+    }
+
+    let completed = content
+        .lines()
+        .filter_map(|line| match format {
+            SweepFormat::Csv => SweepRow::from_csv_line(line),
+            SweepFormat::Jsonl => SweepRow::from_json_line(line),
+        })
+        .map(|row| row.get_key())
+        .collect();
+
+    let mut file = OpenOptions::new().create(true).append(true).open(&out)?;
+    if content.is_empty() && format == SweepFormat::Csv {
+        writeln!(file, "{}", SWEEP_CSV_HEADER)?;
+    }
+
+    let runs_count = spec.get_runs_count();
+    let mut done = content.lines().count().saturating_sub(match format {
+        SweepFormat::Csv => 1,
+        SweepFormat::Jsonl => 0,
+    });
+    run_sweep(&spec, &completed, |row| {
+        let line = match format {
+            SweepFormat::Csv => row.to_csv_line(),
+            SweepFormat::Jsonl => row.to_json_line(),
+        };
+        writeln!(file, "{}", line)?;
+        file.flush()?;
+
+        done += 1;
+        eprintln!(
+            "{}/{} x_shift={} y_shift={} z_height={} q={} iterations={} repetition={}",
+            done,
+            runs_count,
+            row.x_shift,
+            row.y_shift,
+            row.z_height,
+            row.q,
+            row.iterations,
+            row.repetition
+        );
+        Ok::<_, Box<dyn Error>>(())
+    })?;
+
+    Ok(())
+}
+
+fn exact(
+    hexagon: Option<Vec<i32>>,
+    q: f64,
+    torus: Option<Vec<i32>>,
+    weights: Vec<f64>,
+    csv: Option<PathBuf>,
+) -> Result<(), Box<dyn Error>> {
+    let graph = match (hexagon.as_deref(), torus.as_deref()) {
+        (Some(&[a, b, c]), None) => HoneycombGraph::hexagon(a, b, c, q)?,
+        (None, Some(&[n, m])) => match weights[..] {
+            [x, y, z] => HoneycombGraph::torus(n, m, [x, y, z])?,
+            _ => return Err("--weights takes x,y,z".into()),
+        },
+        (Some(_), None) => return Err("--hexagon takes a,b,c".into()),
+        (None, Some(_)) => return Err("--torus takes n,m".into()),
+        _ => return Err("one of --hexagon or --torus is needed".into()),
     };
 
-    let q = match args.get(2) {
-        Some(q) => q.parse::<f32>().unwrap_or(default_q),
-        None => default_q,
+    let partition_function = graph.get_partition_function()?;
+    let probabilities = graph.get_probabilities()?;
+    match csv {
+        Some(path) => {
+            println!("partition function: {}", partition_function);
+            fs::write(path, probabilities_to_csv(&probabilities))?;
+        }
+        None => print!("{}", probabilities_to_csv(&probabilities)),
+    }
+
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn anneal(
+    tiling: TilingArgs,
+    state_in: Option<PathBuf>,
+    seed: Option<u64>,
+    schedule: AnnealingSchedule,
+    energy: InteractionEnergy,
+    energy_csv: Option<PathBuf>,
+    output: OutputArgs,
+    config: &Config,
+) -> Result<(), Box<dyn Error>> {
+    let mut lozenge_tiling = match state_in {
+        Some(path) => read_tiling(&path, tiling.draw_distance)?,
+        None => create_tiling(tiling, config)?,
     };
+    if let Some(seed) = seed.or(config.seed) {
+        lozenge_tiling.set_seed(seed);
+    }
+
+    let log = lozenge_tiling.anneal(&energy, &schedule)?;
+    if let (Some(first), Some(last)) = (log.first(), log.last()) {
+        println!("energy: {} -> {}", first.energy, last.energy);
+    }
+    println!("volume: {}", lozenge_tiling.get_period_box_count());
+    if let Some(path) = energy_csv {
+        fs::write(path, energy_log_to_csv(&log))?;
+    }
 
-    let draw_distance = match args.get(3) {
-        Some(draw_distance) => draw_distance
-            .parse::<i32>()
-            .unwrap_or(default_draw_distance),
-        None => default_draw_distance,
+    write_outputs(&lozenge_tiling, &output)
+}
+
+fn cylindric(
+    tiling: TilingArgs,
+    run: RunArgs,
+    exact: bool,
+    partition_out: Option<PathBuf>,
+    output: OutputArgs,
+    config: &Config,
+) -> Result<(), Box<dyn Error>> {
+    let mut lozenge_tiling = create_tiling(tiling, config)?;
+    let settings = resolve_run(run, config, &mut lozenge_tiling);
+
+    match exact {
+        true => lozenge_tiling.generate_cylindric_exact(settings.q)?,
+        false => lozenge_tiling.generate(settings.algorithm, settings.iterations, settings.q),
+    }
+    let partition = serde_json::to_string(&lozenge_tiling.get_cylindric_partition()?)?;
+    match partition_out {
+        Some(path) => {
+            println!("volume: {}", lozenge_tiling.get_period_box_count());
+            fs::write(path, partition)?;
+        }
+        None => println!("{}", partition),
+    }
+
+    write_outputs(&lozenge_tiling, &output)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn torus(
+    u: Vec<i32>,
+    u_height: i32,
+    v: Vec<i32>,
+    v_height: i32,
+    iterations: i32,
+    seed: Option<u64>,
+    height_window_csv: Option<PathBuf>,
+    height_window: Vec<i32>,
+) -> Result<(), Box<dyn Error>> {
+    let (&[u_x, u_y], &[v_x, v_y]) = (&u[..], &v[..]) else {
+        return Err("--u and --v take x,y".into());
     };
+    let mut lozenge_tiling = ToroidalLozengeTiling::new(TorusPeriods {
+        u: [u_x, u_y],
+        u_height,
+        v: [v_x, v_y],
+        v_height,
+    })?;
+    if let Some(seed) = seed {
+        lozenge_tiling.set_seed(seed);
+    }
+    lozenge_tiling.generate(iterations);
 
-    let mut lozenge_tiling = lozenge_tilings::PeriodicLozengeTiling::new(
-        1,
-        2,
-        3,
-        draw_distance,
-        draw_distance,
-        draw_distance,
+    let [x, y, z] = lozenge_tiling.get_slope();
+    println!("slope: x {:.6}, y {:.6}, z {:.6}", x, y, z);
+    println!(
+        "theoretical surface tension: {:.6}",
+        lozenge_tiling.get_theoretical_surface_tension()
     );
 
+    if let Some(path) = height_window_csv {
+        let window = match height_window[..] {
+            [x, y, x_size, y_size] if x_size >= 0 && y_size >= 0 => {
+                lozenge_tiling.get_height_window(x, y, x_size as usize, y_size as usize)
+            }
+            _ => return Err("height window needs x,y,x_size,y_size with sizes >= 0".into()),
+        };
+        fs::write(path, window.to_csv())?;
+    }
+
+    Ok(())
+}
+
+fn bench(tiling: TilingArgs, run: RunArgs, config: &Config) -> Result<(), Box<dyn Error>> {
+    let mut lozenge_tiling = create_tiling(tiling, config)?;
+    let settings = resolve_run(run, config, &mut lozenge_tiling);
+
+    let start = Instant::now();
+    lozenge_tiling.generate(settings.algorithm, settings.iterations, settings.q);
+    println!("generate: {:?}", start.elapsed());
+
     let start = Instant::now();
-    lozenge_tiling.generate_with_markov_chain(iterations, q);
-    let duration = start.elapsed();
-    println!("generate_with_markov_chain: {:?}", duration);
+    lozenge_tiling.get_box_voxels();
+    println!("get_box_voxels: {:?}", start.elapsed());
 
     let start = Instant::now();
     lozenge_tiling.get_wall_voxels();
-    let duration = start.elapsed();
-    println!("get_box_voxels_js: {:?}", duration);
+    println!("get_wall_voxels: {:?}", start.elapsed());
+
+    Ok(())
+}
+
+pub fn main() -> Result<(), Box<dyn Error>> {
+    let cli = Cli::parse();
+    let config = read_config(&cli.config)?;
+
+    #[cfg(feature = "tracing")]
+    let timings = if cli.timings {
+        Some(lozenge_tilings::init_tracing(false)?)
+    } else {
+        None
+    };
+
+    let result = match cli.command {
+        Command::Sample {
+            tiling,
+            run,
+            output,
+            state_in,
+            heights_in,
+            trajectory_out,
+        } => sample(
+            tiling,
+            run,
+            output,
+            state_in,
+            heights_in,
+            trajectory_out,
+            &config,
+        ),
+        Command::Stats {
+            tiling,
+            run,
+            sample_every,
+            burn_in,
+            csv,
+        } => stats(tiling, run, sample_every, burn_in, csv, &config),
+        Command::Render {
+            state_in,
+            draw_distance,
+            output,
+        } => render(state_in, draw_distance, output),
+        Command::Frames {
+            trajectory,
+            out_dir,
+            every,
+            keyframe_interval,
+            draw_distance,
+            frame,
+        } => frames(
+            trajectory,
+            out_dir,
+            every,
+            keyframe_interval,
+            draw_distance,
+            frame,
+        ),
+        Command::Animate {
+            tiling,
+            iterations,
+            every,
+            seed,
+            out_dir,
+            frame,
+        } => animate(tiling, iterations, every, seed, out_dir, frame, &config),
+        Command::Sweep { spec, out, format } => sweep(spec, out, format),
+        Command::Exact {
+            hexagon,
+            q,
+            torus,
+            weights,
+            csv,
+        } => exact(hexagon, q, torus, weights, csv),
+        Command::Cylindric {
+            tiling,
+            run,
+            exact,
+            partition_out,
+            output,
+        } => cylindric(tiling, run, exact, partition_out, output, &config),
+        Command::Torus {
+            u,
+            u_height,
+            v,
+            v_height,
+            iterations,
+            seed,
+            height_window_csv,
+            height_window,
+        } => torus(
+            u,
+            u_height,
+            v,
+            v_height,
+            iterations,
+            seed,
+            height_window_csv,
+            height_window,
+        ),
+        Command::Anneal {
+            tiling,
+            state_in,
+            seed,
+            steps,
+            volume,
+            neighbour,
+            initial_temperature,
+            final_temperature,
+            cooling,
+            log_every,
+            energy_csv,
+            output,
+        } => anneal(
+            tiling,
+            state_in,
+            seed,
+            AnnealingSchedule {
+                initial_temperature,
+                final_temperature,
+                cooling: cooling.into(),
+                steps,
+                log_every,
+            },
+            InteractionEnergy { volume, neighbour },
+            energy_csv,
+            output,
+            &config,
+        ),
+        Command::Bench { tiling, run } => bench(tiling, run, &config),
+    };
+
+    #[cfg(feature = "tracing")]
+    if let Some(timings) = timings {
+        for timing in timings.get() {
+            eprintln!(
+                "{}: {} calls, {:.3}ms total, {:.3}ms mean",
+                timing.name,
+                timing.count,
+                timing.total_ms,
+                timing.mean_ms()
+            );
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::{error::ErrorKind, Parser};
+    use lozenge_tilings::{Algorithm, DrawDistance, LozengeTilingPeriods};
+
+    use super::{create_tiling, resolve_run, Cli, Command, Config};
+
+    #[test]
+    fn state_in_conflicts_with_the_tiling_options() {
+        for option in [
+            ["--periods", "0,0,3"],
+            ["--symmetry", "cyclic"],
+            ["--symmetry-box", "2,2"],
+            ["--boundary", "cut,fixed,fixed"],
+            ["--obstacles", "obstacles.json"],
+            ["--heights-in", "heights.csv"],
+        ] {
+            let args = [
+                ["lozenge", "sample", "--state-in", "state.json"].as_slice(),
+                &option,
+            ]
+            .concat();
+            let error = Cli::try_parse_from(args).err().unwrap();
+            assert_eq!(error.kind(), ErrorKind::ArgumentConflict);
+        }
+        assert!(Cli::try_parse_from([
+            "lozenge",
+            "sample",
+            "--state-in",
+            "state.json",
+            "--draw-distance",
+            "5",
+            "-n",
+            "10",
+        ])
+        .is_ok());
+    }
+
+    #[test]
+    fn flags_take_precedence_over_the_config() {
+        let config: Config = toml::from_str(
+            "periods = [0, 0, 4]\ndraw_distance = [6]\niterations = 30\nq = 0.5\nalgorithm = \"parallel\"\n",
+        )
+        .unwrap();
+        let cli = Cli::try_parse_from([
+            "lozenge",
+            "sample",
+            "--draw-distance",
+            "7,8,9",
+            "-n",
+            "40",
+            "--config",
+            "config.toml",
+        ])
+        .unwrap();
+        let Command::Sample { tiling, run, .. } = cli.command else {
+            panic!("expected the sample command");
+        };
+
+        let mut lozenge_tiling = create_tiling(tiling, &config).unwrap();
+        let settings = resolve_run(run, &config, &mut lozenge_tiling);
+
+        assert_eq!(
+            *lozenge_tiling.get_periods(),
+            LozengeTilingPeriods {
+                x_shift: 0,
+                y_shift: 0,
+                z_height: 4,
+            }
+        );
+        assert_eq!(
+            *lozenge_tiling.get_draw_distance(),
+            DrawDistance { x: 7, y: 8, z: 9 }
+        );
+        assert_eq!(settings.iterations, 40);
+        assert_eq!(settings.q, 0.5);
+        assert_eq!(settings.algorithm, Algorithm::ParallelMarkovChain);
+        assert!(toml::from_str::<Config>("iteration = 30").is_err());
+    }
 }
@@ -0,0 +1,428 @@
+use std::fmt::Write;
+
+use rustc_hash::{FxHashMap, FxHashSet};
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    faces::{Face, FaceKind, FaceOrientation},
+    raster::ColorScheme,
+    vector3::Vector3,
+    PeriodicLozengeTiling, VoxelBoundaries,
+};
+
+const ORIENTATIONS: [FaceOrientation; 3] =
+    [FaceOrientation::X, FaceOrientation::Y, FaceOrientation::Z];
+const KINDS: [FaceKind; 2] = [FaceKind::Box, FaceKind::Wall];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MeshFace {
+    // indices into the vertices, counterclockwise when looking from outside
+    pub vertices: [u32; 4],
+    pub orientation: FaceOrientation,
+    // false for faces pointing to -x, -y or -z
+    pub positive: bool,
+    pub kind: FaceKind,
+}
+
+// Closed surface of a set of voxels made of unit squares. Vertices are shared between
+// faces, so every edge belongs to two faces with opposite directions.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Mesh {
+    pub vertices: Vec<Vector3>,
+    pub faces: Vec<MeshFace>,
+}
+
+fn get_offset(orientation: FaceOrientation) -> Vector3 {
+    match orientation {
+        FaceOrientation::X => Vector3(1, 0, 0),
+        FaceOrientation::Y => Vector3(0, 1, 0),
+        FaceOrientation::Z => Vector3(0, 0, 1),
+    }
+}
+
+pub(crate) fn get_material_name(kind: FaceKind, orientation: FaceOrientation) -> String {
+    let kind = match kind {
+        FaceKind::Box => "box",
+        FaceKind::Wall => "wall",
+    };
+    let orientation = match orientation {
+        FaceOrientation::X => "x",
+        FaceOrientation::Y => "y",
+        FaceOrientation::Z => "z",
+    };
+    format!("{}_{}", kind, orientation)
+}
+
+fn get_material_index(kind: FaceKind, orientation: FaceOrientation) -> u8 {
+    let kind = match kind {
+        FaceKind::Box => 0,
+        FaceKind::Wall => 3,
+    };
+    let orientation = match orientation {
+        FaceOrientation::X => 0,
+        FaceOrientation::Y => 1,
+        FaceOrientation::Z => 2,
+    };
+    kind + orientation
+}
+
+impl Mesh {
+    // Faces of the voxels that have no voxel next to them. A voxel in both sets
+    // counts as a box.
+    pub fn from_voxels(box_voxels: &[Vector3], wall_voxels: &[Vector3]) -> Mesh {
+        let solid = box_voxels
+            .iter()
+            .chain(wall_voxels.iter())
+            .copied()
+            .collect::<FxHashSet<_>>();
+        let boxes = box_voxels.iter().copied().collect::<FxHashSet<_>>();
+
+        let mut mesh = Mesh::default();
+        let mut vertex_indices = FxHashMap::default();
+        let mut voxels = solid.iter().copied().collect::<Vec<_>>();
+        voxels.sort_unstable_by_key(|Vector3(x, y, z)| (*x, *y, *z));
+
+        for voxel in voxels {
+            let kind = match boxes.contains(&voxel) {
+                true => FaceKind::Box,
+                false => FaceKind::Wall,
+            };
+            for orientation in ORIENTATIONS {
+                let Vector3(dx, dy, dz) = get_offset(orientation);
+                let Vector3(x, y, z) = voxel;
+
+                if !solid.contains(&Vector3(x + dx, y + dy, z + dz)) {
+                    let face = Face {
+                        voxel,
+                        orientation,
+                        kind,
+                    };
+                    mesh.push_face(&mut vertex_indices, face.corners(), face, true);
+                }
+                let below = Vector3(x - dx, y - dy, z - dz);
+                if !solid.contains(&below) {
+                    // the positive face of the missing neighbor, turned around
+                    let face = Face {
+                        voxel: below,
+                        orientation,
+                        kind,
+                    };
+                    let mut corners = face.corners();
+                    corners.reverse();
+                    mesh.push_face(&mut vertex_indices, corners, face, false);
+                }
+            }
+        }
+
+        mesh
+    }
+
+    fn push_face(
+        &mut self,
+        vertex_indices: &mut FxHashMap<Vector3, u32>,
+        corners: [Vector3; 4],
+        face: Face,
+        positive: bool,
+    ) {
+        let vertices = corners.map(|corner| {
+            *vertex_indices.entry(corner).or_insert_with(|| {
+                self.vertices.push(corner);
+                self.vertices.len() as u32 - 1
+            })
+        });
+        self.faces.push(MeshFace {
+            vertices,
+            orientation: face.orientation,
+            positive,
+            kind: face.kind,
+        });
+    }
+
+    fn get_triangles(&self) -> impl Iterator<Item = (&MeshFace, [u32; 3])> {
+        self.faces.iter().flat_map(|face| {
+            let [a, b, c, d] = face.vertices;
+            [(face, [a, b, c]), (face, [a, c, d])]
+        })
+    }
+
+    // Wavefront OBJ with a group and material per face kind and orientation, the
+    // materials are in to_mtl
+    pub fn to_obj(&self, mtl_file_name: Option<&str>) -> String {
+        let mut obj = String::new();
+        if let Some(mtl_file_name) = mtl_file_name {
+            writeln!(obj, "mtllib {}", mtl_file_name).unwrap();
+        }
+        for Vector3(x, y, z) in self.vertices.iter() {
+            writeln!(obj, "v {} {} {}", x, y, z).unwrap();
+        }
+        // normals 1 to 3 point to +x, +y and +z, 4 to 6 to -x, -y and -z
+        for sign in [1, -1] {
+            for orientation in ORIENTATIONS {
+                let Vector3(x, y, z) = get_offset(orientation);
+                writeln!(obj, "vn {} {} {}", sign * x, sign * y, sign * z).unwrap();
+            }
+        }
+
+        for kind in KINDS {
+            for (index, orientation) in ORIENTATIONS.iter().enumerate() {
+                let faces = self
+                    .faces
+                    .iter()
+                    .filter(|face| face.kind == kind && face.orientation == *orientation)
+                    .collect::<Vec<_>>();
+                if faces.is_empty() {
+                    continue;
+                }
+
+                let material_name = get_material_name(kind, *orientation);
+                writeln!(obj, "g {}", material_name).unwrap();
+                writeln!(obj, "usemtl {}", material_name).unwrap();
+                for face in faces {
+                    let normal = index + if face.positive { 1 } else { 4 };
+                    let [a, b, c, d] = face.vertices.map(|vertex| vertex + 1);
+                    writeln!(
+                        obj,
+                        "f {a}//{normal} {b}//{normal} {c}//{normal} {d}//{normal}"
+                    )
+                    .unwrap();
+                }
+            }
+        }
+
+        obj
+    }
+
+    pub fn to_mtl(color_scheme: ColorScheme) -> String {
+        let (box_colors, wall_colors) = color_scheme.get_colors();
+        let mut mtl = String::new();
+        for (kind, colors) in [(FaceKind::Box, box_colors), (FaceKind::Wall, wall_colors)] {
+            for (orientation, [r, g, b]) in ORIENTATIONS.iter().zip(colors) {
+                writeln!(mtl, "newmtl {}", get_material_name(kind, *orientation)).unwrap();
+                writeln!(
+                    mtl,
+                    "Kd {:.4} {:.4} {:.4}",
+                    f64::from(r) / 255.0,
+                    f64::from(g) / 255.0,
+                    f64::from(b) / 255.0
+                )
+                .unwrap();
+            }
+        }
+        mtl
+    }
+
+    // Binary STL, two triangles per face. The attribute of a triangle is the material
+    // index: 0 to 2 for box x, y and z faces, 3 to 5 for wall faces.
+    pub fn to_stl(&self) -> Vec<u8> {
+        let triangles_count = 2 * self.faces.len();
+        let mut stl = Vec::with_capacity(84 + 50 * triangles_count);
+        let mut header = [0u8; 80];
+        let title = b"lozenge tiling";
+        header[..title.len()].copy_from_slice(title);
+        stl.extend_from_slice(&header);
+        stl.extend_from_slice(&(triangles_count as u32).to_le_bytes());
+
+        for (face, triangle) in self.get_triangles() {
+            let sign = if face.positive { 1 } else { -1 };
+            let Vector3(x, y, z) = get_offset(face.orientation);
+            for value in [sign * x, sign * y, sign * z] {
+                stl.extend_from_slice(&(value as f32).to_le_bytes());
+            }
+            for vertex in triangle {
+                let Vector3(x, y, z) = self.vertices[vertex as usize];
+                for value in [x, y, z] {
+                    stl.extend_from_slice(&(value as f32).to_le_bytes());
+                }
+            }
+            let material = u16::from(get_material_index(face.kind, face.orientation));
+            stl.extend_from_slice(&material.to_le_bytes());
+        }
+
+        stl
+    }
+
+    // Binary little endian PLY with quad faces, every face has its material index as
+    // in to_stl
+    pub fn to_ply(&self) -> Vec<u8> {
+        let mut ply = String::new();
+        writeln!(ply, "ply").unwrap();
+        writeln!(ply, "format binary_little_endian 1.0").unwrap();
+        writeln!(ply, "comment lozenge tiling").unwrap();
+        writeln!(ply, "element vertex {}", self.vertices.len()).unwrap();
+        for axis in ["x", "y", "z"] {
+            writeln!(ply, "property float {}", axis).unwrap();
+        }
+        writeln!(ply, "element face {}", self.faces.len()).unwrap();
+        writeln!(ply, "property list uchar uint vertex_indices").unwrap();
+        writeln!(ply, "property uchar material").unwrap();
+        writeln!(ply, "end_header").unwrap();
+
+        let mut ply = ply.into_bytes();
+        for Vector3(x, y, z) in self.vertices.iter() {
+            for value in [x, y, z] {
+                ply.extend_from_slice(&(*value as f32).to_le_bytes());
+            }
+        }
+        for face in self.faces.iter() {
+            ply.push(4);
+            for vertex in face.vertices {
+                ply.extend_from_slice(&vertex.to_le_bytes());
+            }
+            ply.push(get_material_index(face.kind, face.orientation));
+        }
+
+        ply
+    }
+}
+
+impl PeriodicLozengeTiling {
+    // Every voxel of the draw box matching, not only the visible ones like get_voxels,
+    // so the mesh has no shells around hidden voxels
+    fn get_solid_voxels(
+        &self,
+        match_fn: fn(&PeriodicLozengeTiling, &Vector3) -> bool,
+    ) -> Vec<Vector3> {
+        let VoxelBoundaries {
+            x_min,
+            x_max,
+            y_min,
+            y_max,
+            z_min,
+            z_max,
+        } = self.get_voxel_boundaries();
+
+        let mut voxels = Vec::new();
+        for x in x_min..x_max {
+            for y in y_min..y_max {
+                for z in z_min..z_max {
+                    if match_fn(self, &Vector3(x, y, z)) {
+                        voxels.push(Vector3(x, y, z));
+                    }
+                }
+            }
+        }
+        voxels
+    }
+
+    // Closed surface of the boxes inside the draw box, together with the walls when
+    // include_walls is set
+    pub fn get_mesh(&self, include_walls: bool) -> Mesh {
+        let wall_voxels = match include_walls {
+            true => self.get_solid_voxels(PeriodicLozengeTiling::is_wall),
+            false => Vec::new(),
+        };
+        Mesh::from_voxels(
+            &self.get_solid_voxels(PeriodicLozengeTiling::is_box),
+            &wall_voxels,
+        )
+    }
+}
+
+#[wasm_bindgen]
+impl PeriodicLozengeTiling {
+    #[wasm_bindgen(js_name = toObj)]
+    pub fn to_obj_js(&self, include_walls: bool, mtl_file_name: Option<String>) -> String {
+        self.get_mesh(include_walls)
+            .to_obj(mtl_file_name.as_deref())
+    }
+
+    #[wasm_bindgen(js_name = getMtl)]
+    pub fn get_mtl_js() -> String {
+        Mesh::to_mtl(ColorScheme::Default)
+    }
+
+    #[wasm_bindgen(js_name = toStl)]
+    pub fn to_stl_js(&self, include_walls: bool) -> Vec<u8> {
+        self.get_mesh(include_walls).to_stl()
+    }
+
+    #[wasm_bindgen(js_name = toPly)]
+    pub fn to_ply_js(&self, include_walls: bool) -> Vec<u8> {
+        self.get_mesh(include_walls).to_ply()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rustc_hash::FxHashMap;
+
+    use super::Mesh;
+    use crate::{vector3::Vector3, PeriodicLozengeTiling};
+
+    // every directed edge has to be matched by the same edge in the other direction
+    fn assert_watertight(mesh: &Mesh) {
+        let mut edges = FxHashMap::default();
+        for face in mesh.faces.iter() {
+            for i in 0..4 {
+                let edge = (face.vertices[i], face.vertices[(i + 1) % 4]);
+                *edges.entry(edge).or_insert(0) += 1;
+            }
+        }
+        for ((a, b), count) in edges.iter() {
+            assert_eq!(edges.get(&(*b, *a)), Some(count));
+        }
+    }
+
+    #[test]
+    fn cube_has_six_faces_and_eight_vertices() {
+        let mesh = Mesh::from_voxels(&[Vector3(0, 0, 0)], &[]);
+
+        assert_eq!(mesh.faces.len(), 6);
+        assert_eq!(mesh.vertices.len(), 8);
+        assert_watertight(&mesh);
+    }
+
+    #[test]
+    fn tiling_mesh_has_no_hidden_faces() {
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(0, 0, 0, 6, 6, 6);
+        lozenge_tiling.set_seed(5);
+        lozenge_tiling.generate_with_markov_chain(3000, 1.0);
+
+        // the full solid from the saved heights, every box of a column inside the draw box
+        let mut boxes = Vec::new();
+        for [x, y, height] in lozenge_tiling.to_state().heights {
+            if x < 6 && y < 6 {
+                boxes.extend((0..=height.min(5)).map(|z| Vector3(x, y, z)));
+            }
+        }
+        assert!(boxes.len() > 20);
+        let expected = Mesh::from_voxels(&boxes, &[]);
+        let mesh = lozenge_tiling.get_mesh(false);
+
+        assert_eq!(mesh.faces.len(), expected.faces.len());
+        assert_eq!(mesh.vertices.len(), expected.vertices.len());
+        assert_watertight(&mesh);
+    }
+
+    #[test]
+    fn tiling_meshes_are_watertight() {
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(1, 2, 3, 4, 4, 4);
+        lozenge_tiling.set_seed(2);
+        lozenge_tiling.generate_with_markov_chain(500, 0.9);
+
+        for include_walls in [false, true] {
+            let mesh = lozenge_tiling.get_mesh(include_walls);
+            assert!(!mesh.faces.is_empty());
+            assert_watertight(&mesh);
+
+            let stl = mesh.to_stl();
+            assert_eq!(stl.len(), 84 + 50 * 2 * mesh.faces.len());
+            let obj = mesh.to_obj(Some("tiling.mtl"));
+            assert_eq!(
+                obj.lines().filter(|line| line.starts_with("f ")).count(),
+                mesh.faces.len()
+            );
+            let ply = mesh.to_ply();
+            let header_end = b"end_header\n";
+            let data_start = ply
+                .windows(header_end.len())
+                .position(|window| window == header_end)
+                .unwrap()
+                + header_end.len();
+            assert_eq!(
+                ply.len() - data_start,
+                12 * mesh.vertices.len() + 18 * mesh.faces.len()
+            );
+        }
+    }
+}
@@ -0,0 +1,351 @@
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    heights::HeightsError, vector2::Vector2, vector3::Vector3, LozengeTilingPeriods,
+    PeriodicLozengeTiling,
+};
+
+// Parts of the domain the chains can't change. Coordinates are normalized like the
+// columns of TilingState, so obstacles repeat with the periods.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Obstacles {
+    // [x, y, height] columns frozen at a height as in TilingState, they are drawn as
+    // walls and nothing can be stacked on them
+    pub columns: Vec<[i32; 3]>,
+    // [x_min, y_min, z_min, x_max, y_max, z_max] holes where no box can be, max excluded.
+    // Holes are empty space the boxes stay out of, so unlike frozen columns they aren't
+    // drawn: as walls they would look like a support no box gets from them.
+    pub forbidden: Vec<[i32; 6]>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ObstacleLayer {
+    columns: FxHashMap<Vector2, i32>,
+    forbidden: Vec<[i32; 6]>,
+}
+
+impl ObstacleLayer {
+    fn is_empty(&self) -> bool {
+        self.columns.is_empty() && self.forbidden.is_empty()
+    }
+}
+
+impl PeriodicLozengeTiling {
+    pub fn get_obstacles(&self) -> Obstacles {
+        let mut columns = self
+            .obstacles
+            .columns
+            .iter()
+            .map(|(Vector2(x, y), height)| [*x, *y, *height])
+            .collect::<Vec<_>>();
+        columns.sort_unstable();
+
+        Obstacles {
+            columns,
+            forbidden: self.obstacles.forbidden.clone(),
+        }
+    }
+
+    // Keeps the boxes, which must stay clear of the obstacles. The tiling is left
+    // unchanged when they don't.
+    pub fn set_obstacles(&mut self, obstacles: &Obstacles) -> Result<(), HeightsError> {
+        let mut columns = FxHashMap::default();
+        for [x, y, height] in obstacles.columns.iter() {
+            if *height < -1 {
+                return Err(HeightsError::NegativeHeight { x: *x, y: *y });
+            }
+            let Vector3(nx, ny, nz) = self.normalize3(&Vector3(*x, *y, *height));
+            match columns.insert(Vector2(nx, ny), nz) {
+                Some(previous) if previous != nz => {
+                    return Err(HeightsError::Conflict { x: *x, y: *y })
+                }
+                _ => {}
+            }
+        }
+
+        let layer = ObstacleLayer {
+            columns,
+            forbidden: obstacles
+                .forbidden
+                .iter()
+                .flat_map(|region| self.normalize_region(region))
+                .collect(),
+        };
+        let previous_layer = std::mem::replace(&mut self.obstacles, layer);
+        if let Err(error) = self.validate_heights() {
+            self.obstacles = previous_layer;
+            return Err(error);
+        }
+
+        self.rebuild_box_sets();
+        self.clear_history();
+        Ok(())
+    }
+
+    pub fn load_obstacles_json(&mut self, json: &str) -> Result<(), HeightsError> {
+        let obstacles: Obstacles =
+            serde_json::from_str(json).map_err(|error| HeightsError::Parse {
+                line: error.line(),
+                message: error.to_string(),
+            })?;
+        self.set_obstacles(&obstacles)
+    }
+
+    // Pieces of a region moved into the same period as normalize3 moves its cells, the
+    // region is cut where it crosses from one period to the next
+    fn normalize_region(&self, region: &[i32; 6]) -> Vec<[i32; 6]> {
+        let LozengeTilingPeriods {
+            x_shift,
+            y_shift,
+            z_height,
+        } = self.periods;
+        let [x_min, y_min, z_min, x_max, y_max, z_max] = *region;
+
+        if x_shift == 0 && y_shift == 0 {
+            return vec![*region];
+        }
+        let (min, max, period) = if y_shift >= x_shift {
+            (y_min, y_max, y_shift)
+        } else {
+            (x_min, x_max, x_shift)
+        };
+        if min >= max {
+            return Vec::new();
+        }
+
+        (min.div_euclid(period)..=(max - 1).div_euclid(period))
+            .map(|shift| {
+                let piece_min = min.max(shift * period);
+                let piece_max = max.min((shift + 1) * period);
+                let (x_min, x_max, y_min, y_max) = if y_shift >= x_shift {
+                    (x_min, x_max, piece_min, piece_max)
+                } else {
+                    (piece_min, piece_max, y_min, y_max)
+                };
+                [
+                    x_min - shift * x_shift,
+                    y_min - shift * y_shift,
+                    z_min + shift * z_height,
+                    x_max - shift * x_shift,
+                    y_max - shift * y_shift,
+                    z_max + shift * z_height,
+                ]
+            })
+            .collect()
+    }
+
+    pub(crate) fn has_obstacles(&self) -> bool {
+        !self.obstacles.is_empty()
+    }
+
+    // Normalized frozen columns, boxes next to them can be addable without any box
+    // around
+    pub(crate) fn get_obstacle_columns(&self) -> Vec<Vector2> {
+        self.obstacles.columns.keys().copied().collect()
+    }
+
+    // Cells of frozen columns, solid like the walls
+    pub(crate) fn is_obstacle(&self, vector: &Vector3) -> bool {
+        if self.obstacles.is_empty() {
+            return false;
+        }
+        let Vector3(nx, ny, nz) = self.normalize3(vector);
+        self.obstacles
+            .columns
+            .get(&Vector2(nx, ny))
+            .is_some_and(|height| nz <= *height)
+    }
+
+    // Cells no box can enter: holes and everything above a frozen column
+    pub(crate) fn is_forbidden(&self, vector: &Vector3) -> bool {
+        if self.obstacles.is_empty() {
+            return false;
+        }
+        let Vector3(nx, ny, nz) = self.normalize3(vector);
+        let above_column = self
+            .obstacles
+            .columns
+            .get(&Vector2(nx, ny))
+            .is_some_and(|height| nz > *height);
+        let in_hole =
+            self.obstacles
+                .forbidden
+                .iter()
+                .any(|[x_min, y_min, z_min, x_max, y_max, z_max]| {
+                    (*x_min..*x_max).contains(&nx)
+                        && (*y_min..*y_max).contains(&ny)
+                        && (*z_min..*z_max).contains(&nz)
+                });
+        above_column || in_hole
+    }
+
+    // First box of a normalized column inside an obstacle, a column's boxes are the
+    // cells from its top down to the wall
+    pub(crate) fn find_obstructed_column(&self) -> Option<Vector2> {
+        if self.obstacles.is_empty() {
+            return None;
+        }
+        self.data.iter().find_map(|(column, _)| {
+            let Vector2(x, y) = *column;
+            if self.obstacles.columns.contains_key(column) {
+                return Some(*column);
+            }
+            let top = self.get_height(column);
+            let obstructed =
+                self.obstacles
+                    .forbidden
+                    .iter()
+                    .any(|[x_min, y_min, z_min, x_max, y_max, z_max]| {
+                        (*x_min..*x_max).contains(&x)
+                            && (*y_min..*y_max).contains(&y)
+                            && (*z_min..(*z_max).min(top + 1))
+                                .any(|z| self.is_box(&Vector3(x, y, z)))
+                    });
+            obstructed.then_some(*column)
+        })
+    }
+}
+
+#[wasm_bindgen]
+impl PeriodicLozengeTiling {
+    // JSON of Obstacles, like {"columns": [[2, 0, 3]], "forbidden": [[0, 3, 0, 2, 5, 1]]}
+    #[wasm_bindgen(js_name = setObstacles)]
+    pub fn set_obstacles_js(&mut self, obstacles: &str) -> Result<(), JsValue> {
+        self.load_obstacles_json(obstacles)
+            .map_err(|error| JsValue::from(error.to_string()))
+    }
+
+    #[wasm_bindgen(js_name = getObstacles)]
+    pub fn get_obstacles_js(&self) -> String {
+        serde_json::to_string(&self.get_obstacles()).unwrap()
+    }
+
+    #[wasm_bindgen(js_name = clearObstacles)]
+    pub fn clear_obstacles_js(&mut self) {
+        self.set_obstacles(&Obstacles::default()).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Obstacles;
+    use crate::{heights::HeightsError, vector3::Vector3, PeriodicLozengeTiling};
+
+    #[test]
+    fn chain_leaves_obstacles_alone() {
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(0, 0, 5, 6, 6, 6);
+        lozenge_tiling.set_seed(9);
+        lozenge_tiling
+            .load_obstacles_json(r#"{"columns": [[2, 1, 1]], "forbidden": [[0, 3, 0, 2, 4, 5]]}"#)
+            .unwrap();
+        lozenge_tiling.generate_with_markov_chain(5000, 1.0);
+
+        let boxes = lozenge_tiling.get_box_voxels();
+        assert!(boxes.iter().all(|Vector3(x, y, _)| !(*x == 2 && *y == 1)));
+        assert!(boxes.iter().all(|Vector3(x, y, _)| !(*x < 2 && *y == 3)));
+        // nothing behind the frozen column rises above it
+        assert!(boxes
+            .iter()
+            .all(|Vector3(x, y, z)| *x < 2 || *y < 1 || *z <= 1));
+        let walls = lozenge_tiling.get_wall_voxels();
+        assert!(walls.contains(&Vector3(2, 1, 1)));
+        assert!(!walls.contains(&Vector3(2, 1, 2)));
+    }
+
+    #[test]
+    fn keeps_state_when_obstacles_hit_boxes() {
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(0, 0, 0, 4, 4, 4);
+        lozenge_tiling.load_heights_matrix("2 1\n1 0\n").unwrap();
+
+        let hole = Obstacles {
+            forbidden: vec![[0, 0, 1, 1, 1, 2]],
+            ..Default::default()
+        };
+        assert_eq!(
+            lozenge_tiling.set_obstacles(&hole),
+            Err(HeightsError::Obstructed { x: 0, y: 0 })
+        );
+        let column = Obstacles {
+            columns: vec![[1, 1, 0]],
+            ..Default::default()
+        };
+        lozenge_tiling.set_obstacles(&column).unwrap();
+        assert_eq!(lozenge_tiling.get_obstacles(), column);
+        assert_eq!(
+            lozenge_tiling.load_heights_matrix("2 1\n1 1\n"),
+            Err(HeightsError::Obstructed { x: 1, y: 1 })
+        );
+        assert!(matches!(
+            lozenge_tiling.load_obstacles_json("{\"columns\": 1}"),
+            Err(HeightsError::Parse { line: 1, .. })
+        ));
+        assert_eq!(lozenge_tiling.get_period_box_count(), 4);
+
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(0, 0, 0, 4, 4, 4);
+        lozenge_tiling
+            .set_obstacles(&Obstacles {
+                columns: vec![[0, 1, 0], [1, 0, 0]],
+                forbidden: vec![[0, 0, 0, 1, 1, 1]],
+            })
+            .unwrap();
+        // the corner is a hole, the frozen columns hold up the boxes next to them
+        let mut addable_boxes = lozenge_tiling.get_addable_boxes();
+        addable_boxes.sort_unstable_by_key(|Vector3(x, y, z)| (*x, *y, *z));
+        assert_eq!(
+            addable_boxes,
+            vec![Vector3(0, 2, 0), Vector3(1, 1, 0), Vector3(2, 0, 0)]
+        );
+    }
+
+    #[test]
+    fn obstacles_repeat_with_the_periods() {
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(1, 2, 3, 5, 5, 5);
+        lozenge_tiling
+            .set_obstacles(&Obstacles {
+                columns: vec![[3, 4, 2]],
+                ..Default::default()
+            })
+            .unwrap();
+
+        assert!(lozenge_tiling.is_obstacle(&Vector3(3, 4, 2)));
+        assert!(lozenge_tiling.is_obstacle(&Vector3(2, 2, 5)));
+        assert!(lozenge_tiling.is_forbidden(&Vector3(2, 2, 6)));
+        lozenge_tiling.set_seed(3);
+        lozenge_tiling.generate_with_markov_chain(2000, 1.0);
+        let state = lozenge_tiling.to_state();
+        lozenge_tiling.set_heights(&state.heights).unwrap();
+    }
+
+    #[test]
+    fn holes_repeat_with_the_periods() {
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(1, 2, 3, 5, 5, 5);
+        // y from 2 to 5 crosses into the next two periods
+        lozenge_tiling
+            .set_obstacles(&Obstacles {
+                forbidden: vec![[0, 2, 0, 1, 5, 1]],
+                ..Default::default()
+            })
+            .unwrap();
+
+        assert!(lozenge_tiling.is_forbidden(&Vector3(0, 2, 0)));
+        assert!(lozenge_tiling.is_forbidden(&Vector3(0, 4, 0)));
+        assert!(lozenge_tiling.is_forbidden(&Vector3(-1, 0, 3)));
+        assert!(lozenge_tiling.is_forbidden(&Vector3(-2, 0, 6)));
+        assert!(!lozenge_tiling.is_forbidden(&Vector3(0, 0, 0)));
+        assert!(!lozenge_tiling.is_forbidden(&Vector3(0, 2, 1)));
+        assert_eq!(
+            lozenge_tiling.get_obstacles().forbidden,
+            vec![[-1, 0, 3, 0, 2, 4], [-2, 0, 6, -1, 1, 7]]
+        );
+
+        lozenge_tiling.set_seed(6);
+        lozenge_tiling.generate_with_markov_chain(3000, 1.0);
+        let boxes = lozenge_tiling.get_box_voxels();
+        assert!(boxes
+            .iter()
+            .all(|Vector3(x, y, z)| !(*x == 0 && (2..5).contains(y) && *z == 0)));
+    }
+}
@@ -0,0 +1,243 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    height_window::{HeightWindow, WALL_COLUMN_HEIGHT},
+    Algorithm, PeriodicLozengeTiling,
+};
+
+// Positions of the Z lozenges, the tops of the columns, along a vertical line of the
+// isometric picture. Slice s has the columns (x, y) with x - y = s and the top of
+// column (x, y) is at height(x, y) - y. Positions strictly decrease along a slice, and
+// the particles of neighboring slices interlace.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ParticleSlice {
+    pub slice: i32,
+    // in decreasing order, from the column with the smallest y
+    pub positions: Vec<i32>,
+}
+
+impl HeightWindow {
+    // Slices of the columns in the window, columns that are wall all the way up have
+    // no particle
+    pub fn get_particles(&self) -> Vec<ParticleSlice> {
+        let mut slices: BTreeMap<i32, Vec<i32>> = BTreeMap::new();
+        for (index, height) in self.heights.iter().enumerate() {
+            if *height == WALL_COLUMN_HEIGHT {
+                continue;
+            }
+            let x = self.x_start + (index / self.y_size) as i32;
+            let y = self.y_start + (index % self.y_size) as i32;
+            slices.entry(x - y).or_default().push(height - y);
+        }
+
+        slices
+            .into_iter()
+            .map(|(slice, mut positions)| {
+                positions.sort_unstable_by(|a, b| b.cmp(a));
+                ParticleSlice { slice, positions }
+            })
+            .collect()
+    }
+}
+
+// Empirical density of the particles of a window of columns over samples
+#[wasm_bindgen]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParticleDensity {
+    x_start: i32,
+    y_start: i32,
+    x_size: usize,
+    y_size: usize,
+    samples: usize,
+    // particles seen at (slice, position)
+    counts: BTreeMap<(i32, i32), u64>,
+}
+
+impl ParticleDensity {
+    pub fn new(x_start: i32, y_start: i32, x_size: usize, y_size: usize) -> ParticleDensity {
+        ParticleDensity {
+            x_start,
+            y_start,
+            x_size,
+            y_size,
+            samples: 0,
+            counts: BTreeMap::new(),
+        }
+    }
+
+    pub fn add_sample(&mut self, lozenge_tiling: &PeriodicLozengeTiling) {
+        let window =
+            lozenge_tiling.get_height_window(self.x_start, self.y_start, self.x_size, self.y_size);
+        for ParticleSlice { slice, positions } in window.get_particles() {
+            for position in positions {
+                *self.counts.entry((slice, position)).or_insert(0) += 1;
+            }
+        }
+        self.samples += 1;
+    }
+
+    pub fn get_samples(&self) -> usize {
+        self.samples
+    }
+
+    // mean number of particles at the position, between 0 and 1
+    pub fn get_density(&self, slice: i32, position: i32) -> f64 {
+        match self.samples {
+            0 => 0.0,
+            samples => {
+                let count = self.counts.get(&(slice, position)).copied().unwrap_or(0);
+                count as f64 / samples as f64
+            }
+        }
+    }
+
+    // positions seen at least once
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("slice,position,density\n");
+        for (slice, position) in self.counts.keys() {
+            csv.push_str(&format!(
+                "{},{},{}\n",
+                slice,
+                position,
+                self.get_density(*slice, *position)
+            ));
+        }
+        csv
+    }
+}
+
+impl PeriodicLozengeTiling {
+    pub fn get_particles(
+        &self,
+        x_start: i32,
+        y_start: i32,
+        x_size: usize,
+        y_size: usize,
+    ) -> Vec<ParticleSlice> {
+        self.get_height_window(x_start, y_start, x_size, y_size)
+            .get_particles()
+    }
+
+    // Runs the algorithm and adds the particles to the density after every
+    // sample_every iterations.
+    pub fn sample_particle_density(
+        &mut self,
+        density: &mut ParticleDensity,
+        algorithm: Algorithm,
+        iterations: i32,
+        q: f32,
+        sample_every: i32,
+    ) {
+        let sample_every = sample_every.max(1);
+        let mut steps_done = 0;
+
+        while steps_done < iterations {
+            let steps = sample_every.min(iterations - steps_done);
+            self.generate(algorithm, steps, q);
+            steps_done += steps;
+            density.add_sample(self);
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl PeriodicLozengeTiling {
+    // JSON of the particle slices of a window of columns
+    #[wasm_bindgen(js_name = getParticles)]
+    pub fn get_particles_js(
+        &self,
+        x_start: i32,
+        y_start: i32,
+        x_size: usize,
+        y_size: usize,
+    ) -> String {
+        let particles = self.get_particles(x_start, y_start, x_size, y_size);
+        serde_json::to_string(&particles).unwrap()
+    }
+}
+
+#[wasm_bindgen]
+impl ParticleDensity {
+    #[wasm_bindgen(constructor)]
+    pub fn new_js(x_start: i32, y_start: i32, x_size: usize, y_size: usize) -> ParticleDensity {
+        ParticleDensity::new(x_start, y_start, x_size, y_size)
+    }
+
+    #[wasm_bindgen(js_name = addSample)]
+    pub fn add_sample_js(&mut self, lozenge_tiling: &PeriodicLozengeTiling) {
+        self.add_sample(lozenge_tiling);
+    }
+
+    #[wasm_bindgen(js_name = getSamples)]
+    pub fn get_samples_js(&self) -> usize {
+        self.get_samples()
+    }
+
+    #[wasm_bindgen(js_name = getDensity)]
+    pub fn get_density_js(&self, slice: i32, position: i32) -> f64 {
+        self.get_density(slice, position)
+    }
+
+    #[wasm_bindgen(js_name = toCsv)]
+    pub fn to_csv_js(&self) -> String {
+        self.to_csv()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ParticleDensity, ParticleSlice};
+    use crate::{Algorithm, PeriodicLozengeTiling};
+
+    // particles at t or above on neighboring slices differ by at most one for every t
+    fn interlace(a: &[i32], b: &[i32]) -> bool {
+        let top = a.iter().chain(b).max().copied().unwrap_or(0);
+        let bottom = a.iter().chain(b).min().copied().unwrap_or(0);
+        (bottom..=top).all(|t| {
+            let above = |positions: &[i32]| positions.iter().filter(|p| **p >= t).count();
+            above(a).abs_diff(above(b)) <= 1
+        })
+    }
+
+    #[test]
+    fn hexagon_particles_interlace() {
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(0, 0, 3, 4, 4, 4);
+        lozenge_tiling
+            .load_heights_matrix("3 3 2 1\n3 2 1 0\n2 1 1 0\n1 0 0 0")
+            .unwrap();
+
+        let slices = lozenge_tiling.get_particles(0, 0, 4, 4);
+
+        assert_eq!(slices.len(), 7);
+        assert_eq!(
+            slices[3],
+            ParticleSlice {
+                slice: 0,
+                positions: vec![2, 0, -2, -4]
+            }
+        );
+        for pair in slices.windows(2) {
+            assert_eq!(pair[1].slice, pair[0].slice + 1);
+            assert!(interlace(&pair[0].positions, &pair[1].positions));
+        }
+    }
+
+    #[test]
+    fn density_sums_to_particles_per_slice() {
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(1, 2, 3, 5, 5, 5);
+        lozenge_tiling.set_seed(7);
+        let mut density = ParticleDensity::new(0, 0, 3, 3);
+
+        lozenge_tiling.sample_particle_density(&mut density, Algorithm::MarkovChain, 250, 0.9, 50);
+
+        assert_eq!(density.get_samples(), 5);
+        let total = (-30..30)
+            .map(|position| density.get_density(0, position))
+            .sum::<f64>();
+        assert!((total - 3.0).abs() < 1e-9);
+        assert!(density.to_csv().starts_with("slice,position,density\n"));
+    }
+}
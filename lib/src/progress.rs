@@ -0,0 +1,200 @@
+use std::ops::ControlFlow;
+
+use js_sys::{Atomics, Function, Int32Array};
+use wasm_bindgen::prelude::*;
+
+use crate::PeriodicLozengeTiling;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GenerationProgress {
+    pub steps_done: i32,
+    pub volume: i32,
+}
+
+impl PeriodicLozengeTiling {
+    // Runs step in chunks of chunk_size, on_chunk is called after every chunk and
+    // can stop the run. The tiling is valid after every step, so stopping at any
+    // chunk boundary leaves a legal configuration. Returns the number of steps done.
+    fn generate_in_chunks<S, F>(
+        &mut self,
+        iterations: i32,
+        chunk_size: i32,
+        mut step: S,
+        mut on_chunk: F,
+    ) -> i32
+    where
+        S: FnMut(&mut PeriodicLozengeTiling, i32),
+        F: FnMut(GenerationProgress) -> ControlFlow<()>,
+    {
+        let chunk_size = chunk_size.max(1);
+        let mut steps_done = 0;
+
+        while steps_done < iterations {
+            let steps = chunk_size.min(iterations - steps_done);
+            step(self, steps);
+            steps_done += steps;
+
+            let progress = GenerationProgress {
+                steps_done,
+                volume: self.get_period_box_count(),
+            };
+            if on_chunk(progress).is_break() {
+                break;
+            }
+        }
+
+        steps_done
+    }
+
+    pub fn generate_with_markov_chain_in_chunks<F>(
+        &mut self,
+        iterations: i32,
+        q: f32,
+        chunk_size: i32,
+        on_chunk: F,
+    ) -> i32
+    where
+        F: FnMut(GenerationProgress) -> ControlFlow<()>,
+    {
+        self.generate_in_chunks(
+            iterations,
+            chunk_size,
+            |lozenge_tiling, steps| lozenge_tiling.generate_with_markov_chain(steps, q),
+            on_chunk,
+        )
+    }
+
+    pub fn generate_by_adding_only_in_chunks<F>(
+        &mut self,
+        iterations: i32,
+        chunk_size: i32,
+        on_chunk: F,
+    ) -> i32
+    where
+        F: FnMut(GenerationProgress) -> ControlFlow<()>,
+    {
+        self.generate_in_chunks(
+            iterations,
+            chunk_size,
+            |lozenge_tiling, steps| lozenge_tiling.generate_by_adding_only(steps),
+            on_chunk,
+        )
+    }
+}
+
+// Calls on_progress(stepsDone, volume) and stops when it returns true or when
+// cancel[0] != 0 (set e.g. with Atomics.store on a SharedArrayBuffer from the main thread).
+fn report_js_progress(
+    progress: GenerationProgress,
+    on_progress: &Function,
+    cancel: Option<&Int32Array>,
+    error: &mut Option<JsValue>,
+) -> ControlFlow<()> {
+    let result = on_progress
+        .call2(
+            &JsValue::NULL,
+            &JsValue::from(progress.steps_done),
+            &JsValue::from(progress.volume),
+        )
+        .and_then(|cancelled| match cancel {
+            _ if cancelled.is_truthy() => Ok(1),
+            Some(cancel) => Atomics::load(cancel, 0),
+            None => Ok(0),
+        });
+
+    match result {
+        Ok(0) => ControlFlow::Continue(()),
+        Ok(_) => ControlFlow::Break(()),
+        Err(js_error) => {
+            *error = Some(js_error);
+            ControlFlow::Break(())
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl PeriodicLozengeTiling {
+    #[wasm_bindgen(js_name = generateWithMarkovChainWithProgress)]
+    pub fn generate_with_markov_chain_with_progress_js(
+        &mut self,
+        iterations: i32,
+        q: f32,
+        progress_every: i32,
+        on_progress: &Function,
+        cancel: Option<Int32Array>,
+    ) -> Result<i32, JsValue> {
+        let mut error = None;
+        let steps_done = time!("generate_with_markov_chain_with_progress_js", {
+            self.generate_with_markov_chain_in_chunks(iterations, q, progress_every, |progress| {
+                report_js_progress(progress, on_progress, cancel.as_ref(), &mut error)
+            })
+        });
+
+        match error {
+            Some(error) => Err(error),
+            None => Ok(steps_done),
+        }
+    }
+
+    #[wasm_bindgen(js_name = generateByAddingOnlyWithProgress)]
+    pub fn generate_by_adding_only_with_progress_js(
+        &mut self,
+        iterations: i32,
+        progress_every: i32,
+        on_progress: &Function,
+        cancel: Option<Int32Array>,
+    ) -> Result<i32, JsValue> {
+        let mut error = None;
+        let steps_done = time!("generate_by_adding_only_with_progress_js", {
+            self.generate_by_adding_only_in_chunks(iterations, progress_every, |progress| {
+                report_js_progress(progress, on_progress, cancel.as_ref(), &mut error)
+            })
+        });
+
+        match error {
+            Some(error) => Err(error),
+            None => Ok(steps_done),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::ControlFlow;
+
+    use crate::PeriodicLozengeTiling;
+
+    #[test]
+    fn reports_progress_after_every_chunk() {
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(1, 2, 3, 10, 10, 10);
+        let mut reported = Vec::new();
+        let steps_done = lozenge_tiling.generate_by_adding_only_in_chunks(25, 10, |progress| {
+            reported.push(progress);
+            ControlFlow::Continue(())
+        });
+
+        assert_eq!(steps_done, 25);
+        assert_eq!(
+            reported.iter().map(|p| p.steps_done).collect::<Vec<_>>(),
+            vec![10, 20, 25]
+        );
+        assert_eq!(reported.last().unwrap().volume, 25);
+    }
+
+    #[test]
+    fn can_be_cancelled_between_chunks() {
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(1, 2, 3, 10, 10, 10);
+        let steps_done =
+            lozenge_tiling.generate_with_markov_chain_in_chunks(1000, 0.9, 100, |progress| {
+                match progress.steps_done >= 300 {
+                    true => ControlFlow::Break(()),
+                    false => ControlFlow::Continue(()),
+                }
+            });
+
+        assert_eq!(steps_done, 300);
+        for vector in lozenge_tiling.removable_boxes.iter() {
+            assert!(lozenge_tiling.can_remove_box(vector));
+        }
+    }
+}
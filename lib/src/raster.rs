@@ -0,0 +1,434 @@
+use std::{error::Error, fmt};
+
+use crate::{
+    faces::{project_isometric, Face, FaceKind, FaceOrientation},
+    PeriodicLozengeTiling,
+};
+
+pub type Rgb = [u8; 3];
+
+pub const MAX_SUPERSAMPLING: u32 = 16;
+// supersampled pixels of one image, 4 bytes each
+pub const MAX_SUPERSAMPLED_PIXELS: u64 = 1 << 28;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorScheme {
+    // the colors of the svg export
+    #[default]
+    Default,
+    // walls in the box colors, a flat lozenge picture
+    Lozenges,
+    Grayscale,
+}
+
+impl ColorScheme {
+    // box and wall colors of X, Y and Z faces
+    pub(crate) fn get_colors(self) -> ([Rgb; 3], [Rgb; 3]) {
+        let box_colors = [[0xe0, 0x5a, 0x47], [0xf2, 0xa5, 0x41], [0xf6, 0xe8, 0xc3]];
+        match self {
+            ColorScheme::Default => (
+                box_colors,
+                [[0x3f, 0x6f, 0xb5], [0x6a, 0x9b, 0xd8], [0xc3, 0xd7, 0xf0]],
+            ),
+            ColorScheme::Lozenges => (box_colors, box_colors),
+            ColorScheme::Grayscale => (
+                [[0x70, 0x70, 0x70], [0xa8, 0xa8, 0xa8], [0xe8, 0xe8, 0xe8]],
+                [[0x40, 0x40, 0x40], [0x68, 0x68, 0x68], [0x98, 0x98, 0x98]],
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RasterOptions {
+    // size of the image in pixels, the picture is scaled to fit with a margin
+    pub width: u32,
+    pub height: u32,
+    // samples per pixel side used for antialiasing
+    pub supersampling: u32,
+    pub box_colors: [Rgb; 3],
+    pub wall_colors: [Rgb; 3],
+    pub stroke_color: Rgb,
+    // in pixels, 0 for no outlines
+    pub stroke_width: f64,
+    // None for a transparent background
+    pub background: Option<Rgb>,
+}
+
+impl Default for RasterOptions {
+    fn default() -> Self {
+        RasterOptions::with_color_scheme(ColorScheme::Default)
+    }
+}
+
+impl RasterOptions {
+    pub fn with_color_scheme(color_scheme: ColorScheme) -> Self {
+        let (box_colors, wall_colors) = color_scheme.get_colors();
+        RasterOptions {
+            width: 1600,
+            height: 1200,
+            supersampling: 3,
+            box_colors,
+            wall_colors,
+            stroke_color: [0x22, 0x22, 0x22],
+            stroke_width: 1.0,
+            background: Some([0xff, 0xff, 0xff]),
+        }
+    }
+
+    fn get_fill(&self, face: &Face) -> Rgb {
+        let colors = match face.kind {
+            FaceKind::Box => &self.box_colors,
+            FaceKind::Wall => &self.wall_colors,
+        };
+        match face.orientation {
+            FaceOrientation::X => colors[0],
+            FaceOrientation::Y => colors[1],
+            FaceOrientation::Z => colors[2],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RasterError {
+    // supersampling above MAX_SUPERSAMPLING
+    Supersampling(u32),
+    // more than MAX_SUPERSAMPLED_PIXELS supersampled pixels
+    TooLarge {
+        width: u32,
+        height: u32,
+        supersampling: u32,
+    },
+}
+
+impl fmt::Display for RasterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RasterError::Supersampling(supersampling) => write!(
+                f,
+                "supersampling {} is above {}",
+                supersampling, MAX_SUPERSAMPLING
+            ),
+            RasterError::TooLarge {
+                width,
+                height,
+                supersampling,
+            } => write!(
+                f,
+                "a {}x{} image with supersampling {} is too large",
+                width, height, supersampling
+            ),
+        }
+    }
+}
+
+impl Error for RasterError {}
+
+// RGBA pixels, row by row from the top
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RasterImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl RasterImage {
+    pub fn get_pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let index = 4 * (y as usize * self.width as usize + x as usize);
+        self.pixels[index..index + 4].try_into().unwrap()
+    }
+
+    #[cfg(feature = "raster")]
+    pub fn to_png(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&self.pixels).unwrap();
+        writer.finish().unwrap();
+        bytes
+    }
+}
+
+// Fills the isometric projections of faces. Visible faces don't overlap, so no depth
+// ordering is needed.
+pub fn render_faces(faces: &[Face], options: &RasterOptions) -> Result<RasterImage, RasterError> {
+    if options.supersampling > MAX_SUPERSAMPLING {
+        return Err(RasterError::Supersampling(options.supersampling));
+    }
+    let samples = options.supersampling.max(1);
+    let (width, height) = (
+        u64::from(options.width.max(1)) * u64::from(samples),
+        u64::from(options.height.max(1)) * u64::from(samples),
+    );
+    // no overflow, both sides are below 2^36
+    if width * height > MAX_SUPERSAMPLED_PIXELS {
+        return Err(RasterError::TooLarge {
+            width: options.width,
+            height: options.height,
+            supersampling: options.supersampling,
+        });
+    }
+    // below 2^28, so usize even in wasm
+    let (samples, width, height) = (samples as usize, width as usize, height as usize);
+
+    let polygons = faces
+        .iter()
+        .map(|face| {
+            let points = face.corners().map(|corner| {
+                let (u, v) = project_isometric(&corner);
+                (u, -v)
+            });
+            (options.get_fill(face), points)
+        })
+        .collect::<Vec<_>>();
+
+    let (mut min_x, mut min_y, mut max_x, mut max_y) = (0.0f64, 0.0f64, 0.0f64, 0.0f64);
+    for (_, points) in polygons.iter() {
+        for (x, y) in points {
+            min_x = min_x.min(*x);
+            min_y = min_y.min(*y);
+            max_x = max_x.max(*x);
+            max_y = max_y.max(*y);
+        }
+    }
+    let margin = samples as f64 * options.stroke_width.max(1.0);
+    let scale = ((width as f64 - 2.0 * margin) / (max_x - min_x).max(f64::EPSILON))
+        .min((height as f64 - 2.0 * margin) / (max_y - min_y).max(f64::EPSILON));
+    let offset_x = (width as f64 - (max_x - min_x) * scale) / 2.0 - min_x * scale;
+    let offset_y = (height as f64 - (max_y - min_y) * scale) / 2.0 - min_y * scale;
+    let half_stroke = options.stroke_width * samples as f64 / 2.0;
+
+    let mut colors: Vec<Option<Rgb>> = vec![None; width * height];
+    for (fill, points) in polygons.iter() {
+        let points = points.map(|(x, y)| (x * scale + offset_x, y * scale + offset_y));
+        fill_polygon(&mut colors, width, height, &points, |distance| {
+            if distance < half_stroke {
+                options.stroke_color
+            } else {
+                *fill
+            }
+        });
+    }
+
+    Ok(downsample(
+        &colors,
+        options.width.max(1),
+        options.height.max(1),
+        samples,
+        options.background,
+    ))
+}
+
+// Colors pixels with centers inside a convex polygon, color gets the distance of the
+// pixel center from the nearest polygon side
+fn fill_polygon<F: Fn(f64) -> Rgb>(
+    colors: &mut [Option<Rgb>],
+    width: usize,
+    height: usize,
+    points: &[(f64, f64); 4],
+    color: F,
+) {
+    let area = (0..4)
+        .map(|i| {
+            let (x0, y0) = points[i];
+            let (x1, y1) = points[(i + 1) % 4];
+            x0 * y1 - x1 * y0
+        })
+        .sum::<f64>();
+    let orientation = area.signum();
+
+    let (mut min_x, mut min_y, mut max_x, mut max_y) = (
+        f64::INFINITY,
+        f64::INFINITY,
+        f64::NEG_INFINITY,
+        f64::NEG_INFINITY,
+    );
+    for (x, y) in points {
+        min_x = min_x.min(*x);
+        min_y = min_y.min(*y);
+        max_x = max_x.max(*x);
+        max_y = max_y.max(*y);
+    }
+    let (x_start, x_end) = (
+        min_x.floor().max(0.0) as usize,
+        (max_x.ceil() as usize).min(width),
+    );
+    let (y_start, y_end) = (
+        min_y.floor().max(0.0) as usize,
+        (max_y.ceil() as usize).min(height),
+    );
+
+    for py in y_start..y_end {
+        for px in x_start..x_end {
+            let (x, y) = (px as f64 + 0.5, py as f64 + 0.5);
+            // signed distances to the sides, all non-negative inside
+            let distance = (0..4)
+                .map(|i| {
+                    let (x0, y0) = points[i];
+                    let (x1, y1) = points[(i + 1) % 4];
+                    let length = (x1 - x0).hypot(y1 - y0);
+                    orientation * ((x1 - x0) * (y - y0) - (y1 - y0) * (x - x0)) / length
+                })
+                .fold(f64::INFINITY, f64::min);
+            if distance >= 0.0 {
+                colors[py * width + px] = Some(color(distance));
+            }
+        }
+    }
+}
+
+fn downsample(
+    colors: &[Option<Rgb>],
+    width: u32,
+    height: u32,
+    samples: usize,
+    background: Option<Rgb>,
+) -> RasterImage {
+    let (image_width, image_height) = (width as usize, height as usize);
+    let sampled_width = image_width * samples;
+    let mut pixels = Vec::with_capacity(4 * image_width * image_height);
+
+    for y in 0..image_height {
+        for x in 0..image_width {
+            let mut sum = [0u64; 4];
+            for sy in 0..samples {
+                for sx in 0..samples {
+                    let index = (y * samples + sy) * sampled_width + x * samples + sx;
+                    let (rgb, alpha) = match (colors[index], background) {
+                        (Some(rgb), _) => (rgb, 255),
+                        (None, Some(rgb)) => (rgb, 255),
+                        (None, None) => ([0, 0, 0], 0),
+                    };
+                    // premultiplied, so transparent samples don't darken the edges
+                    for channel in 0..3 {
+                        sum[channel] += u64::from(rgb[channel]) * alpha;
+                    }
+                    sum[3] += alpha;
+                }
+            }
+
+            let count = (samples * samples) as u64;
+            let channels =
+                (0..3).map(|channel| sum[channel].checked_div(sum[3]).unwrap_or(0) as u8);
+            pixels.extend(channels);
+            pixels.push((sum[3] / count) as u8);
+        }
+    }
+
+    RasterImage {
+        width,
+        height,
+        pixels,
+    }
+}
+
+impl PeriodicLozengeTiling {
+    pub fn to_raster(&self, options: &RasterOptions) -> Result<RasterImage, RasterError> {
+        render_faces(&self.get_visible_faces(), options)
+    }
+
+    #[cfg(feature = "raster")]
+    pub fn to_png(&self, options: &RasterOptions) -> Result<Vec<u8>, RasterError> {
+        Ok(self.to_raster(options)?.to_png())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ColorScheme, RasterError, RasterOptions, MAX_SUPERSAMPLING};
+    use crate::PeriodicLozengeTiling;
+
+    #[test]
+    fn renders_face_colors_and_background() {
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(1, 2, 3, 3, 3, 3);
+        lozenge_tiling.generate_by_adding_only(5);
+        let options = RasterOptions {
+            width: 120,
+            height: 90,
+            supersampling: 1,
+            stroke_width: 0.0,
+            ..RasterOptions::with_color_scheme(ColorScheme::Default)
+        };
+
+        let image = lozenge_tiling.to_raster(&options).unwrap();
+
+        assert_eq!(image.pixels.len(), 4 * 120 * 90);
+        // corners are outside of the hexagonal window
+        assert_eq!(image.get_pixel(0, 0), [0xff, 0xff, 0xff, 0xff]);
+        let center = image.get_pixel(60, 45);
+        let colors = options
+            .box_colors
+            .iter()
+            .chain(options.wall_colors.iter())
+            .collect::<Vec<_>>();
+        assert!(colors.contains(&&[center[0], center[1], center[2]]));
+    }
+
+    #[test]
+    fn transparent_background_without_color() {
+        let lozenge_tiling = PeriodicLozengeTiling::new(1, 2, 3, 3, 3, 3);
+        let options = RasterOptions {
+            width: 40,
+            height: 30,
+            background: None,
+            ..RasterOptions::default()
+        };
+
+        let image = lozenge_tiling.to_raster(&options).unwrap();
+
+        assert_eq!(image.get_pixel(0, 0)[3], 0);
+        assert_eq!(image.get_pixel(20, 15)[3], 0xff);
+    }
+
+    #[test]
+    fn rejects_images_above_the_limits() {
+        let lozenge_tiling = PeriodicLozengeTiling::new(1, 2, 3, 3, 3, 3);
+        let options = RasterOptions {
+            width: 2,
+            height: 2,
+            supersampling: MAX_SUPERSAMPLING,
+            ..RasterOptions::default()
+        };
+        assert_eq!(lozenge_tiling.to_raster(&options).unwrap().pixels.len(), 16);
+        assert_eq!(
+            lozenge_tiling.to_raster(&RasterOptions {
+                supersampling: 300,
+                ..options.clone()
+            }),
+            Err(RasterError::Supersampling(300))
+        );
+
+        for (width, height) in [(16385, 16384), (100_000, 100_000), (u32::MAX, u32::MAX)] {
+            assert_eq!(
+                lozenge_tiling.to_raster(&RasterOptions {
+                    width,
+                    height,
+                    supersampling: 1,
+                    ..options.clone()
+                }),
+                Err(RasterError::TooLarge {
+                    width,
+                    height,
+                    supersampling: 1,
+                })
+            );
+        }
+    }
+
+    #[cfg(feature = "raster")]
+    #[test]
+    fn encodes_png() {
+        let lozenge_tiling = PeriodicLozengeTiling::new(1, 2, 3, 3, 3, 3);
+        let options = RasterOptions {
+            width: 40,
+            height: 30,
+            ..RasterOptions::default()
+        };
+
+        let png = lozenge_tiling.to_png(&options).unwrap();
+
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+    }
+}
@@ -0,0 +1,256 @@
+use std::{error::Error, fmt};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    vector2::Vector2, BoundaryConditions, BoundaryError, DrawDistance, HeightsError,
+    LozengeTilingPeriods, Obstacles, PeriodicLozengeTiling, SymmetryClass, SymmetryError,
+};
+
+// Everything needed to restore a tiling, heights are the saved (normalized) column heights.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TilingState {
+    pub periods: LozengeTilingPeriods,
+    pub draw_distance: DrawDistance,
+    // [x, y, height] of every column with at least one box
+    pub heights: Vec<[i32; 3]>,
+    // missing in files saved before obstacles
+    #[serde(default)]
+    pub obstacles: Obstacles,
+    // fixed in files saved before boundary conditions
+    #[serde(default)]
+    pub boundary_conditions: BoundaryConditions,
+    // none in files saved before symmetries
+    #[serde(default)]
+    pub symmetry: Option<SymmetryClass>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateError {
+    // boundary conditions the periods or the symmetry don't allow
+    Boundary(BoundaryError),
+    // a symmetry the periods or the boundary conditions don't allow
+    Symmetry(SymmetryError),
+    // heights that aren't a legal configuration of the restored tiling
+    Heights(HeightsError),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::Boundary(error) => write!(f, "invalid boundary conditions: {}", error),
+            StateError::Symmetry(error) => write!(f, "invalid symmetry: {}", error),
+            StateError::Heights(error) => write!(f, "invalid heights: {}", error),
+        }
+    }
+}
+
+impl Error for StateError {}
+
+impl From<BoundaryError> for StateError {
+    fn from(error: BoundaryError) -> Self {
+        StateError::Boundary(error)
+    }
+}
+
+impl From<SymmetryError> for StateError {
+    fn from(error: SymmetryError) -> Self {
+        StateError::Symmetry(error)
+    }
+}
+
+impl From<HeightsError> for StateError {
+    fn from(error: HeightsError) -> Self {
+        StateError::Heights(error)
+    }
+}
+
+impl TilingState {
+    pub fn heights_to_csv(&self) -> String {
+        let mut csv = String::from("x,y,height\n");
+        for [x, y, height] in self.heights.iter() {
+            csv.push_str(&format!("{},{},{}\n", x, y, height));
+        }
+        csv
+    }
+}
+
+impl PeriodicLozengeTiling {
+    pub fn to_state(&self) -> TilingState {
+        let mut heights = self
+            .data
+            .iter()
+            .map(|(Vector2(x, y), height)| [*x, *y, *height])
+            .collect::<Vec<_>>();
+        heights.sort_unstable();
+
+        TilingState {
+            periods: self.periods,
+            draw_distance: self.draw_distance,
+            heights,
+            obstacles: self.get_obstacles(),
+            boundary_conditions: self.boundary_conditions,
+            symmetry: self.symmetry,
+        }
+    }
+
+    // Goes through the same checks as setting the boundary conditions, symmetry and
+    // heights one by one, so a hand edited or corrupted state can't make an illegal
+    // tiling
+    pub fn from_state(state: &TilingState) -> Result<PeriodicLozengeTiling, StateError> {
+        let LozengeTilingPeriods {
+            x_shift,
+            y_shift,
+            z_height,
+        } = state.periods;
+        let DrawDistance { x, y, z } = state.draw_distance;
+
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(x_shift, y_shift, z_height, x, y, z);
+        lozenge_tiling.set_obstacles(&state.obstacles)?;
+        lozenge_tiling.set_boundary_conditions(state.boundary_conditions)?;
+        lozenge_tiling.set_symmetry(state.symmetry)?;
+        lozenge_tiling.set_heights(&state.heights)?;
+
+        Ok(lozenge_tiling)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StateError;
+    use crate::{
+        Boundary, BoundaryConditions, BoundaryError, HeightsError, PeriodicLozengeTiling,
+        SymmetryClass, SymmetryError,
+    };
+
+    #[test]
+    fn restored_tiling_has_same_boxes_and_moves() {
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(1, 2, 3, 10, 10, 10);
+        lozenge_tiling.generate_with_markov_chain(500, 0.9);
+
+        let restored = PeriodicLozengeTiling::from_state(&lozenge_tiling.to_state()).unwrap();
+
+        assert_eq!(restored.to_state(), lozenge_tiling.to_state());
+        assert_eq!(restored.get_box_voxels(), lozenge_tiling.get_box_voxels());
+        assert_eq!(
+            restored.addable_boxes_count(),
+            lozenge_tiling.addable_boxes_count()
+        );
+        assert_eq!(
+            restored.removable_boxes_count(),
+            lozenge_tiling.removable_boxes_count()
+        );
+        for vector in lozenge_tiling.addable_boxes.iter() {
+            assert!(restored.addable_boxes.iter().any(|v| v == vector));
+        }
+    }
+
+    #[test]
+    fn state_survives_json_round_trip() {
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(0, 0, 3, 5, 5, 5);
+        lozenge_tiling.generate_by_adding_only(10);
+        let state = lozenge_tiling.to_state();
+
+        let json = serde_json::to_string(&state).unwrap();
+
+        assert_eq!(
+            serde_json::from_str::<crate::TilingState>(&json).unwrap(),
+            state
+        );
+    }
+
+    #[test]
+    fn rejects_states_the_setters_would_reject() {
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(0, 0, 4, 6, 6, 6);
+        lozenge_tiling.generate_by_adding_only(30);
+        let state = lozenge_tiling.to_state();
+
+        let mut shifted = state.clone();
+        shifted.periods.x_shift = 1;
+        shifted.boundary_conditions.x = Boundary::Cut;
+        assert_eq!(
+            PeriodicLozengeTiling::from_state(&shifted).unwrap_err(),
+            StateError::Boundary(BoundaryError::NonZeroShift)
+        );
+
+        let mut symmetric = state.clone();
+        symmetric.boundary_conditions.y = Boundary::Cut;
+        symmetric.symmetry = Some(SymmetryClass::Transpose);
+        assert_eq!(
+            PeriodicLozengeTiling::from_state(&symmetric).unwrap_err(),
+            StateError::Symmetry(SymmetryError::BoundaryConditions)
+        );
+
+        let mut cut = state.clone();
+        cut.heights = vec![[0, 0, 0], [0, 1, 0]];
+        cut.boundary_conditions = BoundaryConditions {
+            z: Boundary::Cut,
+            ..Default::default()
+        };
+        assert_eq!(
+            PeriodicLozengeTiling::from_state(&cut).unwrap_err(),
+            StateError::Heights(HeightsError::OutsideBoundary { x: 0, y: 1 })
+        );
+
+        let mut asymmetric = state;
+        asymmetric.heights = vec![[0, 0, 1], [0, 1, 0]];
+        asymmetric.symmetry = Some(SymmetryClass::Transpose);
+        assert_eq!(
+            PeriodicLozengeTiling::from_state(&asymmetric).unwrap_err(),
+            StateError::Heights(HeightsError::NotSymmetric { x: 0, y: 1 })
+        );
+    }
+
+    #[test]
+    fn state_round_trips_boundary_conditions() {
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(0, 0, 6, 8, 8, 8);
+        lozenge_tiling.set_seed(4);
+        lozenge_tiling
+            .set_boundary_conditions(BoundaryConditions {
+                x: Boundary::Cut,
+                z: Boundary::MirrorCut,
+                ..Default::default()
+            })
+            .unwrap();
+        lozenge_tiling.generate_with_markov_chain(3000, 0.95);
+        let state = lozenge_tiling.to_state();
+
+        let restored = PeriodicLozengeTiling::from_state(&state).unwrap();
+
+        assert_eq!(restored.to_state(), state);
+        assert_eq!(
+            restored.get_boundary_conditions(),
+            lozenge_tiling.get_boundary_conditions()
+        );
+        assert_eq!(
+            restored.addable_boxes_count(),
+            lozenge_tiling.addable_boxes_count()
+        );
+        assert_eq!(
+            restored.removable_boxes_count(),
+            lozenge_tiling.removable_boxes_count()
+        );
+        lozenge_tiling.set_heights(&state.heights).unwrap();
+        assert_eq!(lozenge_tiling.to_state(), state);
+    }
+
+    #[test]
+    fn state_round_trips_symmetry() {
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(0, 0, 2, 4, 4, 4);
+        lozenge_tiling
+            .set_symmetry(Some(SymmetryClass::SelfComplementary {
+                x_size: 2,
+                y_size: 1,
+            }))
+            .unwrap();
+        let state = lozenge_tiling.to_state();
+
+        let mut restored = PeriodicLozengeTiling::from_state(&state).unwrap();
+
+        assert_eq!(restored.to_state(), state);
+        assert_eq!(restored.get_symmetry(), lozenge_tiling.get_symmetry());
+        // restored tilings keep moving whole orbits
+        restored.generate_with_markov_chain(200, 0.9);
+        assert_eq!(restored.get_period_box_count(), 2);
+    }
+}
@@ -0,0 +1,130 @@
+use crate::{Algorithm, PeriodicLozengeTiling};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VolumeStatistics {
+    pub samples: usize,
+    pub mean: f64,
+    pub variance: f64,
+    // lag 1 autocorrelation of consecutive samples
+    pub autocorrelation: f64,
+    // 1 + 2 * sum of autocorrelations up to the first non-positive one
+    pub integrated_autocorrelation_time: f64,
+}
+
+fn get_autocorrelation(values: &[f64], mean: f64, variance: f64, lag: usize) -> f64 {
+    if variance == 0.0 || lag >= values.len() {
+        return 0.0;
+    }
+
+    let covariance = values
+        .iter()
+        .zip(values.iter().skip(lag))
+        .map(|(a, b)| (a - mean) * (b - mean))
+        .sum::<f64>()
+        / (values.len() - lag) as f64;
+
+    covariance / variance
+}
+
+impl VolumeStatistics {
+    pub fn from_samples(volumes: &[i32]) -> VolumeStatistics {
+        let values = volumes.iter().map(|v| f64::from(*v)).collect::<Vec<_>>();
+        let samples = values.len();
+
+        if samples == 0 {
+            return VolumeStatistics {
+                samples,
+                mean: 0.0,
+                variance: 0.0,
+                autocorrelation: 0.0,
+                integrated_autocorrelation_time: 1.0,
+            };
+        }
+
+        let mean = values.iter().sum::<f64>() / samples as f64;
+        let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / samples as f64;
+
+        let mut integrated_autocorrelation_time = 1.0;
+        for lag in 1..samples {
+            let autocorrelation = get_autocorrelation(&values, mean, variance, lag);
+            if autocorrelation <= 0.0 {
+                break;
+            }
+            integrated_autocorrelation_time += 2.0 * autocorrelation;
+        }
+
+        VolumeStatistics {
+            samples,
+            mean,
+            variance,
+            autocorrelation: get_autocorrelation(&values, mean, variance, 1),
+            integrated_autocorrelation_time,
+        }
+    }
+
+    // standard error of the mean corrected for autocorrelation
+    pub fn standard_error(&self) -> f64 {
+        if self.samples == 0 {
+            return 0.0;
+        }
+        (self.variance * self.integrated_autocorrelation_time / self.samples as f64).sqrt()
+    }
+}
+
+impl PeriodicLozengeTiling {
+    // Runs the algorithm and records the period volume after every sample_every iterations.
+    pub fn sample_volumes(
+        &mut self,
+        algorithm: Algorithm,
+        iterations: i32,
+        q: f32,
+        sample_every: i32,
+    ) -> Vec<i32> {
+        let sample_every = sample_every.max(1);
+        let mut volumes = Vec::new();
+        let mut steps_done = 0;
+
+        while steps_done < iterations {
+            let steps = sample_every.min(iterations - steps_done);
+            self.generate(algorithm, steps, q);
+            steps_done += steps;
+            volumes.push(self.get_period_box_count());
+        }
+
+        volumes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{stats::VolumeStatistics, Algorithm, PeriodicLozengeTiling};
+
+    #[test]
+    fn samples_volume_after_every_sample_interval() {
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(1, 2, 3, 10, 10, 10);
+        let volumes = lozenge_tiling.sample_volumes(Algorithm::AddingOnly, 25, 1.0, 10);
+        assert_eq!(volumes, vec![10, 20, 25]);
+    }
+
+    #[test]
+    fn computes_mean_and_variance() {
+        let statistics = VolumeStatistics::from_samples(&[1, 2, 3, 4]);
+        assert_eq!(statistics.samples, 4);
+        assert_eq!(statistics.mean, 2.5);
+        assert_eq!(statistics.variance, 1.25);
+    }
+
+    #[test]
+    fn alternating_samples_are_anticorrelated() {
+        let statistics = VolumeStatistics::from_samples(&[0, 2, 0, 2, 0, 2]);
+        assert!(statistics.autocorrelation < 0.0);
+        assert_eq!(statistics.integrated_autocorrelation_time, 1.0);
+    }
+
+    #[test]
+    fn constant_samples_have_no_variance() {
+        let statistics = VolumeStatistics::from_samples(&[3, 3, 3]);
+        assert_eq!(statistics.variance, 0.0);
+        assert_eq!(statistics.autocorrelation, 0.0);
+    }
+}
@@ -0,0 +1,162 @@
+use std::fmt::Write;
+
+use crate::{
+    faces::{project_isometric, Face, FaceKind, FaceOrientation},
+    PeriodicLozengeTiling,
+};
+
+#[derive(Debug, Clone)]
+pub struct SvgOptions {
+    // length of a lozenge side in pixels
+    pub scale: f64,
+    pub stroke_width: f64,
+    // fill colors for X, Y and Z faces
+    pub box_colors: [String; 3],
+    pub wall_colors: [String; 3],
+    pub stroke_color: String,
+}
+
+impl Default for SvgOptions {
+    fn default() -> Self {
+        SvgOptions {
+            scale: 20.0,
+            stroke_width: 1.0,
+            box_colors: [
+                "#e05a47".to_string(),
+                "#f2a541".to_string(),
+                "#f6e8c3".to_string(),
+            ],
+            wall_colors: [
+                "#3f6fb5".to_string(),
+                "#6a9bd8".to_string(),
+                "#c3d7f0".to_string(),
+            ],
+            stroke_color: "#222222".to_string(),
+        }
+    }
+}
+
+impl SvgOptions {
+    // Flat 2D lozenge picture, faces colored by orientation only
+    pub fn lozenges() -> Self {
+        let default = SvgOptions::default();
+        SvgOptions {
+            wall_colors: default.box_colors.clone(),
+            ..default
+        }
+    }
+
+    fn get_fill(&self, face: &Face) -> &str {
+        let colors = match face.kind {
+            FaceKind::Box => &self.box_colors,
+            FaceKind::Wall => &self.wall_colors,
+        };
+        match face.orientation {
+            FaceOrientation::X => &colors[0],
+            FaceOrientation::Y => &colors[1],
+            FaceOrientation::Z => &colors[2],
+        }
+    }
+}
+
+// Writes faces as lozenges of an svg image sized to fit them.
+pub fn faces_to_svg(faces: &[Face], options: &SvgOptions) -> String {
+    let polygons = faces
+        .iter()
+        .map(|face| {
+            let points = face.corners().map(|corner| {
+                let (u, v) = project_isometric(&corner);
+                (u * options.scale, -v * options.scale)
+            });
+            (face, points)
+        })
+        .collect::<Vec<_>>();
+
+    let (mut min_x, mut min_y, mut max_x, mut max_y) = (0.0f64, 0.0f64, 0.0f64, 0.0f64);
+    for (_, points) in polygons.iter() {
+        for (x, y) in points {
+            min_x = min_x.min(*x);
+            min_y = min_y.min(*y);
+            max_x = max_x.max(*x);
+            max_y = max_y.max(*y);
+        }
+    }
+    let margin = options.stroke_width;
+
+    let mut svg = String::new();
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="{:.2} {:.2} {:.2} {:.2}" width="{:.0}" height="{:.0}">"#,
+        min_x - margin,
+        min_y - margin,
+        max_x - min_x + 2.0 * margin,
+        max_y - min_y + 2.0 * margin,
+        (max_x - min_x + 2.0 * margin).ceil(),
+        (max_y - min_y + 2.0 * margin).ceil(),
+    )
+    .unwrap();
+    writeln!(
+        svg,
+        r#"<g stroke="{}" stroke-width="{}" stroke-linejoin="round">"#,
+        options.stroke_color, options.stroke_width
+    )
+    .unwrap();
+
+    for (face, points) in polygons.iter() {
+        let points = points
+            .iter()
+            .map(|(x, y)| format!("{:.2},{:.2}", x, y))
+            .collect::<Vec<_>>()
+            .join(" ");
+        writeln!(
+            svg,
+            r#"<polygon points="{}" fill="{}"/>"#,
+            points,
+            options.get_fill(face)
+        )
+        .unwrap();
+    }
+
+    svg.push_str("</g>\n</svg>\n");
+    svg
+}
+
+impl PeriodicLozengeTiling {
+    pub fn to_svg(&self, options: &SvgOptions) -> String {
+        faces_to_svg(&self.get_visible_faces(), options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{svg::SvgOptions, PeriodicLozengeTiling};
+
+    #[test]
+    fn svg_has_polygon_for_every_visible_face() {
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(1, 2, 3, 4, 4, 4);
+        lozenge_tiling.generate_by_adding_only(10);
+
+        let svg = lozenge_tiling.to_svg(&SvgOptions::default());
+
+        assert!(svg.starts_with("<svg"));
+        assert_eq!(
+            svg.matches("<polygon").count(),
+            lozenge_tiling.get_visible_faces().len()
+        );
+    }
+
+    #[test]
+    fn lozenges_have_one_color_per_orientation() {
+        let lozenge_tiling = PeriodicLozengeTiling::new(1, 2, 3, 4, 4, 4);
+
+        let svg = lozenge_tiling.to_svg(&SvgOptions::lozenges());
+
+        let options = SvgOptions::default();
+        for color in options.box_colors.iter() {
+            assert!(svg.contains(color.as_str()));
+        }
+        for color in options.wall_colors.iter() {
+            assert!(!svg.contains(color.as_str()));
+        }
+    }
+}
@@ -0,0 +1,387 @@
+use std::time::Instant;
+
+use rand::random;
+use rustc_hash::FxHashSet;
+use serde::{Deserialize, Serialize};
+
+use crate::{stats::VolumeStatistics, Algorithm, PeriodicLozengeTiling};
+
+// Draw distance does not influence sampling, voxels are never requested during a sweep.
+const SWEEP_DRAW_DISTANCE: i32 = 1;
+
+pub trait SweepValue: Copy {
+    fn get_range(start: Self, end: Self, step: Self) -> Vec<Self>;
+}
+
+impl SweepValue for i32 {
+    fn get_range(start: i32, end: i32, step: i32) -> Vec<i32> {
+        (start..=end).step_by(step.max(1) as usize).collect()
+    }
+}
+
+impl SweepValue for f32 {
+    // end is included when it is a whole number of steps from start
+    fn get_range(start: f32, end: f32, step: f32) -> Vec<f32> {
+        if step <= 0.0 || end < start {
+            return vec![start];
+        }
+        let steps = ((end - start) / step + 1e-4).floor() as i32;
+        (0..=steps)
+            .map(|i| (f64::from(start) + f64::from(i) * f64::from(step)) as f32)
+            .collect()
+    }
+}
+
+// A single value, a list of values or an inclusive range.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum SweepValues<T> {
+    Single(T),
+    List(Vec<T>),
+    Range { start: T, end: T, step: T },
+}
+
+impl<T: SweepValue> SweepValues<T> {
+    pub fn get_values(&self) -> Vec<T> {
+        match self {
+            SweepValues::Single(value) => vec![*value],
+            SweepValues::List(values) => values.clone(),
+            SweepValues::Range { start, end, step } => T::get_range(*start, *end, *step),
+        }
+    }
+}
+
+fn default_repetitions() -> u32 {
+    1
+}
+
+fn default_sample_every() -> i32 {
+    100
+}
+
+fn default_algorithm() -> Algorithm {
+    Algorithm::MarkovChain
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SweepSpec {
+    pub x_shift: SweepValues<i32>,
+    pub y_shift: SweepValues<i32>,
+    pub z_height: SweepValues<i32>,
+    pub q: SweepValues<f32>,
+    // iterations sampled after burn in
+    pub iterations: SweepValues<i32>,
+    #[serde(default = "default_repetitions")]
+    pub repetitions: u32,
+    #[serde(default)]
+    pub burn_in: i32,
+    #[serde(default = "default_sample_every")]
+    pub sample_every: i32,
+    #[serde(default = "default_algorithm")]
+    pub algorithm: Algorithm,
+    // runs are seeded from this seed, random seeds are used when missing
+    pub seed: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SweepPoint {
+    pub x_shift: i32,
+    pub y_shift: i32,
+    pub z_height: i32,
+    pub q: f32,
+    pub iterations: i32,
+}
+
+// Identifies a finished run so an interrupted sweep can be resumed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SweepKey {
+    x_shift: i32,
+    y_shift: i32,
+    z_height: i32,
+    q_bits: u32,
+    iterations: i32,
+    repetition: u32,
+}
+
+impl SweepPoint {
+    fn get_key(&self, repetition: u32) -> SweepKey {
+        SweepKey {
+            x_shift: self.x_shift,
+            y_shift: self.y_shift,
+            z_height: self.z_height,
+            q_bits: self.q.to_bits(),
+            iterations: self.iterations,
+            repetition,
+        }
+    }
+}
+
+impl SweepSpec {
+    pub fn get_points(&self) -> Vec<SweepPoint> {
+        let mut points = Vec::new();
+        for x_shift in self.x_shift.get_values() {
+            for y_shift in self.y_shift.get_values() {
+                for z_height in self.z_height.get_values() {
+                    for q in self.q.get_values() {
+                        for iterations in self.iterations.get_values() {
+                            points.push(SweepPoint {
+                                x_shift,
+                                y_shift,
+                                z_height,
+                                q,
+                                iterations,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        points
+    }
+
+    pub fn get_runs_count(&self) -> usize {
+        self.get_points().len() * self.repetitions as usize
+    }
+
+    fn get_seed(&self, point_index: usize, repetition: u32) -> u64 {
+        match self.seed {
+            Some(seed) => seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(((point_index as u64) << 32) | u64::from(repetition)),
+            None => random(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SweepRow {
+    pub x_shift: i32,
+    pub y_shift: i32,
+    pub z_height: i32,
+    pub q: f32,
+    pub iterations: i32,
+    pub repetition: u32,
+    pub seed: u64,
+    pub samples: usize,
+    pub volume_mean: f64,
+    pub volume_variance: f64,
+    pub autocorrelation: f64,
+    pub integrated_autocorrelation_time: f64,
+    pub seconds: f64,
+}
+
+pub const SWEEP_CSV_HEADER: &str = "x_shift,y_shift,z_height,q,iterations,repetition,seed,samples,volume_mean,volume_variance,autocorrelation,integrated_autocorrelation_time,seconds";
+
+impl SweepRow {
+    pub fn get_key(&self) -> SweepKey {
+        SweepPoint {
+            x_shift: self.x_shift,
+            y_shift: self.y_shift,
+            z_height: self.z_height,
+            q: self.q,
+            iterations: self.iterations,
+        }
+        .get_key(self.repetition)
+    }
+
+    pub fn to_csv_line(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            self.x_shift,
+            self.y_shift,
+            self.z_height,
+            self.q,
+            self.iterations,
+            self.repetition,
+            self.seed,
+            self.samples,
+            self.volume_mean,
+            self.volume_variance,
+            self.autocorrelation,
+            self.integrated_autocorrelation_time,
+            self.seconds
+        )
+    }
+
+    // None for lines that are not complete rows, e.g. the header or a line cut by an interruption
+    pub fn from_csv_line(line: &str) -> Option<SweepRow> {
+        let values = line.trim().split(',').collect::<Vec<_>>();
+        if values.len() != SWEEP_CSV_HEADER.split(',').count() {
+            return None;
+        }
+
+        Some(SweepRow {
+            x_shift: values[0].parse().ok()?,
+            y_shift: values[1].parse().ok()?,
+            z_height: values[2].parse().ok()?,
+            q: values[3].parse().ok()?,
+            iterations: values[4].parse().ok()?,
+            repetition: values[5].parse().ok()?,
+            seed: values[6].parse().ok()?,
+            samples: values[7].parse().ok()?,
+            volume_mean: values[8].parse().ok()?,
+            volume_variance: values[9].parse().ok()?,
+            autocorrelation: values[10].parse().ok()?,
+            integrated_autocorrelation_time: values[11].parse().ok()?,
+            seconds: values[12].parse().ok()?,
+        })
+    }
+
+    pub fn to_json_line(&self) -> String {
+        serde_json::to_string(self).expect("sweep row is always serializable")
+    }
+
+    pub fn from_json_line(line: &str) -> Option<SweepRow> {
+        serde_json::from_str(line).ok()
+    }
+}
+
+fn run_sweep_point(spec: &SweepSpec, point: &SweepPoint, repetition: u32, seed: u64) -> SweepRow {
+    let start = Instant::now();
+
+    let mut lozenge_tiling = PeriodicLozengeTiling::new(
+        point.x_shift,
+        point.y_shift,
+        point.z_height,
+        SWEEP_DRAW_DISTANCE,
+        SWEEP_DRAW_DISTANCE,
+        SWEEP_DRAW_DISTANCE,
+    );
+    lozenge_tiling.set_seed(seed);
+    lozenge_tiling.generate(spec.algorithm, spec.burn_in, point.q);
+    let volumes =
+        lozenge_tiling.sample_volumes(spec.algorithm, point.iterations, point.q, spec.sample_every);
+    let statistics = VolumeStatistics::from_samples(&volumes);
+
+    SweepRow {
+        x_shift: point.x_shift,
+        y_shift: point.y_shift,
+        z_height: point.z_height,
+        q: point.q,
+        iterations: point.iterations,
+        repetition,
+        seed,
+        samples: statistics.samples,
+        volume_mean: statistics.mean,
+        volume_variance: statistics.variance,
+        autocorrelation: statistics.autocorrelation,
+        integrated_autocorrelation_time: statistics.integrated_autocorrelation_time,
+        seconds: start.elapsed().as_secs_f64(),
+    }
+}
+
+// Runs every point and repetition that is not completed yet and hands each row to on_row
+// as soon as it is done. Returns the number of runs done.
+pub fn run_sweep<E, F>(
+    spec: &SweepSpec,
+    completed: &FxHashSet<SweepKey>,
+    mut on_row: F,
+) -> Result<usize, E>
+where
+    F: FnMut(&SweepRow) -> Result<(), E>,
+{
+    let mut runs = 0;
+
+    for (point_index, point) in spec.get_points().iter().enumerate() {
+        for repetition in 0..spec.repetitions {
+            if completed.contains(&point.get_key(repetition)) {
+                continue;
+            }
+
+            let seed = spec.get_seed(point_index, repetition);
+            on_row(&run_sweep_point(spec, point, repetition, seed))?;
+            runs += 1;
+        }
+    }
+
+    Ok(runs)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use rustc_hash::FxHashSet;
+
+    use crate::sweep::{run_sweep, SweepRow, SweepSpec, SweepValues};
+
+    fn get_spec() -> SweepSpec {
+        serde_json::from_str(
+            r#"{
+                "x_shift": 1,
+                "y_shift": [1, 2],
+                "z_height": { "start": 2, "end": 4, "step": 2 },
+                "q": { "start": 0.5, "end": 0.7, "step": 0.1 },
+                "iterations": 200,
+                "repetitions": 2,
+                "sample_every": 20,
+                "seed": 1
+            }"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn expands_values_and_ranges() {
+        let spec = get_spec();
+        assert_eq!(spec.z_height.get_values(), vec![2, 4]);
+        assert_eq!(spec.q.get_values().len(), 3);
+        assert_eq!(SweepValues::Single(3).get_values(), vec![3]);
+        assert_eq!(spec.get_runs_count(), 2 * 2 * 3 * 2);
+    }
+
+    #[test]
+    fn resumes_from_completed_rows() {
+        let spec = get_spec();
+        let mut rows = Vec::new();
+        run_sweep(&spec, &FxHashSet::default(), |row| {
+            rows.push(row.clone());
+            Ok::<_, Infallible>(())
+        })
+        .unwrap();
+        assert_eq!(rows.len(), spec.get_runs_count());
+        assert!(rows.iter().all(|row| row.samples == 10));
+
+        let completed = rows[..5].iter().map(|row| row.get_key()).collect();
+        let mut resumed = Vec::new();
+        run_sweep(&spec, &completed, |row| {
+            resumed.push(row.clone());
+            Ok::<_, Infallible>(())
+        })
+        .unwrap();
+
+        // same seeds, so the remaining runs repeat exactly
+        assert_eq!(
+            resumed.iter().map(|row| row.get_key()).collect::<Vec<_>>(),
+            rows[5..]
+                .iter()
+                .map(|row| row.get_key())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(resumed[0].volume_mean, rows[5].volume_mean);
+    }
+
+    #[test]
+    fn rows_survive_csv_and_json_lines() {
+        let spec = get_spec();
+        let mut rows = Vec::new();
+        run_sweep(&spec, &FxHashSet::default(), |row| {
+            rows.push(row.clone());
+            Ok::<_, Infallible>(())
+        })
+        .unwrap();
+
+        for row in rows.iter() {
+            assert_eq!(
+                SweepRow::from_csv_line(&row.to_csv_line()).as_ref(),
+                Some(row)
+            );
+            assert_eq!(
+                SweepRow::from_json_line(&row.to_json_line()).as_ref(),
+                Some(row)
+            );
+        }
+        assert_eq!(SweepRow::from_csv_line("1,2,3"), None);
+    }
+}
@@ -0,0 +1,387 @@
+use std::{error::Error, fmt};
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    box_move::BoxMove, vector2::Vector2, vector3::Vector3, LozengeTilingPeriods,
+    PeriodicLozengeTiling,
+};
+
+// Symmetry classes of plane partitions, the tiling then only moves whole orbits of boxes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "class", rename_all = "kebab-case")]
+pub enum SymmetryClass {
+    // (x, y, z) and (y, x, z)
+    Transpose,
+    // (x, y, z), (y, z, x) and (z, x, y)
+    Cyclic,
+    // the complement of the partition in the x_size by y_size by z_height box is the
+    // partition rotated by half a turn
+    SelfComplementary { x_size: i32, y_size: i32 },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SymmetryError {
+    // symmetries need x_shift = y_shift = 0
+    NonZeroShift,
+    // self-complementary needs a box with positive sides, z_height included
+    EmptyBox,
+    // a box with an odd number of cells has no self-complementary partition
+    OddVolume,
+    // cut sides already restrict the domain
+    BoundaryConditions,
+}
+
+impl fmt::Display for SymmetryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SymmetryError::NonZeroShift => write!(f, "symmetries need zero x and y shifts"),
+            SymmetryError::EmptyBox => {
+                write!(
+                    f,
+                    "self-complementary needs positive box sides and z period"
+                )
+            }
+            SymmetryError::OddVolume => write!(f, "the box has an odd number of cells"),
+            SymmetryError::BoundaryConditions => {
+                write!(f, "symmetries can't be combined with cut sides")
+            }
+        }
+    }
+}
+
+impl Error for SymmetryError {}
+
+impl PeriodicLozengeTiling {
+    pub fn get_symmetry(&self) -> Option<SymmetryClass> {
+        self.symmetry
+    }
+
+    // Restricts the chains to orbits of the symmetry, or lifts the restriction with None.
+    // With a symmetry the tiling restarts from the smallest symmetric configuration:
+    // empty, or for self-complementary the box half filled along an even side.
+    pub fn set_symmetry(&mut self, symmetry: Option<SymmetryClass>) -> Result<(), SymmetryError> {
+        let LozengeTilingPeriods {
+            x_shift,
+            y_shift,
+            z_height,
+        } = self.periods;
+
+        if symmetry.is_some() && (x_shift != 0 || y_shift != 0) {
+            return Err(SymmetryError::NonZeroShift);
+        }
+        if symmetry.is_some() && !self.boundary_conditions.is_fixed() {
+            return Err(SymmetryError::BoundaryConditions);
+        }
+        if let Some(SymmetryClass::SelfComplementary { x_size, y_size }) = symmetry {
+            if x_size <= 0 || y_size <= 0 || z_height <= 0 {
+                return Err(SymmetryError::EmptyBox);
+            }
+            if x_size * y_size * z_height % 2 != 0 {
+                return Err(SymmetryError::OddVolume);
+            }
+        }
+
+        self.symmetry = symmetry;
+        if symmetry.is_some() {
+            self.reset_to_symmetric_start();
+        }
+        Ok(())
+    }
+
+    pub(crate) fn reset_to_symmetric_start(&mut self) {
+        let mut heights = Vec::new();
+        if let Some(SymmetryClass::SelfComplementary { x_size, y_size }) = self.symmetry {
+            let z_height = self.periods.z_height;
+            for x in 0..x_size {
+                for y in 0..y_size {
+                    let height = if x_size % 2 == 0 {
+                        if x < x_size / 2 {
+                            z_height
+                        } else {
+                            0
+                        }
+                    } else if y_size % 2 == 0 {
+                        if y < y_size / 2 {
+                            z_height
+                        } else {
+                            0
+                        }
+                    } else {
+                        z_height / 2
+                    };
+                    if height > 0 {
+                        heights.push([x, y, height - 1]);
+                    }
+                }
+            }
+        }
+        self.set_heights(&heights)
+            .expect("the symmetric start is a legal configuration");
+    }
+
+    // First column whose heights break the symmetry
+    pub(crate) fn find_asymmetric_column(&self) -> Option<Vector2> {
+        let symmetry = self.symmetry?;
+        let count = |column: &Vector2| self.data.get(column) + 1;
+
+        match symmetry {
+            SymmetryClass::Transpose => self
+                .data
+                .iter()
+                .map(|(column, _)| *column)
+                .find(|Vector2(x, y)| count(&Vector2(*x, *y)) != count(&Vector2(*y, *x))),
+            // every box has its rotation, the rotation is a bijection so that's enough
+            SymmetryClass::Cyclic => self.data.iter().find_map(|(column, height)| {
+                let Vector2(x, y) = *column;
+                (0..=*height)
+                    .any(|z| !self.is_box(&Vector3(y, z, x)))
+                    .then_some(*column)
+            }),
+            SymmetryClass::SelfComplementary { x_size, y_size } => {
+                let z_height = self.periods.z_height;
+                let outside = self
+                    .data
+                    .iter()
+                    .map(|(column, _)| *column)
+                    .find(|Vector2(x, y)| *x >= x_size || *y >= y_size);
+                outside.or_else(|| {
+                    (0..x_size)
+                        .flat_map(|x| (0..y_size).map(move |y| Vector2(x, y)))
+                        .find(|Vector2(x, y)| {
+                            let complement = Vector2(x_size - 1 - x, y_size - 1 - y);
+                            count(&Vector2(*x, *y)) + count(&complement) != z_height
+                        })
+                })
+            }
+        }
+    }
+
+    // Moves of the orbit of a box picked from the addable (add) or removable boxes
+    fn get_orbit_moves(
+        &self,
+        symmetry: SymmetryClass,
+        position: Vector3,
+        add: bool,
+    ) -> Vec<BoxMove> {
+        let box_move = |position| match add {
+            true => BoxMove::Add(position),
+            false => BoxMove::Remove(position),
+        };
+        let Vector3(x, y, z) = position;
+
+        let mut positions = match symmetry {
+            SymmetryClass::Transpose => vec![position, Vector3(y, x, z)],
+            SymmetryClass::Cyclic => vec![position, Vector3(y, z, x), Vector3(z, x, y)],
+            SymmetryClass::SelfComplementary { x_size, y_size } => {
+                let complement = Vector3(
+                    x_size - 1 - x,
+                    y_size - 1 - y,
+                    self.periods.z_height - 1 - z,
+                );
+                // a box and its complement change in opposite directions
+                let complement_move = match add {
+                    true => BoxMove::Remove(complement),
+                    false => BoxMove::Add(complement),
+                };
+                return vec![box_move(position), complement_move];
+            }
+        };
+        positions.sort_unstable_by_key(|Vector3(x, y, z)| (*x, *y, *z));
+        positions.dedup();
+        positions.into_iter().map(box_move).collect()
+    }
+
+    // The moves are legal one by one and don't touch each other, so they can be made in
+    // any order. Self-complementary moves also stay inside the box.
+    fn can_apply_orbit(&self, moves: &[BoxMove]) -> bool {
+        let position = |box_move: &BoxMove| match box_move {
+            BoxMove::Add(position) | BoxMove::Remove(position) => *position,
+        };
+
+        if let Some(SymmetryClass::SelfComplementary { x_size, y_size }) = self.symmetry {
+            let inside = |Vector3(x, y, _): Vector3| x >= 0 && x < x_size && y >= 0 && y < y_size;
+            if !moves.iter().all(|box_move| inside(position(box_move))) {
+                return false;
+            }
+        }
+
+        let legal = moves.iter().all(|box_move| match box_move {
+            BoxMove::Add(position) => self.can_add_box(position),
+            BoxMove::Remove(position) => self.can_remove_box(position),
+        });
+        let apart = moves.iter().enumerate().all(|(index, first)| {
+            moves[index + 1..].iter().all(|second| {
+                let Vector3(x1, y1, z1) = position(first);
+                let Vector3(x2, y2, z2) = position(second);
+                (x1 - x2).abs() + (y1 - y2).abs() + (z1 - z2).abs() > 1
+            })
+        });
+        legal && apart
+    }
+
+    fn get_orbit_volume_change(moves: &[BoxMove]) -> i32 {
+        moves
+            .iter()
+            .map(|box_move| match box_move {
+                BoxMove::Add(_) => 1,
+                BoxMove::Remove(_) => -1,
+            })
+            .sum()
+    }
+
+    // Picks a box like generate_with_markov_chain and moves its orbit. The race already
+    // weighs the addable side by q, the acceptance makes up the rest of q^volume change.
+    pub(crate) fn move_random_orbit(&mut self, add: bool, q: f32) {
+        let Some(symmetry) = self.symmetry else {
+            return;
+        };
+        let position = match add {
+            true => self.get_random_addable_box(),
+            false => self.get_random_removable_box(),
+        };
+        let Some(position) = position else {
+            return;
+        };
+
+        let moves = self.get_orbit_moves(symmetry, position, add);
+        if !self.can_apply_orbit(&moves) {
+            return;
+        }
+        let race_weight = if add { 1 } else { -1 };
+        let exponent = Self::get_orbit_volume_change(&moves) - race_weight;
+        let acceptance = q.powi(exponent);
+        if acceptance < 1.0 && self.rng.gen::<f32>() >= acceptance {
+            return;
+        }
+
+        self.apply_move_group(moves);
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub fn generate_by_adding_orbits_only(&mut self, iterations: i32) {
+        for _ in 0..iterations {
+            self.move_random_orbit(true, 1.0);
+        }
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub fn generate_with_symmetric_markov_chain(&mut self, iterations: i32, q: f32) {
+        for _ in 0..iterations {
+            let rn1 = self.rng.gen::<f32>();
+            let rn2 = self.rng.gen::<f32>();
+
+            let num1 = -(1.0 - rn1).ln() / self.addable_boxes_count() as f32 / q;
+            let num2 = -(1.0 - rn2).ln() / self.removable_boxes_count() as f32;
+
+            self.move_random_orbit(num1 < num2, q);
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl PeriodicLozengeTiling {
+    // JSON like {"class": "self-complementary", "x_size": 4, "y_size": 4}
+    #[wasm_bindgen(js_name = setSymmetry)]
+    pub fn set_symmetry_js(&mut self, symmetry: &str) -> Result<(), JsValue> {
+        let symmetry: SymmetryClass =
+            serde_json::from_str(symmetry).map_err(|error| JsValue::from(error.to_string()))?;
+        self.set_symmetry(Some(symmetry))
+            .map_err(|error| JsValue::from(error.to_string()))
+    }
+
+    #[wasm_bindgen(js_name = clearSymmetry)]
+    pub fn clear_symmetry_js(&mut self) {
+        self.set_symmetry(None).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SymmetryClass, SymmetryError};
+    use crate::{heights::HeightsError, PeriodicLozengeTiling};
+
+    #[test]
+    fn chain_keeps_every_symmetry() {
+        for (symmetry, z_height) in [
+            (SymmetryClass::Transpose, 5),
+            (SymmetryClass::Cyclic, 4),
+            (
+                SymmetryClass::SelfComplementary {
+                    x_size: 3,
+                    y_size: 4,
+                },
+                3,
+            ),
+        ] {
+            let mut lozenge_tiling = PeriodicLozengeTiling::new(0, 0, z_height, 6, 6, 6);
+            lozenge_tiling.set_seed(13);
+            lozenge_tiling.set_symmetry(Some(symmetry)).unwrap();
+
+            let mut volumes = Vec::new();
+            for _ in 0..40 {
+                lozenge_tiling.generate_with_symmetric_markov_chain(50, 0.9);
+                assert_eq!(lozenge_tiling.find_asymmetric_column(), None);
+                lozenge_tiling.generate_with_parallel_markov_chain(2, 0.9);
+                lozenge_tiling.add_random_box_js();
+                lozenge_tiling.remove_random_box_js();
+                assert_eq!(lozenge_tiling.find_asymmetric_column(), None);
+                volumes.push(lozenge_tiling.get_period_box_count());
+            }
+            match symmetry {
+                SymmetryClass::SelfComplementary { .. } => {
+                    assert!(volumes.iter().all(|volume| *volume == 18))
+                }
+                _ => assert!(volumes.iter().any(|volume| *volume > 1)),
+            }
+        }
+    }
+
+    #[test]
+    fn validator_rejects_asymmetric_heights() {
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(0, 0, 2, 4, 4, 4);
+        lozenge_tiling
+            .set_symmetry(Some(SymmetryClass::Transpose))
+            .unwrap();
+
+        assert_eq!(
+            lozenge_tiling.load_heights_matrix("2 1\n0 0\n"),
+            Err(HeightsError::NotSymmetric { x: 0, y: 1 })
+        );
+        lozenge_tiling.load_heights_matrix("2 1\n1 0\n").unwrap();
+        assert_eq!(lozenge_tiling.get_period_box_count(), 4);
+
+        lozenge_tiling
+            .set_symmetry(Some(SymmetryClass::SelfComplementary {
+                x_size: 2,
+                y_size: 1,
+            }))
+            .unwrap();
+        assert_eq!(lozenge_tiling.get_period_box_count(), 2);
+        assert_eq!(
+            lozenge_tiling.load_heights_matrix("2\n1\n"),
+            Err(HeightsError::NotSymmetric { x: 0, y: 0 })
+        );
+    }
+
+    #[test]
+    fn rejects_symmetry_without_a_box() {
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(1, 2, 3, 4, 4, 4);
+        assert_eq!(
+            lozenge_tiling.set_symmetry(Some(SymmetryClass::Cyclic)),
+            Err(SymmetryError::NonZeroShift)
+        );
+
+        lozenge_tiling.set_periods(0, 0, 3);
+        assert_eq!(
+            lozenge_tiling.set_symmetry(Some(SymmetryClass::SelfComplementary {
+                x_size: 3,
+                y_size: 1,
+            })),
+            Err(SymmetryError::OddVolume)
+        );
+        assert_eq!(lozenge_tiling.get_symmetry(), None);
+    }
+}
@@ -0,0 +1,342 @@
+use std::fmt::Write;
+
+use rustc_hash::FxHashMap;
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    faces::{project_isometric, Face, FaceKind, FaceOrientation},
+    raster::ColorScheme,
+    vector3::Vector3,
+    LozengeTilingPeriods, PeriodicLozengeTiling,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TikzStyle {
+    // flat lozenges in projected coordinates
+    #[default]
+    Lozenges,
+    // boxes in 3D coordinates with isometric unit vectors set on the picture, so
+    // the view can be changed in the output
+    Boxes,
+}
+
+#[derive(Debug, Clone)]
+pub struct TikzOptions {
+    pub style: TikzStyle,
+    // tikz units per lozenge side
+    pub scale: f64,
+    // fill faces with a color per orientation (and kind for boxes), white otherwise
+    pub color_by_type: bool,
+    // honeycomb graph dual to the lozenges with the edges of the matching highlighted
+    pub dimers: bool,
+    // outline of the faces of one period
+    pub fundamental_domain: bool,
+}
+
+impl Default for TikzOptions {
+    fn default() -> Self {
+        TikzOptions {
+            style: TikzStyle::Lozenges,
+            scale: 0.5,
+            color_by_type: true,
+            dimers: false,
+            fundamental_domain: false,
+        }
+    }
+}
+
+type Point = [f64; 3];
+
+fn to_point(vector: &Vector3) -> Point {
+    let Vector3(x, y, z) = *vector;
+    [f64::from(x), f64::from(y), f64::from(z)]
+}
+
+fn average(points: &[Point]) -> Point {
+    let mut sum = [0.0; 3];
+    for point in points {
+        for axis in 0..3 {
+            sum[axis] += point[axis];
+        }
+    }
+    sum.map(|value| value / points.len() as f64)
+}
+
+fn get_color_name(kind: FaceKind, orientation: FaceOrientation) -> &'static str {
+    match (kind, orientation) {
+        (FaceKind::Box, FaceOrientation::X) => "boxX",
+        (FaceKind::Box, FaceOrientation::Y) => "boxY",
+        (FaceKind::Box, FaceOrientation::Z) => "boxZ",
+        (FaceKind::Wall, FaceOrientation::X) => "wallX",
+        (FaceKind::Wall, FaceOrientation::Y) => "wallY",
+        (FaceKind::Wall, FaceOrientation::Z) => "wallZ",
+    }
+}
+
+// The two triangles of a lozenge: the corners of its short diagonal in the
+// projection together with one of the other corners each
+fn get_triangles(corners: &[Vector3; 4]) -> [[Point; 3]; 2] {
+    let projected = corners.map(|corner| project_isometric(&corner));
+    let length = |a: usize, b: usize| {
+        (projected[a].0 - projected[b].0).hypot(projected[a].1 - projected[b].1)
+    };
+    let points = corners.map(|corner| to_point(&corner));
+    let [a, b, c, d] = points;
+    if length(0, 2) < length(1, 3) {
+        [[a, c, b], [a, c, d]]
+    } else {
+        [[b, d, a], [b, d, c]]
+    }
+}
+
+// at most 4 decimals, integers without any
+fn format_number(value: f64) -> String {
+    let rounded = (value * 10000.0).round() / 10000.0;
+    format!("{}", rounded + 0.0)
+}
+
+struct TikzWriter<'a> {
+    options: &'a TikzOptions,
+    tikz: String,
+}
+
+impl TikzWriter<'_> {
+    fn format_point(&self, point: &Point) -> String {
+        match self.options.style {
+            TikzStyle::Lozenges => {
+                let u = (point[0] - point[1]) * 3f64.sqrt() / 2.0;
+                let v = point[2] - (point[0] + point[1]) / 2.0;
+                format!("({},{})", format_number(u), format_number(v))
+            }
+            TikzStyle::Boxes => format!(
+                "({},{},{})",
+                format_number(point[0]),
+                format_number(point[1]),
+                format_number(point[2])
+            ),
+        }
+    }
+
+    fn write_path(&mut self, style: &str, points: &[Point], cycle: bool) {
+        let mut path = points
+            .iter()
+            .map(|point| self.format_point(point))
+            .collect::<Vec<_>>()
+            .join(" -- ");
+        if cycle {
+            path.push_str(" -- cycle");
+        }
+        writeln!(self.tikz, "\\draw[{}] {};", style, path).unwrap();
+    }
+
+    fn write_colors(&mut self) {
+        let (box_colors, wall_colors) = ColorScheme::Default.get_colors();
+        let orientations = [FaceOrientation::X, FaceOrientation::Y, FaceOrientation::Z];
+        for (kind, colors) in [(FaceKind::Box, box_colors), (FaceKind::Wall, wall_colors)] {
+            for (orientation, [r, g, b]) in orientations.iter().zip(colors) {
+                writeln!(
+                    self.tikz,
+                    "\\definecolor{{{}}}{{HTML}}{{{:02X}{:02X}{:02X}}}",
+                    get_color_name(kind, *orientation),
+                    r,
+                    g,
+                    b
+                )
+                .unwrap();
+            }
+        }
+    }
+
+    fn write_face(&mut self, face: &Face) {
+        let fill = match (self.options.color_by_type, self.options.style) {
+            (false, _) => "white",
+            // a lozenge picture doesn't tell boxes from walls
+            (true, TikzStyle::Lozenges) => get_color_name(FaceKind::Box, face.orientation),
+            (true, TikzStyle::Boxes) => get_color_name(face.kind, face.orientation),
+        };
+        let points = face.corners().map(|corner| to_point(&corner));
+        self.write_path(&format!("fill={}", fill), &points, true);
+    }
+
+    fn write_dimers(&mut self, face: &Face) {
+        let triangles = get_triangles(&face.corners());
+        let centers = triangles.map(|triangle| average(&triangle));
+
+        // half edges of the honeycomb across the outer sides of both triangles
+        for (triangle, center) in triangles.iter().zip(centers) {
+            for corner in 0..2 {
+                let middle = average(&[triangle[corner], triangle[2]]);
+                self.write_path("honeycomb", &[center, middle], false);
+            }
+        }
+        self.write_path("dimer", &centers, false);
+        for center in centers {
+            writeln!(
+                self.tikz,
+                "\\fill {} circle[radius=0.06];",
+                self.format_point(&center)
+            )
+            .unwrap();
+        }
+    }
+}
+
+impl PeriodicLozengeTiling {
+    // A tikzpicture of the visible faces inside the draw box, needs \usepackage{tikz}
+    pub fn to_tikz(&self, options: &TikzOptions) -> String {
+        let faces = self.get_visible_faces();
+        let mut writer = TikzWriter {
+            options,
+            tikz: String::new(),
+        };
+
+        writeln!(writer.tikz, "% lozenge tiling, needs \\usepackage{{tikz}}").unwrap();
+        let scale = options.scale;
+        match options.style {
+            TikzStyle::Lozenges => writeln!(
+                writer.tikz,
+                "\\begin{{tikzpicture}}[scale={}, line join=round]",
+                scale
+            ),
+            TikzStyle::Boxes => writeln!(
+                writer.tikz,
+                "\\begin{{tikzpicture}}[x={{({:.4}cm,{:.4}cm)}}, y={{({:.4}cm,{:.4}cm)}}, z={{(0cm,{:.4}cm)}}, line join=round]",
+                scale * 3f64.sqrt() / 2.0,
+                -scale / 2.0,
+                -scale * 3f64.sqrt() / 2.0,
+                -scale / 2.0,
+                scale
+            ),
+        }
+        .unwrap();
+        writeln!(
+            writer.tikz,
+            "\\tikzset{{honeycomb/.style={{gray, very thin}}, dimer/.style={{line width=1.2pt}}, domain/.style={{red, line width=1.5pt}}}}"
+        )
+        .unwrap();
+        if options.color_by_type {
+            writer.write_colors();
+        }
+
+        for face in faces.iter() {
+            writer.write_face(face);
+        }
+        if options.dimers {
+            for face in faces.iter() {
+                writer.write_dimers(face);
+            }
+        }
+        if options.fundamental_domain {
+            for [a, b] in self.get_fundamental_domain_outline(&faces) {
+                writer.write_path("domain", &[to_point(&a), to_point(&b)], false);
+            }
+        }
+
+        writer.tikz.push_str("\\end{tikzpicture}\n");
+        writer.tikz
+    }
+
+    // Sides of the faces whose voxel is in the fundamental domain that aren't shared
+    // with another such face, empty without periodicity
+    fn get_fundamental_domain_outline(&self, faces: &[Face]) -> Vec<[Vector3; 2]> {
+        let LozengeTilingPeriods {
+            x_shift, y_shift, ..
+        } = self.periods;
+        if x_shift == 0 && y_shift == 0 {
+            return Vec::new();
+        }
+
+        let mut sides: FxHashMap<[(i32, i32, i32); 2], usize> = FxHashMap::default();
+        for face in faces {
+            if self.normalize3(&face.voxel) != face.voxel {
+                continue;
+            }
+            let corners = face.corners();
+            for index in 0..4 {
+                let Vector3(ax, ay, az) = corners[index];
+                let Vector3(bx, by, bz) = corners[(index + 1) % 4];
+                let mut side = [(ax, ay, az), (bx, by, bz)];
+                side.sort_unstable();
+                *sides.entry(side).or_insert(0) += 1;
+            }
+        }
+
+        let mut outline = sides
+            .into_iter()
+            .filter(|(_, count)| *count == 1)
+            .map(|(side, _)| side)
+            .collect::<Vec<_>>();
+        outline.sort_unstable();
+        outline
+            .into_iter()
+            .map(|side| side.map(|(x, y, z)| Vector3(x, y, z)))
+            .collect()
+    }
+}
+
+#[wasm_bindgen]
+impl PeriodicLozengeTiling {
+    #[wasm_bindgen(js_name = toTikz)]
+    pub fn to_tikz_js(
+        &self,
+        boxes: bool,
+        color_by_type: bool,
+        dimers: bool,
+        fundamental_domain: bool,
+    ) -> String {
+        self.to_tikz(&TikzOptions {
+            style: match boxes {
+                true => TikzStyle::Boxes,
+                false => TikzStyle::Lozenges,
+            },
+            color_by_type,
+            dimers,
+            fundamental_domain,
+            ..TikzOptions::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TikzOptions, TikzStyle};
+    use crate::PeriodicLozengeTiling;
+
+    #[test]
+    fn tikz_has_path_for_every_face_and_dimer() {
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(1, 2, 3, 3, 3, 3);
+        lozenge_tiling.generate_by_adding_only(10);
+        let faces_count = lozenge_tiling.get_visible_faces().len();
+
+        for style in [TikzStyle::Lozenges, TikzStyle::Boxes] {
+            let tikz = lozenge_tiling.to_tikz(&TikzOptions {
+                style,
+                dimers: true,
+                fundamental_domain: true,
+                ..TikzOptions::default()
+            });
+
+            assert!(tikz.contains("\\begin{tikzpicture}"));
+            assert!(tikz.ends_with("\\end{tikzpicture}\n"));
+            assert_eq!(tikz.matches("\\draw[fill=").count(), faces_count);
+            assert_eq!(tikz.matches("\\draw[dimer]").count(), faces_count);
+            assert_eq!(tikz.matches("\\draw[honeycomb]").count(), 4 * faces_count);
+            assert!(tikz.contains("\\draw[domain]"));
+        }
+    }
+
+    #[test]
+    fn no_fundamental_domain_without_periods() {
+        let lozenge_tiling = PeriodicLozengeTiling::new(0, 0, 0, 3, 3, 3);
+
+        let tikz = lozenge_tiling.to_tikz(&TikzOptions {
+            color_by_type: false,
+            fundamental_domain: true,
+            ..TikzOptions::default()
+        });
+
+        assert!(!tikz.contains("\\draw[domain]"));
+        assert!(!tikz.contains("\\definecolor"));
+        assert!(tikz.contains("fill=white"));
+    }
+}
@@ -1,9 +1,216 @@
-// A macro that generates a label based on the function name
+// A macro that times an expression under a label: a tracing span with the `tracing`
+// feature, a console timer on wasm, nothing natively
+#[cfg(feature = "tracing")]
 macro_rules! time {
     ($label:expr, $e:expr) => {{
-        console::time_with_label($label);
+        let _span = tracing::info_span!($label).entered();
+        $e
+    }};
+}
+
+#[cfg(all(not(feature = "tracing"), target_arch = "wasm32"))]
+macro_rules! time {
+    ($label:expr, $e:expr) => {{
+        web_sys::console::time_with_label($label);
         let result = $e;
-        console::time_end_with_label($label);
+        web_sys::console::time_end_with_label($label);
         result
     }};
 }
+
+#[cfg(all(not(feature = "tracing"), not(target_arch = "wasm32")))]
+macro_rules! time {
+    ($label:expr, $e:expr) => {{
+        $e
+    }};
+}
+
+#[cfg(feature = "tracing")]
+pub use timing::*;
+
+#[cfg(feature = "tracing")]
+mod timing {
+    use std::sync::{Arc, Mutex};
+
+    use rustc_hash::FxHashMap;
+    use serde::Serialize;
+    use tracing::{span, subscriber::SetGlobalDefaultError, Subscriber};
+    use tracing_subscriber::{layer::Context, prelude::*, registry::LookupSpan, Layer};
+    use wasm_bindgen::prelude::*;
+
+    #[derive(Debug, Clone, PartialEq, Serialize)]
+    pub struct SpanTiming {
+        pub name: String,
+        pub count: u64,
+        pub total_ms: f64,
+    }
+
+    impl SpanTiming {
+        pub fn mean_ms(&self) -> f64 {
+            self.total_ms / self.count as f64
+        }
+    }
+
+    // Span durations summed by span name, shared between the layer and its owner
+    #[derive(Debug, Clone, Default)]
+    pub struct Timings(Arc<Mutex<FxHashMap<&'static str, SpanTiming>>>);
+
+    impl Timings {
+        // Sorted by total time, longest first
+        pub fn get(&self) -> Vec<SpanTiming> {
+            let mut timings: Vec<SpanTiming> = self.0.lock().unwrap().values().cloned().collect();
+            timings.sort_by(|a, b| b.total_ms.total_cmp(&a.total_ms));
+            timings
+        }
+
+        pub fn reset(&self) {
+            self.0.lock().unwrap().clear();
+        }
+
+        fn record(&self, name: &'static str, elapsed_ms: f64) {
+            let mut timings = self.0.lock().unwrap();
+            let timing = timings.entry(name).or_insert_with(|| SpanTiming {
+                name: name.to_string(),
+                count: 0,
+                total_ms: 0.0,
+            });
+            timing.count += 1;
+            timing.total_ms += elapsed_ms;
+        }
+    }
+
+    struct SpanStart(f64);
+
+    // Records how long each span was open; with `log_spans` every closed span is also
+    // written to the console on wasm and to stderr natively
+    pub struct TimingLayer {
+        timings: Timings,
+        log_spans: bool,
+    }
+
+    impl TimingLayer {
+        pub fn new(log_spans: bool) -> Self {
+            Self {
+                timings: Timings::default(),
+                log_spans,
+            }
+        }
+
+        pub fn timings(&self) -> Timings {
+            self.timings.clone()
+        }
+    }
+
+    impl<S> Layer<S> for TimingLayer
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        fn on_new_span(&self, _attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+            if let Some(span) = ctx.span(id) {
+                span.extensions_mut().insert(SpanStart(now_ms()));
+            }
+        }
+
+        fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+            let Some(span) = ctx.span(&id) else {
+                return;
+            };
+            let Some(start) = span.extensions().get::<SpanStart>().map(|start| start.0) else {
+                return;
+            };
+            let elapsed_ms = now_ms() - start;
+
+            self.timings.record(span.name(), elapsed_ms);
+            if self.log_spans {
+                log(&format!("{}: {:.3}ms", span.name(), elapsed_ms));
+            }
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn now_ms() -> f64 {
+        js_sys::Date::now()
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn now_ms() -> f64 {
+        use std::{sync::OnceLock, time::Instant};
+
+        static START: OnceLock<Instant> = OnceLock::new();
+        START.get_or_init(Instant::now).elapsed().as_secs_f64() * 1000.0
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn log(message: &str) {
+        web_sys::console::log_1(&JsValue::from(message));
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn log(message: &str) {
+        eprintln!("{}", message);
+    }
+
+    // Installs a global subscriber with a TimingLayer, fails if one is already set
+    pub fn init_tracing(log_spans: bool) -> Result<Timings, SetGlobalDefaultError> {
+        let layer = TimingLayer::new(log_spans);
+        let timings = layer.timings();
+        tracing::subscriber::set_global_default(tracing_subscriber::registry().with(layer))?;
+        Ok(timings)
+    }
+
+    thread_local! {
+        static JS_TIMINGS: std::cell::RefCell<Option<Timings>> = const { std::cell::RefCell::new(None) };
+    }
+
+    #[wasm_bindgen(js_name = initTracing)]
+    pub fn init_tracing_js(log_spans: bool) -> Result<(), JsValue> {
+        let timings = init_tracing(log_spans).map_err(|error| JsValue::from(error.to_string()))?;
+        JS_TIMINGS.with(|js_timings| *js_timings.borrow_mut() = Some(timings));
+        Ok(())
+    }
+
+    // JSON array of SpanTiming, empty before initTracing
+    #[wasm_bindgen(js_name = getTimings)]
+    pub fn get_timings_js() -> String {
+        let timings = JS_TIMINGS.with(|js_timings| {
+            js_timings
+                .borrow()
+                .as_ref()
+                .map(Timings::get)
+                .unwrap_or_default()
+        });
+        serde_json::to_string(&timings).unwrap()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use tracing_subscriber::prelude::*;
+
+        use super::TimingLayer;
+        use crate::PeriodicLozengeTiling;
+
+        #[test]
+        fn records_instrumented_spans() {
+            let layer = TimingLayer::new(false);
+            let timings = layer.timings();
+            let subscriber = tracing_subscriber::registry().with(layer);
+
+            tracing::subscriber::with_default(subscriber, || {
+                let mut lozenge_tiling = PeriodicLozengeTiling::new(1, 2, 3, 5, 5, 5);
+                lozenge_tiling.set_seed(1);
+                lozenge_tiling.generate_with_markov_chain(100, 0.9);
+                lozenge_tiling.generate_with_markov_chain(100, 0.9);
+                lozenge_tiling.get_box_voxels();
+            });
+
+            let timings = timings.get();
+            let markov_chain = timings
+                .iter()
+                .find(|timing| timing.name == "generate_with_markov_chain")
+                .unwrap();
+            assert_eq!(markov_chain.count, 2);
+            assert!(markov_chain.total_ms >= 0.0);
+            assert!(timings.iter().any(|timing| timing.name == "get_box_voxels"));
+        }
+    }
+}
@@ -0,0 +1,403 @@
+use std::{error::Error, f64::consts::PI, fmt};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+use crate::height_window::HeightWindow;
+
+// Two independent period vectors of the columns and the height change along each,
+// height(c + u) = height(c) + u_height and the same for v
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TorusPeriods {
+    pub u: [i32; 2],
+    pub u_height: i32,
+    pub v: [i32; 2],
+    pub v_height: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TorusError {
+    // u and v are parallel
+    DegeneratePeriods,
+    // the heights would have to increase along x or y
+    SlopeOutside,
+}
+
+impl fmt::Display for TorusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TorusError::DegeneratePeriods => write!(f, "period vectors are parallel"),
+            TorusError::SlopeOutside => {
+                write!(f, "height changes give a slope with no lozenge tilings")
+            }
+        }
+    }
+}
+
+impl Error for TorusError {}
+
+// A lozenge tiling of the torus, as the heights of the top boxes of the columns of a
+// fundamental domain. Periodicity is reduced to the basis (a, b), (0, d) with
+// 0 <= b < d so the columns 0..a, 0..d are a fundamental domain. The heights never
+// increase along x or y, the X and Y lozenges are the drops between neighbors and
+// every column has one Z lozenge on top, so the lozenge counts are fixed by the
+// periods and the moves of the chain keep the slope.
+#[derive(Debug, Clone)]
+#[wasm_bindgen]
+pub struct ToroidalLozengeTiling {
+    periods: TorusPeriods,
+    a: i32,
+    b: i32,
+    d: i32,
+    // height changes along (a, b) and (0, d)
+    a_height: i32,
+    d_height: i32,
+    // a row per x
+    heights: Vec<i32>,
+    rng: StdRng,
+}
+
+// (g, s, t) with s * x + t * y = g = gcd(x, y) >= 0
+fn extended_gcd(x: i32, y: i32) -> (i32, i32, i32) {
+    if y == 0 {
+        return (x.abs(), x.signum(), 0);
+    }
+    let (g, s, t) = extended_gcd(y, x.rem_euclid(y));
+    (g, t, s - x.div_euclid(y) * t)
+}
+
+impl ToroidalLozengeTiling {
+    pub fn new(periods: TorusPeriods) -> Result<ToroidalLozengeTiling, TorusError> {
+        let TorusPeriods {
+            u: [u1, u2],
+            u_height,
+            v: [v1, v2],
+            v_height,
+        } = periods;
+
+        let determinant = u1 * v2 - u2 * v1;
+        if determinant == 0 {
+            return Err(TorusError::DegeneratePeriods);
+        }
+
+        let (a, s, t) = extended_gcd(u1, v1);
+        let (mut b, mut a_height) = (s * u2 + t * v2, s * u_height + t * v_height);
+        let mut d = (v1 * u2 - u1 * v2) / a;
+        let mut d_height = (v1 * u_height - u1 * v_height) / a;
+        if d < 0 {
+            d = -d;
+            d_height = -d_height;
+        }
+        let shift = b.div_euclid(d);
+        b -= shift * d;
+        a_height -= shift * d_height;
+
+        // the linear height, -alpha x - beta y, with alpha and beta the mean drops
+        let x_numerator = u_height * v2 - v_height * u2;
+        let y_numerator = u1 * v_height - v1 * u_height;
+        let [x_numerator, y_numerator, denominator] = match determinant > 0 {
+            true => [x_numerator, y_numerator, determinant],
+            false => [-x_numerator, -y_numerator, -determinant],
+        };
+        if x_numerator > 0 || y_numerator > 0 {
+            return Err(TorusError::SlopeOutside);
+        }
+
+        // rounding down a linear height that has the periods gives a tiling
+        let mut heights = Vec::with_capacity((a * d) as usize);
+        for x in 0..a {
+            for y in 0..d {
+                heights.push((x_numerator * x + y_numerator * y).div_euclid(denominator));
+            }
+        }
+
+        Ok(ToroidalLozengeTiling {
+            periods,
+            a,
+            b,
+            d,
+            a_height,
+            d_height,
+            heights,
+            rng: StdRng::from_entropy(),
+        })
+    }
+
+    pub fn get_periods(&self) -> &TorusPeriods {
+        &self.periods
+    }
+
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    // columns in the fundamental domain
+    pub fn get_column_count(&self) -> usize {
+        self.heights.len()
+    }
+
+    // index in the fundamental domain and height change to the column
+    fn normalize(&self, x: i32, y: i32) -> (usize, i32) {
+        let k = x.div_euclid(self.a);
+        let y = y - k * self.b;
+        let j = y.div_euclid(self.d);
+        let index = (x - k * self.a) * self.d + y - j * self.d;
+        (index as usize, k * self.a_height + j * self.d_height)
+    }
+
+    pub fn get_height(&self, x: i32, y: i32) -> i32 {
+        let (index, offset) = self.normalize(x, y);
+        self.heights[index] + offset
+    }
+
+    fn is_locally_valid(&self, x: i32, y: i32) -> bool {
+        let height = self.get_height(x, y);
+        self.get_height(x - 1, y) >= height
+            && self.get_height(x, y - 1) >= height
+            && self.get_height(x + 1, y) <= height
+            && self.get_height(x, y + 1) <= height
+    }
+
+    // Adds or removes a box on top of the column and all its copies, does nothing
+    // when that doesn't give a tiling
+    pub fn move_box(&mut self, x: i32, y: i32, add: bool) -> bool {
+        let (index, _) = self.normalize(x, y);
+        let change = if add { 1 } else { -1 };
+        self.heights[index] += change;
+        if self.is_locally_valid(x, y) {
+            true
+        } else {
+            self.heights[index] -= change;
+            false
+        }
+    }
+
+    // Chain of random box additions and removals, symmetric so the tilings with the
+    // slope of the periods are sampled uniformly
+    pub fn generate(&mut self, iterations: i32) {
+        for _ in 0..iterations {
+            let index = self.rng.gen_range(0..self.heights.len()) as i32;
+            let add = self.rng.gen::<bool>();
+            self.move_box(index / self.d, index % self.d, add);
+        }
+    }
+
+    // X, Y and Z lozenges in the fundamental domain
+    pub fn get_lozenge_counts(&self) -> [i32; 3] {
+        let mut counts = [0, 0, self.heights.len() as i32];
+        for x in 0..self.a {
+            for y in 0..self.d {
+                let height = self.get_height(x, y);
+                counts[0] += height - self.get_height(x + 1, y);
+                counts[1] += height - self.get_height(x, y + 1);
+            }
+        }
+        counts
+    }
+
+    // proportions of X, Y and Z lozenges
+    pub fn get_slope(&self) -> [f64; 3] {
+        let counts = self.get_lozenge_counts();
+        let total = counts.iter().sum::<i32>() as f64;
+        counts.map(|count| count as f64 / total)
+    }
+
+    // The slope is fixed by the periods, moves don't change it. This is the value of
+    // the surface tension formula at that slope, nothing is measured from the tiling.
+    pub fn get_theoretical_surface_tension(&self) -> f64 {
+        surface_tension(self.get_slope())
+    }
+
+    // Unwrapped heights over a window of columns
+    pub fn get_height_window(
+        &self,
+        x_start: i32,
+        y_start: i32,
+        x_size: usize,
+        y_size: usize,
+    ) -> HeightWindow {
+        let mut heights = Vec::with_capacity(x_size * y_size);
+        for x in x_start..x_start + x_size as i32 {
+            for y in y_start..y_start + y_size as i32 {
+                heights.push(self.get_height(x, y));
+            }
+        }
+        HeightWindow {
+            x_start,
+            y_start,
+            x_size,
+            y_size,
+            heights,
+        }
+    }
+}
+
+// Clausen function Cl2, sum of sin(k theta) / k^2, from its series around 0 after
+// reducing theta to [-pi, pi]
+fn clausen(theta: f64) -> f64 {
+    let theta = (theta + PI).rem_euclid(2.0 * PI) - PI;
+    if theta == 0.0 {
+        return 0.0;
+    }
+
+    let ratio = (theta / (2.0 * PI)).powi(2);
+    let mut power = theta;
+    let mut sum = theta - theta * theta.abs().ln();
+    for k in 1..60 {
+        power *= ratio;
+        let zeta = match k {
+            1 => PI * PI / 6.0,
+            2 => PI.powi(4) / 90.0,
+            _ => (1..100).map(|n| (n as f64).powi(-2 * k)).sum::<f64>(),
+        };
+        sum += zeta / (k * (2 * k + 1)) as f64 * power;
+    }
+    sum
+}
+
+// Entropy per lozenge of the tilings with the proportions of X, Y and Z lozenges,
+// (L(pi px) + L(pi py) + L(pi pz)) / pi with the Lobachevsky function L
+pub fn lozenge_entropy(proportions: [f64; 3]) -> f64 {
+    proportions
+        .iter()
+        .map(|p| clausen(2.0 * PI * p) / 2.0)
+        .sum::<f64>()
+        / PI
+}
+
+// Surface tension of the slope, minus the entropy of the translation invariant
+// Gibbs measure with the proportions of lozenges
+pub fn surface_tension(proportions: [f64; 3]) -> f64 {
+    -lozenge_entropy(proportions)
+}
+
+#[wasm_bindgen]
+impl ToroidalLozengeTiling {
+    #[wasm_bindgen(constructor)]
+    pub fn new_js(
+        u_x: i32,
+        u_y: i32,
+        u_height: i32,
+        v_x: i32,
+        v_y: i32,
+        v_height: i32,
+    ) -> Result<ToroidalLozengeTiling, JsValue> {
+        ToroidalLozengeTiling::new(TorusPeriods {
+            u: [u_x, u_y],
+            u_height,
+            v: [v_x, v_y],
+            v_height,
+        })
+        .map_err(|error| JsValue::from(error.to_string()))
+    }
+
+    #[wasm_bindgen(js_name = setSeed)]
+    pub fn set_seed_js(&mut self, seed: u64) {
+        self.set_seed(seed);
+    }
+
+    #[wasm_bindgen(js_name = generate)]
+    pub fn generate_js(&mut self, iterations: i32) {
+        self.generate(iterations);
+    }
+
+    #[wasm_bindgen(js_name = getHeight)]
+    pub fn get_height_js(&self, x: i32, y: i32) -> i32 {
+        self.get_height(x, y)
+    }
+
+    #[wasm_bindgen(js_name = getHeightWindow)]
+    pub fn get_height_window_js(
+        &self,
+        x_start: i32,
+        y_start: i32,
+        x_size: usize,
+        y_size: usize,
+    ) -> js_sys::Int32Array {
+        let window = self.get_height_window(x_start, y_start, x_size, y_size);
+        js_sys::Int32Array::from(&window.heights[..])
+    }
+
+    #[wasm_bindgen(js_name = getSlope)]
+    pub fn get_slope_js(&self) -> Vec<f64> {
+        self.get_slope().to_vec()
+    }
+
+    #[wasm_bindgen(js_name = getTheoreticalSurfaceTension)]
+    pub fn get_theoretical_surface_tension_js(&self) -> f64 {
+        self.get_theoretical_surface_tension()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{lozenge_entropy, ToroidalLozengeTiling, TorusError, TorusPeriods};
+
+    #[test]
+    fn moves_keep_the_slope() {
+        let periods = TorusPeriods {
+            u: [3, 1],
+            u_height: -4,
+            v: [-1, 4],
+            v_height: -3,
+        };
+        let mut lozenge_tiling = ToroidalLozengeTiling::new(periods).unwrap();
+        lozenge_tiling.set_seed(5);
+        let counts = lozenge_tiling.get_lozenge_counts();
+        assert_eq!(lozenge_tiling.get_column_count(), 13);
+        assert_eq!(counts[2], 13);
+
+        lozenge_tiling.generate(5000);
+
+        assert_eq!(lozenge_tiling.get_lozenge_counts(), counts);
+        for x in -5..5 {
+            for y in -5..5 {
+                assert!(lozenge_tiling.is_locally_valid(x, y));
+                assert_eq!(
+                    lozenge_tiling.get_height(x + 3, y + 1),
+                    lozenge_tiling.get_height(x, y) - 4
+                );
+                assert_eq!(
+                    lozenge_tiling.get_height(x - 1, y + 4),
+                    lozenge_tiling.get_height(x, y) - 3
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_impossible_periods() {
+        let parallel = TorusPeriods {
+            u: [1, 2],
+            u_height: 0,
+            v: [2, 4],
+            v_height: 0,
+        };
+        assert_eq!(
+            ToroidalLozengeTiling::new(parallel).unwrap_err(),
+            TorusError::DegeneratePeriods
+        );
+
+        let increasing = TorusPeriods {
+            u: [2, 0],
+            u_height: 1,
+            v: [0, 2],
+            v_height: -1,
+        };
+        assert_eq!(
+            ToroidalLozengeTiling::new(increasing).unwrap_err(),
+            TorusError::SlopeOutside
+        );
+    }
+
+    #[test]
+    fn entropy_is_largest_at_equal_proportions() {
+        let third = 1.0 / 3.0;
+        let maximum = lozenge_entropy([third, third, third]);
+        assert!((maximum - 0.323065947).abs() < 1e-8);
+        assert!(lozenge_entropy([0.5, 0.25, 0.25]) < maximum);
+        assert!(lozenge_entropy([0.0, 0.0, 1.0]).abs() < 1e-12);
+        assert!(lozenge_entropy([0.5, 0.5, 0.0]).abs() < 1e-10);
+    }
+}
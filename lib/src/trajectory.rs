@@ -0,0 +1,532 @@
+use std::io::{self, Read, Write};
+
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    box_move::BoxMove, vector3::Vector3, Boundary, BoundaryConditions, DrawDistance,
+    LozengeTilingPeriods, Obstacles, PeriodicLozengeTiling, StateError, SymmetryClass, TilingState,
+};
+
+const MAGIC: &[u8; 4] = b"LZTR";
+const VERSION: u8 = 1;
+
+// A starting state and every move made from it. Moves are stored normalized, so
+// replaying them from the initial state reproduces the run exactly.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trajectory {
+    pub initial_state: TilingState,
+    pub moves: Vec<BoxMove>,
+}
+
+impl Trajectory {
+    pub fn new(initial_state: TilingState) -> Trajectory {
+        Trajectory {
+            initial_state,
+            moves: Vec::new(),
+        }
+    }
+
+    pub fn get_step_count(&self) -> usize {
+        self.moves.len()
+    }
+
+    // Binary log: magic, version, periods, draw distance, initial heights, obstacle
+    // columns and forbidden regions, boundary conditions, symmetry and moves. Integers
+    // are LEB128 varints (zigzag for signed ones). Every move is the kind in the lowest
+    // bit of its zigzagged x followed by y and z.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[VERSION])?;
+
+        let LozengeTilingPeriods {
+            x_shift,
+            y_shift,
+            z_height,
+        } = self.initial_state.periods;
+        let DrawDistance { x, y, z } = self.initial_state.draw_distance;
+        for value in [x_shift, y_shift, z_height, x, y, z] {
+            write_signed(writer, value)?;
+        }
+
+        write_unsigned(writer, self.initial_state.heights.len() as u64)?;
+        for [x, y, height] in self.initial_state.heights.iter() {
+            write_signed(writer, *x)?;
+            write_signed(writer, *y)?;
+            write_signed(writer, *height)?;
+        }
+
+        let Obstacles { columns, forbidden } = &self.initial_state.obstacles;
+        write_unsigned(writer, columns.len() as u64)?;
+        for value in columns.iter().flatten() {
+            write_signed(writer, *value)?;
+        }
+        write_unsigned(writer, forbidden.len() as u64)?;
+        for value in forbidden.iter().flatten() {
+            write_signed(writer, *value)?;
+        }
+        let BoundaryConditions { x, y, z } = self.initial_state.boundary_conditions;
+        for boundary in [x, y, z] {
+            write_unsigned(writer, boundary_to_code(boundary))?;
+        }
+        match self.initial_state.symmetry {
+            None => write_unsigned(writer, 0)?,
+            Some(SymmetryClass::Transpose) => write_unsigned(writer, 1)?,
+            Some(SymmetryClass::Cyclic) => write_unsigned(writer, 2)?,
+            Some(SymmetryClass::SelfComplementary { x_size, y_size }) => {
+                write_unsigned(writer, 3)?;
+                write_signed(writer, x_size)?;
+                write_signed(writer, y_size)?;
+            }
+        }
+
+        write_unsigned(writer, self.moves.len() as u64)?;
+        for box_move in self.moves.iter() {
+            let (kind, Vector3(x, y, z)) = match box_move {
+                BoxMove::Add(vector) => (0, vector),
+                BoxMove::Remove(vector) => (1, vector),
+            };
+            write_unsigned(writer, zigzag(*x) << 1 | kind)?;
+            write_signed(writer, *y)?;
+            write_signed(writer, *z)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(reader: &mut R) -> io::Result<Trajectory> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a trajectory log"));
+        }
+        let mut version = [0; 1];
+        reader.read_exact(&mut version)?;
+        if version[0] != VERSION {
+            return Err(invalid_data(&format!(
+                "unsupported trajectory log version {}",
+                version[0]
+            )));
+        }
+
+        let mut values = [0; 6];
+        for value in values.iter_mut() {
+            *value = read_signed(reader)?;
+        }
+        let [x_shift, y_shift, z_height, x, y, z] = values;
+
+        let heights_count = read_unsigned(reader)?;
+        let mut heights = Vec::new();
+        for _ in 0..heights_count {
+            heights.push([
+                read_signed(reader)?,
+                read_signed(reader)?,
+                read_signed(reader)?,
+            ]);
+        }
+
+        let mut obstacles = Obstacles::default();
+        for _ in 0..read_unsigned(reader)? {
+            let mut column = [0; 3];
+            for value in column.iter_mut() {
+                *value = read_signed(reader)?;
+            }
+            obstacles.columns.push(column);
+        }
+        for _ in 0..read_unsigned(reader)? {
+            let mut region = [0; 6];
+            for value in region.iter_mut() {
+                *value = read_signed(reader)?;
+            }
+            obstacles.forbidden.push(region);
+        }
+        let mut boundaries = [Boundary::Fixed; 3];
+        for boundary in boundaries.iter_mut() {
+            *boundary = code_to_boundary(read_unsigned(reader)?)?;
+        }
+        let [x_boundary, y_boundary, z_boundary] = boundaries;
+        let symmetry = match read_unsigned(reader)? {
+            0 => None,
+            1 => Some(SymmetryClass::Transpose),
+            2 => Some(SymmetryClass::Cyclic),
+            3 => Some(SymmetryClass::SelfComplementary {
+                x_size: read_signed(reader)?,
+                y_size: read_signed(reader)?,
+            }),
+            _ => return Err(invalid_data("unknown symmetry")),
+        };
+
+        let moves_count = read_unsigned(reader)?;
+        let mut moves = Vec::new();
+        for _ in 0..moves_count {
+            let first = read_unsigned(reader)?;
+            let vector = Vector3(
+                unzigzag(first >> 1)?,
+                read_signed(reader)?,
+                read_signed(reader)?,
+            );
+            moves.push(match first & 1 {
+                0 => BoxMove::Add(vector),
+                _ => BoxMove::Remove(vector),
+            });
+        }
+
+        Ok(Trajectory {
+            initial_state: TilingState {
+                periods: LozengeTilingPeriods {
+                    x_shift,
+                    y_shift,
+                    z_height,
+                },
+                draw_distance: DrawDistance { x, y, z },
+                heights,
+                obstacles,
+                boundary_conditions: BoundaryConditions {
+                    x: x_boundary,
+                    y: y_boundary,
+                    z: z_boundary,
+                },
+                symmetry,
+            },
+            moves,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        self.write_to(&mut bytes).unwrap();
+        bytes
+    }
+
+    pub fn from_bytes(mut bytes: &[u8]) -> io::Result<Trajectory> {
+        Trajectory::read_from(&mut bytes)
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn boundary_to_code(boundary: Boundary) -> u64 {
+    match boundary {
+        Boundary::Fixed => 0,
+        Boundary::Cut => 1,
+        Boundary::MirrorCut => 2,
+    }
+}
+
+fn code_to_boundary(code: u64) -> io::Result<Boundary> {
+    match code {
+        0 => Ok(Boundary::Fixed),
+        1 => Ok(Boundary::Cut),
+        2 => Ok(Boundary::MirrorCut),
+        _ => Err(invalid_data("unknown boundary")),
+    }
+}
+
+fn zigzag(value: i32) -> u64 {
+    ((value << 1) ^ (value >> 31)) as u32 as u64
+}
+
+fn unzigzag(value: u64) -> io::Result<i32> {
+    let value = u32::try_from(value).map_err(|_| invalid_data("integer out of range"))?;
+    Ok((value >> 1) as i32 ^ -((value & 1) as i32))
+}
+
+fn write_unsigned<W: Write>(writer: &mut W, mut value: u64) -> io::Result<()> {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            return writer.write_all(&[byte]);
+        }
+        writer.write_all(&[byte | 0x80])?;
+    }
+}
+
+fn write_signed<W: Write>(writer: &mut W, value: i32) -> io::Result<()> {
+    write_unsigned(writer, zigzag(value))
+}
+
+fn read_unsigned<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0; 1];
+        reader.read_exact(&mut byte)?;
+        value |= ((byte[0] & 0x7f) as u64) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(invalid_data("varint too long"))
+}
+
+fn read_signed<R: Read>(reader: &mut R) -> io::Result<i32> {
+    unzigzag(read_unsigned(reader)?)
+}
+
+impl PeriodicLozengeTiling {
+    // Starts recording every move made through add_box and remove_box from the
+    // current state, replacing a recording in progress
+    pub fn start_recording(&mut self) {
+        self.recording = Some(Trajectory::new(self.to_state()));
+    }
+
+    pub fn stop_recording(&mut self) -> Option<Trajectory> {
+        self.recording.take()
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+}
+
+// Rebuilds the tiling of a trajectory at any step. A snapshot is kept every
+// keyframe_interval moves, so seeking replays at most that many moves.
+#[wasm_bindgen]
+pub struct TrajectoryReplay {
+    trajectory: Trajectory,
+    keyframe_interval: usize,
+    // keyframes[i] is the tiling after i * keyframe_interval moves. Kept whole rather
+    // than as states, the moves of a symmetry orbit are logged box by box so a keyframe
+    // can fall inside an orbit and wouldn't pass the checks of from_state.
+    keyframes: Vec<PeriodicLozengeTiling>,
+    lozenge_tiling: PeriodicLozengeTiling,
+    step: usize,
+}
+
+impl TrajectoryReplay {
+    pub fn new(
+        trajectory: Trajectory,
+        keyframe_interval: usize,
+    ) -> Result<TrajectoryReplay, StateError> {
+        let keyframe_interval = keyframe_interval.max(1);
+        let initial_tiling = PeriodicLozengeTiling::from_state(&trajectory.initial_state)?;
+        let mut lozenge_tiling = initial_tiling.clone();
+        let mut keyframes = vec![initial_tiling.clone()];
+        for (index, box_move) in trajectory.moves.iter().enumerate() {
+            lozenge_tiling.apply_move(*box_move);
+            if (index + 1) % keyframe_interval == 0 {
+                keyframes.push(lozenge_tiling.clone());
+            }
+        }
+
+        Ok(TrajectoryReplay {
+            lozenge_tiling: initial_tiling,
+            trajectory,
+            keyframe_interval,
+            keyframes,
+            step: 0,
+        })
+    }
+
+    pub fn get_trajectory(&self) -> &Trajectory {
+        &self.trajectory
+    }
+
+    pub fn get_step(&self) -> usize {
+        self.step
+    }
+
+    pub fn get_step_count(&self) -> usize {
+        self.trajectory.get_step_count()
+    }
+
+    pub fn get_tiling(&self) -> &PeriodicLozengeTiling {
+        &self.lozenge_tiling
+    }
+
+    // Moves the replayed tiling to step, clamped to the trajectory length
+    pub fn seek(&mut self, step: usize) -> &PeriodicLozengeTiling {
+        let step = step.min(self.get_step_count());
+        let keyframe = step / self.keyframe_interval;
+        let keyframe_step = keyframe * self.keyframe_interval;
+
+        // continue from the current step when it's not behind the nearest keyframe
+        if step < self.step || self.step < keyframe_step {
+            let draw_distance = *self.lozenge_tiling.get_draw_distance();
+            self.lozenge_tiling = self.keyframes[keyframe].clone();
+            self.lozenge_tiling.set_draw_distance(
+                draw_distance.x,
+                draw_distance.y,
+                draw_distance.z,
+            );
+            self.step = keyframe_step;
+        }
+        for box_move in self.trajectory.moves[self.step..step].iter() {
+            self.lozenge_tiling.apply_move(*box_move);
+        }
+        self.step = step;
+
+        &self.lozenge_tiling
+    }
+
+    pub fn set_draw_distance(&mut self, x: i32, y: i32, z: i32) {
+        self.lozenge_tiling.set_draw_distance(x, y, z);
+    }
+}
+
+#[wasm_bindgen]
+impl PeriodicLozengeTiling {
+    #[wasm_bindgen(js_name = startRecording)]
+    pub fn start_recording_js(&mut self) {
+        self.start_recording();
+    }
+
+    // Binary trajectory log, undefined when not recording
+    #[wasm_bindgen(js_name = stopRecording)]
+    pub fn stop_recording_js(&mut self) -> Option<Vec<u8>> {
+        self.stop_recording()
+            .map(|trajectory| trajectory.to_bytes())
+    }
+}
+
+#[wasm_bindgen]
+impl TrajectoryReplay {
+    #[wasm_bindgen(constructor)]
+    pub fn new_js(bytes: &[u8], keyframe_interval: usize) -> Result<TrajectoryReplay, JsValue> {
+        let trajectory =
+            Trajectory::from_bytes(bytes).map_err(|error| JsValue::from(error.to_string()))?;
+        TrajectoryReplay::new(trajectory, keyframe_interval)
+            .map_err(|error| JsValue::from(error.to_string()))
+    }
+
+    #[wasm_bindgen(js_name = getStep)]
+    pub fn get_step_js(&self) -> usize {
+        self.get_step()
+    }
+
+    #[wasm_bindgen(js_name = getStepCount)]
+    pub fn get_step_count_js(&self) -> usize {
+        self.get_step_count()
+    }
+
+    // Copy of the tiling at step
+    #[wasm_bindgen(js_name = seek)]
+    pub fn seek_js(&mut self, step: usize) -> PeriodicLozengeTiling {
+        self.seek(step).clone()
+    }
+
+    #[wasm_bindgen(js_name = setDrawDistance)]
+    pub fn set_draw_distance_js(&mut self, x: i32, y: i32, z: i32) {
+        self.set_draw_distance(x, y, z);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Trajectory, TrajectoryReplay};
+    use crate::{
+        vector3::Vector3, Boundary, BoundaryConditions, PeriodicLozengeTiling, SymmetryClass,
+    };
+
+    #[test]
+    fn can_write_and_read_trajectory() {
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(1, 2, 3, 5, 5, 5);
+        lozenge_tiling.set_seed(5);
+        lozenge_tiling.generate_by_adding_only(20);
+        lozenge_tiling.start_recording();
+        lozenge_tiling.generate_with_markov_chain(200, 0.9);
+        let trajectory = lozenge_tiling.stop_recording().unwrap();
+
+        let bytes = trajectory.to_bytes();
+        assert_eq!(Trajectory::from_bytes(&bytes).unwrap(), trajectory);
+        assert!(Trajectory::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Trajectory::from_bytes(b"LZTX").is_err());
+    }
+
+    #[test]
+    fn replay_matches_recorded_run() {
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(1, 2, 3, 5, 5, 5);
+        lozenge_tiling.set_seed(6);
+        lozenge_tiling.start_recording();
+        lozenge_tiling.generate_with_markov_chain(300, 0.9);
+        let final_state = lozenge_tiling.to_state();
+        let trajectory = lozenge_tiling.stop_recording().unwrap();
+        let mut replay = TrajectoryReplay::new(trajectory.clone(), 7).unwrap();
+
+        let mut steps_tiling =
+            PeriodicLozengeTiling::from_state(&trajectory.initial_state).unwrap();
+        let mut step_states = vec![steps_tiling.to_state()];
+        for box_move in trajectory.moves.iter() {
+            steps_tiling.apply_move(*box_move);
+            step_states.push(steps_tiling.to_state());
+        }
+        assert_eq!(step_states.last(), Some(&final_state));
+
+        for step in [0, 13, 5, 100, trajectory.moves.len(), 1] {
+            let step = step.min(trajectory.moves.len());
+            assert_eq!(replay.seek(step).to_state(), step_states[step]);
+        }
+    }
+
+    #[test]
+    fn replay_keeps_obstacles() {
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(0, 0, 5, 6, 6, 6);
+        lozenge_tiling.set_seed(4);
+        lozenge_tiling
+            .load_obstacles_json(r#"{"columns": [[2, 1, 1]], "forbidden": [[0, 3, 0, 2, 4, 5]]}"#)
+            .unwrap();
+        lozenge_tiling.start_recording();
+        lozenge_tiling.generate_with_markov_chain(400, 1.0);
+        let final_state = lozenge_tiling.to_state();
+        let trajectory = lozenge_tiling.stop_recording().unwrap();
+        assert_eq!(
+            trajectory.initial_state.obstacles,
+            lozenge_tiling.get_obstacles()
+        );
+
+        let trajectory = Trajectory::from_bytes(&trajectory.to_bytes()).unwrap();
+        let mut replay = TrajectoryReplay::new(trajectory.clone(), 9).unwrap();
+        let last_step = trajectory.get_step_count();
+        assert_eq!(replay.seek(last_step).to_state(), final_state);
+        // back through a keyframe, the rebuilt tiling still can't grow into the obstacles
+        let replayed = replay.seek(last_step / 2 + 1);
+        assert_eq!(replayed.get_obstacles(), final_state.obstacles);
+        let mut restored = PeriodicLozengeTiling::from_state(&replayed.to_state()).unwrap();
+        restored.generate_with_markov_chain(500, 1.0);
+        let boxes = restored.get_box_voxels();
+        assert!(boxes.iter().all(|Vector3(x, y, _)| !(*x == 2 && *y == 1)));
+        assert!(boxes.iter().all(|Vector3(x, y, _)| !(*x < 2 && *y == 3)));
+    }
+
+    #[test]
+    fn replay_keeps_boundary_conditions() {
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(0, 0, 6, 8, 8, 8);
+        lozenge_tiling.set_seed(4);
+        lozenge_tiling
+            .set_boundary_conditions(BoundaryConditions {
+                y: Boundary::Cut,
+                z: Boundary::MirrorCut,
+                ..Default::default()
+            })
+            .unwrap();
+        lozenge_tiling.start_recording();
+        lozenge_tiling.generate_with_markov_chain(500, 0.9);
+        let trajectory = lozenge_tiling.stop_recording().unwrap();
+
+        let read = Trajectory::from_bytes(&trajectory.to_bytes()).unwrap();
+        assert_eq!(read, trajectory);
+        let mut replay = TrajectoryReplay::new(read, 50).unwrap();
+        let replayed = replay.seek(trajectory.get_step_count());
+        assert_eq!(replayed.to_state(), lozenge_tiling.to_state());
+    }
+
+    #[test]
+    fn replay_keeps_symmetry() {
+        let mut lozenge_tiling = PeriodicLozengeTiling::new(0, 0, 3, 6, 6, 6);
+        lozenge_tiling.set_seed(8);
+        lozenge_tiling
+            .set_symmetry(Some(SymmetryClass::Cyclic))
+            .unwrap();
+        lozenge_tiling.start_recording();
+        lozenge_tiling.generate_with_markov_chain(300, 0.9);
+        let trajectory = lozenge_tiling.stop_recording().unwrap();
+
+        let read = Trajectory::from_bytes(&trajectory.to_bytes()).unwrap();
+        assert_eq!(read, trajectory);
+        let mut replay = TrajectoryReplay::new(read, 40).unwrap();
+        let replayed = replay.seek(trajectory.get_step_count());
+        assert_eq!(replayed.to_state(), lozenge_tiling.to_state());
+        assert_eq!(replayed.get_symmetry(), lozenge_tiling.get_symmetry());
+    }
+}
@@ -1,15 +1,14 @@
-use rand::{prelude::IteratorRandom, rngs::ThreadRng};
+use rand::{prelude::IteratorRandom, Rng};
 use rustc_hash::FxHashSet;
 
 use crate::vector3::Vector3;
 
 type HasSetVector3 = FxHashSet<Vector3>;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Vector3Set {
     initial_data: HasSetVector3,
     data: HasSetVector3,
-    rng: ThreadRng,
 }
 
 impl Vector3Set {
@@ -19,7 +18,6 @@ impl Vector3Set {
         Vector3Set {
             initial_data,
             data: FxHashSet::from_iter(data),
-            rng: rand::thread_rng(),
         }
     }
 
@@ -35,12 +33,20 @@ impl Vector3Set {
         self.data.len()
     }
 
+    pub fn clear(&mut self) {
+        self.data.clear();
+    }
+
     pub fn reset(&mut self) {
         self.data = self.initial_data.clone();
     }
 
-    pub fn get_random(&mut self) -> Option<Vector3> {
-        self.data.iter().choose(&mut self.rng).copied()
+    pub fn get_random<R: Rng>(&self, rng: &mut R) -> Option<Vector3> {
+        self.data.iter().choose(rng).copied()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Vector3> {
+        self.data.iter()
     }
 }
 
@@ -97,14 +103,14 @@ mod tests {
         set.insert(Vector3(1, 2, 3));
         set.insert(Vector3(1, 2, 4));
         assert!(matches!(
-            set.get_random(),
+            set.get_random(&mut rand::thread_rng()),
             Some(v) if v == Vector3  (1, 2, 3) || v == Vector3  (1, 2, 4)
         ));
     }
 
     #[test]
     fn get_random_returns_none_when_empty() {
-        let mut set = Vector3Set::new(None);
-        assert_eq!(set.get_random(), None);
+        let set = Vector3Set::new(None);
+        assert_eq!(set.get_random(&mut rand::thread_rng()), None);
     }
 }
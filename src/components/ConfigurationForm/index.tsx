@@ -4,6 +4,7 @@ import { atom, useAtomValue, useSetAtom } from 'jotai';
 import IterationsInput from '../IterationsInput';
 import MarkovChainCheckbox from '../MarkovChainCheckbox';
 import MarkovChainQInput from '../MarkovChainQInput';
+import ParallelCheckbox from '../ParallelCheckbox';
 import { changesDisabledAtom } from '../ProcessingWithProgress';
 import ResetButton from '../ResetButton';
 import AddBoxButton from '../AddBoxButton';
@@ -63,6 +64,7 @@ function ConfigurationForm() {
           <IterationsInput />
           <MarkovChainCheckbox />
           <MarkovChainQInput />
+          <ParallelCheckbox />
         </Column>
         <Column>
           <ResetButton />
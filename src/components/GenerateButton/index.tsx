@@ -1,13 +1,18 @@
-import { atom, useAtomValue } from 'jotai';
+import { atom, Setter, useAtomValue } from 'jotai';
 import { Button } from '@mui/material';
+import * as Comlink from 'comlink';
 import {
+  cancelFlagAtom,
   changesDisabledAtom,
+  progressValueAtom,
+  resetCancelProcessingAtom,
   startProcessingAtom,
   stopProcessingAtom,
 } from '../ProcessingWithProgress';
 import { iterationsAtom } from '../IterationsInput';
 import { qAtom } from '../MarkovChainQInput';
 import { markovChainAtom } from '../MarkovChainCheckbox';
+import { parallelAtom } from '../ParallelCheckbox';
 import { boxesAtom } from '../MainScene/Boxes';
 import { lozengeTilingComlink } from '../../lozengeTilingComlink';
 import { configValidAtom } from '../ConfigurationForm';
@@ -15,34 +20,70 @@ import { generatingContinuouslyAtom } from '../RunStopButton';
 
 export const generateBussyAtom = atom(false);
 
-const generateWithMarkovChainAtom = atom(null, async (get) => {
+// number of progress reports per generation
+const progressSteps = 100;
+
+function createProgressCallback(set: Setter, iterations: number) {
+  return Comlink.proxy((stepsDone: number) => {
+    set(progressValueAtom, (stepsDone / iterations) * 100);
+  });
+}
+
+const generateWithMarkovChainAtom = atom(null, async (get, set) => {
   const iterations = get(iterationsAtom);
   const q = get(qAtom);
-  await lozengeTilingComlink.generateWithMarkovChain(iterations, q);
+  await lozengeTilingComlink.generateWithMarkovChainWithProgress(
+    iterations,
+    q,
+    Math.ceil(iterations / progressSteps),
+    createProgressCallback(set, iterations),
+    get(cancelFlagAtom)
+  );
 });
 
-const generateByAddingOnlyAtom = atom(null, async (get) => {
+const generateWithParallelMarkovChainAtom = atom(null, async (get, set) => {
+  const sweeps = get(iterationsAtom);
+  const q = get(qAtom);
+  await lozengeTilingComlink.generateWithParallelMarkovChainWithProgress(
+    sweeps,
+    q,
+    Math.ceil(sweeps / progressSteps),
+    createProgressCallback(set, sweeps),
+    get(cancelFlagAtom)
+  );
+});
+
+const generateByAddingOnlyAtom = atom(null, async (get, set) => {
   const iterations = get(iterationsAtom);
-  await lozengeTilingComlink.generateByAddingOnly(iterations);
+  await lozengeTilingComlink.generateByAddingOnlyWithProgress(
+    iterations,
+    Math.ceil(iterations / progressSteps),
+    createProgressCallback(set, iterations),
+    get(cancelFlagAtom)
+  );
 });
 
 export const generateTilingAtom = atom(
   null,
   async (get, set, delayMs: number | undefined = undefined) => {
     set(generateBussyAtom, true);
+    set(resetCancelProcessingAtom);
 
     if (delayMs) {
       await new Promise((resolve) => setTimeout(resolve, delayMs)); // pause before updating
     }
 
     const markovChain = get(markovChainAtom);
+    const parallel = get(parallelAtom);
     const generatingContinuously = get(generatingContinuouslyAtom);
 
     if (!generatingContinuously) {
       set(startProcessingAtom);
     }
 
-    if (markovChain) {
+    if (markovChain && parallel) {
+      await set(generateWithParallelMarkovChainAtom);
+    } else if (markovChain) {
       await set(generateWithMarkovChainAtom);
     } else {
       await set(generateByAddingOnlyAtom);
@@ -0,0 +1,36 @@
+import { atom, useAtom, useAtomValue } from 'jotai';
+import { Checkbox, css, FormControlLabel } from '@mui/material';
+import { changesDisabledAtom } from '../ProcessingWithProgress';
+import { markovChainAtom } from '../MarkovChainCheckbox';
+import { parallelAvailable } from '../../lozengeTilingComlink';
+
+// checkerboard sweeps on all threads, iterations count sweeps instead of moves
+export const parallelAtom = atom(false);
+
+function ParallelCheckbox() {
+  const changesDisabled = useAtomValue(changesDisabledAtom);
+  const markovChain = useAtomValue(markovChainAtom);
+  const [parallel, setParallel] = useAtom(parallelAtom);
+
+  if (!parallelAvailable) {
+    return null;
+  }
+
+  return (
+    <FormControlLabel
+      control={
+        <Checkbox
+          checked={parallel}
+          onChange={() => setParallel((prev) => !prev)}
+          disabled={changesDisabled || !markovChain}
+        />
+      }
+      css={css`
+        margin-right: 0;
+      `}
+      label="Parallel Sweeps"
+    />
+  );
+}
+
+export default ParallelCheckbox;
@@ -1,8 +1,28 @@
 import { css, LinearProgress } from '@mui/material';
 import { atom, useAtomValue } from 'jotai';
+import { lozengeTilingComlink } from '../../lozengeTilingComlink';
 
 export const changesDisabledAtom = atom(false);
 export const showProgressAtom = atom(false);
+// percentage of the current generation, null when unknown
+export const progressValueAtom = atom<number | null>(null);
+
+// a flag in shared memory stops a generation mid-chunk, only available when cross-origin isolated;
+// otherwise the worker handles a cancel message between chunks
+const cancelFlag =
+  typeof SharedArrayBuffer !== 'undefined' && self.crossOriginIsolated
+    ? new Int32Array(new SharedArrayBuffer(Int32Array.BYTES_PER_ELEMENT))
+    : undefined;
+
+export const cancelFlagAtom = atom(() => cancelFlag);
+
+export const cancelProcessingAtom = atom(null, (get) => {
+  const flag = get(cancelFlagAtom);
+  if (flag) {
+    Atomics.store(flag, 0, 1);
+  }
+  lozengeTilingComlink.cancel();
+});
 
 let showProgressTimeout: ReturnType<typeof setTimeout> | undefined;
 
@@ -22,15 +42,26 @@ const delayedProgressAtom = atom(
     }
   }
 );
+export const resetCancelProcessingAtom = atom(null, (get) => {
+  const flag = get(cancelFlagAtom);
+  if (flag) {
+    Atomics.store(flag, 0, 0);
+  }
+  lozengeTilingComlink.resetCancel();
+});
+
 export const startProcessingAtom = atom(null, (_, set) => {
+  set(progressValueAtom, null);
   set(delayedProgressAtom, true);
 });
 export const stopProcessingAtom = atom(null, (_, set) => {
   set(delayedProgressAtom, false);
+  set(progressValueAtom, null);
 });
 
 function ProcessingWithProgress() {
   const showProgress = useAtomValue(showProgressAtom);
+  const progressValue = useAtomValue(progressValueAtom);
 
   return (
     <div
@@ -38,7 +69,12 @@ function ProcessingWithProgress() {
         height: 4px;
       `}
     >
-      {showProgress && <LinearProgress />}
+      {showProgress &&
+        (progressValue === null ? (
+          <LinearProgress />
+        ) : (
+          <LinearProgress variant="determinate" value={progressValue} />
+        ))}
     </div>
   );
 }
@@ -1,6 +1,7 @@
 import { atom, useAtom, useAtomValue, useSetAtom } from 'jotai';
 import { Button } from '@mui/material';
 import {
+  cancelProcessingAtom,
   changesDisabledAtom,
   showProgressAtom,
 } from '../ProcessingWithProgress';
@@ -23,6 +24,7 @@ function RunPauseButton() {
   const setChangesDisabled = useSetAtom(changesDisabledAtom);
   const setShowingProgress = useSetAtom(showProgressAtom);
   const generateBussy = useAtomValue(generateBussyAtom);
+  const cancelProcessing = useSetAtom(cancelProcessingAtom);
 
   // start/repeat generation loop
   useEffect(() => {
@@ -59,6 +61,9 @@ function RunPauseButton() {
       color={generatingContinuosuly ? 'error' : 'primary'}
       disabled={!configValid}
       onClick={() => {
+        if (generatingContinuosuly) {
+          cancelProcessing(); // stop the generation in progress
+        }
         setGeneratingContinuosuly((running) => !running);
       }}
     >
@@ -1,3 +1,4 @@
+import * as lozengeTilingWasm from '../../build/lib';
 import initWasm, {
   PeriodicLozengeTiling as PeriodicLozengeTilingWasm,
 } from '../../build/lib';
@@ -16,10 +17,22 @@ export interface DrawDistance {
   z: number;
 }
 
+export type GenerationProgressCallback = (
+  stepsDone: number,
+  volume: number
+) => void;
+
+// returns true to stop the generation
+type ChunkProgressCallback = (stepsDone: number, volume: number) => boolean;
+
 export class PeriodicLozengeTilingWorker {
   private lozengeTiling: PeriodicLozengeTilingWasm | null = null;
   private initialPeriods: LozengeTilingPeriods;
   private initialDrawDistance: DrawDistance;
+  // set by cancel(), for when no cancel flag in shared memory is available
+  private cancelRequested = false;
+  // wasm built with threads, see LOZENGE_PARALLEL in webpack.config.ts
+  private parallel = false;
 
   constructor(periods: LozengeTilingPeriods, drawDistance: DrawDistance) {
     this.initialPeriods = periods;
@@ -29,6 +42,15 @@ export class PeriodicLozengeTilingWorker {
   public async init(): Promise<void> {
     await initWasm();
 
+    // only exported when wasm is built with the "parallel" feature
+    if ('initThreadPool' in lozengeTilingWasm) {
+      const { initThreadPool } = lozengeTilingWasm as unknown as {
+        initThreadPool: (threads: number) => Promise<void>;
+      };
+      await initThreadPool(navigator.hardwareConcurrency);
+      this.parallel = true;
+    }
+
     this.lozengeTiling = new PeriodicLozengeTilingWasm(
       this.initialPeriods.xShift,
       this.initialPeriods.yShift,
@@ -102,6 +124,129 @@ export class PeriodicLozengeTilingWorker {
     this.lozengeTiling.generateWithMarkovChain(iterations, q);
   }
 
+  public async cancel(): Promise<void> {
+    this.cancelRequested = true;
+  }
+
+  public async resetCancel(): Promise<void> {
+    this.cancelRequested = false;
+  }
+
+  // runs the generation one progress step at a time and yields to the event loop
+  // in between, so a cancel() message can be handled while it is running
+  private async generateInChunks(
+    iterations: number,
+    progressEvery: number,
+    onProgress: Comlink.Remote<GenerationProgressCallback>,
+    generateChunk: (
+      steps: number,
+      onChunkProgress: ChunkProgressCallback
+    ) => number
+  ): Promise<number> {
+    let stepsDone = 0;
+    try {
+      while (stepsDone < iterations && !this.cancelRequested) {
+        const steps = Math.min(progressEvery, iterations - stepsDone);
+        const chunkStart = stepsDone;
+        const chunkStepsDone = generateChunk(steps, (done, volume) => {
+          onProgress(chunkStart + done, volume);
+          return this.cancelRequested;
+        });
+        stepsDone += chunkStepsDone;
+        if (chunkStepsDone < steps) {
+          break; // cancelled through the shared flag
+        }
+        await new Promise((resolve) => setTimeout(resolve, 0));
+      }
+    } finally {
+      // each generation gets its own proxy, release its message port
+      onProgress[Comlink.releaseProxy]();
+    }
+    return stepsDone;
+  }
+
+  public async generateWithMarkovChainWithProgress(
+    iterations: number,
+    q: number,
+    progressEvery: number,
+    onProgress: Comlink.Remote<GenerationProgressCallback>,
+    cancel?: Int32Array
+  ): Promise<number> {
+    const lozengeTiling = this.lozengeTiling;
+    if (!lozengeTiling) {
+      throw new Error('LozengeTiling not initialized');
+    }
+    return this.generateInChunks(
+      iterations,
+      progressEvery,
+      onProgress,
+      (steps, onChunkProgress) =>
+        lozengeTiling.generateWithMarkovChainWithProgress(
+          steps,
+          q,
+          steps,
+          onChunkProgress,
+          cancel
+        )
+    );
+  }
+
+  public async generateByAddingOnlyWithProgress(
+    iterations: number,
+    progressEvery: number,
+    onProgress: Comlink.Remote<GenerationProgressCallback>,
+    cancel?: Int32Array
+  ): Promise<number> {
+    const lozengeTiling = this.lozengeTiling;
+    if (!lozengeTiling) {
+      throw new Error('LozengeTiling not initialized');
+    }
+    return this.generateInChunks(
+      iterations,
+      progressEvery,
+      onProgress,
+      (steps, onChunkProgress) =>
+        lozengeTiling.generateByAddingOnlyWithProgress(
+          steps,
+          steps,
+          onChunkProgress,
+          cancel
+        )
+    );
+  }
+
+  public async isParallel(): Promise<boolean> {
+    return this.parallel;
+  }
+
+  // iterations are checkerboard sweeps, a shared cancel flag is only checked
+  // between chunks since a sweep runs on all threads at once
+  public async generateWithParallelMarkovChainWithProgress(
+    sweeps: number,
+    q: number,
+    progressEvery: number,
+    onProgress: Comlink.Remote<GenerationProgressCallback>,
+    cancel?: Int32Array
+  ): Promise<number> {
+    const lozengeTiling = this.lozengeTiling;
+    if (!lozengeTiling) {
+      throw new Error('LozengeTiling not initialized');
+    }
+    return this.generateInChunks(
+      sweeps,
+      progressEvery,
+      onProgress,
+      (steps, onChunkProgress) => {
+        if (cancel && Atomics.load(cancel, 0) !== 0) {
+          return 0;
+        }
+        lozengeTiling.generateWithParallelMarkovChain(steps, q);
+        onChunkProgress(steps, lozengeTiling.getPeriodBoxCount());
+        return steps;
+      }
+    );
+  }
+
   public async addRandomBox(): Promise<void> {
     if (!this.lozengeTiling) {
       throw new Error('LozengeTiling not initialized');
@@ -19,4 +19,7 @@ const lozengeTilingComlink = await new LozengeTilingComlink(
   initialDrawDistance
 );
 
-export { lozengeTilingComlink };
+// the parallel Markov chain is only offered by wasm built with threads
+const parallelAvailable = await lozengeTilingComlink.isParallel();
+
+export { lozengeTilingComlink, parallelAvailable };
@@ -24,6 +24,16 @@ export interface WebpackConfigurationGenerator {
     | Promise<webpack.Configuration>;
 }
 
+// LOZENGE_PARALLEL=1 builds wasm with threads (lib feature "parallel"),
+// requires nightly rust and cross-origin isolation for SharedArrayBuffer
+const isParallelWasm = process.env.LOZENGE_PARALLEL === '1';
+
+if (isParallelWasm) {
+  process.env.RUSTFLAGS =
+    '-C target-feature=+atomics,+bulk-memory,+mutable-globals';
+  process.env.RUSTUP_TOOLCHAIN = process.env.RUSTUP_TOOLCHAIN ?? 'nightly';
+}
+
 const generateConfig: WebpackConfigurationGenerator = (_env, argv) => {
   const mode =
     argv?.mode === WebpackMode.Production
@@ -113,7 +123,9 @@ const generateConfig: WebpackConfigurationGenerator = (_env, argv) => {
       new WasmPackPlugin({
         crateDirectory: path.join(__dirname, 'lib'),
         outDir: path.join(__dirname, 'build', 'lib'),
-        extraArgs: '--target web',
+        extraArgs: isParallelWasm
+          ? '--target web -- --features parallel -Z build-std=panic_abort,std'
+          : '--target web',
       }),
     ],
     devServer: {
@@ -121,6 +133,12 @@ const generateConfig: WebpackConfigurationGenerator = (_env, argv) => {
       client: {
         overlay: false,
       },
+      headers: isParallelWasm
+        ? {
+            'Cross-Origin-Opener-Policy': 'same-origin',
+            'Cross-Origin-Embedder-Policy': 'require-corp',
+          }
+        : {},
     },
     experiments: {
       topLevelAwait: true,